@@ -1,11 +1,38 @@
-use std::process::Command;
+use std::{env, path::PathBuf};
 
+/// Embeds a prebuilt `mumble_bridge.exe` if there is one. Otherwise the overlay looks for the
+/// bridge at runtime, so building on linux doesn't need a windows toolchain
 fn main() {
-    // Should trigger if it doesn't exist
-    println!("cargo:rerun-if-changed=helper/mumble.exe");
-    println!("cargo:rerun-if-changed=helper/mumble_helper.cpp");
-    Command::new("make")
-        .current_dir("helper")
-        .status()
-        .expect("Failed to compile mumble helper");
+    println!("cargo:rustc-check-cfg=cfg(embed_bridge)");
+    println!("cargo:rerun-if-env-changed=RUSTYGW2_BRIDGE");
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let candidates: Vec<PathBuf> = match env::var_os("RUSTYGW2_BRIDGE") {
+        Some(path) => vec![PathBuf::from(path)],
+        None => ["release", "debug"]
+            .iter()
+            .map(|profile| {
+                manifest_dir
+                    .join("../target/x86_64-pc-windows-gnu")
+                    .join(profile)
+                    .join("mumble_bridge.exe")
+            })
+            .collect(),
+    };
+    for candidate in &candidates {
+        println!("cargo:rerun-if-changed={}", candidate.display());
+    }
+
+    match candidates.iter().find(|path| path.is_file()) {
+        Some(path) => {
+            println!("cargo:rustc-cfg=embed_bridge");
+            println!(
+                "cargo:rustc-env=RUSTYGW2_BRIDGE_PATH={}",
+                path.canonicalize().unwrap().display()
+            );
+        }
+        None => println!(
+            "cargo:warning=mumble_bridge.exe not found and won't be embedded. \
+             Build it with `cargo build -p mumble_bridge --release --target x86_64-pc-windows-gnu`"
+        ),
+    }
 }
@@ -0,0 +1,234 @@
+//! Command line of the overlay. Without a subcommand the overlay runs.
+
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "RustyGw2-Overlay",
+    version,
+    about = "Marker overlay for Guild Wars 2"
+)]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Args, Debug, Default, Clone)]
+pub struct ConfigArgs {
+    /// Config file that overrides the ones in the XDG config dirs
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Overrides a single value of the config, e.g. `--set window.width=2560`
+    #[arg(long = "set", global = true, value_name = "SECTION.KEY=VALUE")]
+    pub overrides: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Shows the markers on top of the game
+    Run(RunArgs),
+    /// Parses every XML and TRL file of the marker packs and reports the broken ones
+    ValidatePacks {
+        /// Defaults to `markers.path` of the config
+        #[arg(value_name = "DIR")]
+        packs: Option<PathBuf>,
+    },
+    /// Lists the number of markers and trails of every map
+    ListMaps {
+        /// Defaults to `markers.path` of the config
+        #[arg(value_name = "DIR")]
+        packs: Option<PathBuf>,
+    },
+    /// Writes every marker pack as a directory below OUT, as the overlay read it
+    UnpackPacks {
+        #[arg(value_name = "OUT")]
+        out: PathBuf,
+        /// Defaults to `markers.path` of the config
+        #[arg(long, value_name = "DIR")]
+        packs: Option<PathBuf>,
+    },
+    /// Runs the overlay and records the link of every game
+    Record {
+        /// Every further game is recorded to `<FILE>.<index>`
+        file: PathBuf,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// Runs the overlay with a recorded link instead of a game
+    Replay {
+        file: PathBuf,
+        /// Starts over at the end of the recording
+        #[arg(long = "loop")]
+        looping: bool,
+        /// Directory with the marker packs
+        #[arg(long, value_name = "DIR")]
+        packs: Option<PathBuf>,
+    },
+    /// Checks the shared memory, the port, wine and the helper
+    Doctor,
+}
+
+/// Which games the overlay attaches to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GameSelection {
+    /// Every game that is running or started later
+    #[default]
+    Auto,
+    Pid(i32),
+}
+
+fn parse_game(value: &str) -> Result<GameSelection, String> {
+    match value {
+        "auto" => Ok(GameSelection::Auto),
+        _ => value
+            .parse()
+            .map(GameSelection::Pid)
+            .map_err(|_| format!("{} is neither a pid nor auto", value)),
+    }
+}
+
+/// Where the link of the games is read from
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkMode {
+    /// Packets of the helper that runs next to every game
+    #[default]
+    Udp,
+    /// An existing MumbleLink shared memory, e.g. of a game that runs without wine
+    Shm,
+}
+
+#[derive(Args, Debug, Default, Clone)]
+pub struct RunArgs {
+    /// Directory with the marker packs
+    #[arg(long, value_name = "DIR")]
+    pub packs: Option<PathBuf>,
+    /// Pid of the game to attach to, or `auto` for every game
+    #[arg(long, value_name = "PID|auto", default_value = "auto", value_parser = parse_game)]
+    pub game: GameSelection,
+    /// Port of the first game. Every further game uses the next one
+    #[arg(long)]
+    pub port: Option<u16>,
+    #[arg(long, value_enum, default_value_t)]
+    pub link: LinkMode,
+}
+
+/// `--packs` as a config override, so it survives a reload of the config
+fn packs_override(packs: &Option<PathBuf>) -> Option<String> {
+    let packs = packs.as_ref()?.to_string_lossy().to_string();
+    Some(format!("markers.path={}", toml::Value::String(packs)))
+}
+
+impl RunArgs {
+    pub fn get_overrides(&self) -> Vec<String> {
+        packs_override(&self.packs)
+            .into_iter()
+            .chain(self.port.map(|port| format!("link.port={}", port)))
+            .collect()
+    }
+}
+
+impl Cli {
+    /// The `--set` values followed by the ones of the subcommand
+    pub fn get_overrides(&self) -> Vec<String> {
+        let mut overrides = self.config.overrides.clone();
+        match &self.command {
+            Some(Command::Run(run) | Command::Record { run, .. }) => {
+                overrides.extend(run.get_overrides())
+            }
+            Some(Command::Replay { packs, .. }) => overrides.extend(packs_override(packs)),
+            _ => (),
+        }
+        overrides
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use clap::Parser;
+
+    use super::{Cli, Command, GameSelection, LinkMode};
+
+    #[test]
+    fn test_run() {
+        let cli = Cli::try_parse_from(["overlay"]).unwrap();
+        assert!(cli.command.is_none());
+
+        let cli = Cli::try_parse_from([
+            "overlay", "run", "--packs", "/packs", "--game", "1234", "--port", "7080",
+        ])
+        .unwrap();
+        let Some(Command::Run(run)) = &cli.command else {
+            panic!("expected run");
+        };
+        assert_eq!(run.game, GameSelection::Pid(1234));
+        assert_eq!(run.link, LinkMode::Udp);
+        assert_eq!(
+            cli.get_overrides(),
+            vec![r#"markers.path="/packs""#, "link.port=7080"]
+        );
+
+        let cli = Cli::try_parse_from(["overlay", "run"]).unwrap();
+        let Some(Command::Run(run)) = &cli.command else {
+            panic!("expected run");
+        };
+        assert_eq!(run.game, GameSelection::Auto);
+        assert!(Cli::try_parse_from(["overlay", "run", "--game", "first"]).is_err());
+
+        let cli = Cli::try_parse_from(["overlay", "run", "--link", "shm"]).unwrap();
+        let Some(Command::Run(run)) = &cli.command else {
+            panic!("expected run");
+        };
+        assert_eq!(run.link, LinkMode::Shm);
+        assert!(Cli::try_parse_from(["overlay", "run", "--link", "tcp"]).is_err());
+    }
+
+    #[test]
+    fn test_subcommands() {
+        // The config arguments are accepted before and after the subcommand
+        let cli = Cli::try_parse_from([
+            "overlay",
+            "--set",
+            "camera.fov=1.5",
+            "validate-packs",
+            "--config",
+            "a.toml",
+        ])
+        .unwrap();
+        assert_eq!(cli.config.config, Some(PathBuf::from("a.toml")));
+        assert_eq!(cli.get_overrides(), vec!["camera.fov=1.5"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::ValidatePacks { packs: None })
+        ));
+
+        let cli = Cli::try_parse_from(["overlay", "replay", "session.mlr", "--loop"]).unwrap();
+        let Some(Command::Replay { file, looping, .. }) = cli.command else {
+            panic!("expected replay");
+        };
+        assert_eq!(file, PathBuf::from("session.mlr"));
+        assert!(looping);
+
+        let cli = Cli::try_parse_from(["overlay", "record", "session.mlr", "--port", "7080"]);
+        assert!(matches!(
+            cli.unwrap().command,
+            Some(Command::Record { run, .. }) if run.port == Some(7080)
+        ));
+        let cli = Cli::try_parse_from(["overlay", "unpack-packs", "out", "--packs", "in"]);
+        assert!(matches!(
+            cli.unwrap().command,
+            Some(Command::UnpackPacks { out, packs: Some(packs) })
+                if out.as_os_str() == "out" && packs.as_os_str() == "in"
+        ));
+        assert!(matches!(
+            Cli::try_parse_from(["overlay", "doctor"]).unwrap().command,
+            Some(Command::Doctor)
+        ));
+        assert!(Cli::try_parse_from(["overlay", "inspect"]).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use bevy::{prelude::*, render::view::RenderLayers};
+
+use crate::processutils::{ProcessInfo, ProcessScanner};
+
+/// Name of the MumbleLink the game uses if it was started without `-mumble`
+pub const DEFAULT_MUMBLE_NAME: &str = "MumbleLink";
+/// Every client renders to its own layer
+pub const MAX_CLIENTS: usize = RenderLayers::TOTAL_LAYERS;
+
+/// A running game and where its link data arrives
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameClient {
+    pub pid: i32,
+    /// Tells the game apart from a later process with the same pid
+    pub start_time: u64,
+    /// Passed to the game with `-mumble <name>` to run multiple clients in one prefix
+    pub mumble_name: String,
+    pub port: u16,
+}
+
+/// The MumbleLink name from the game arguments
+pub fn get_mumble_name(cmdline: &[String]) -> String {
+    cmdline
+        .iter()
+        .position(|arg| arg.eq_ignore_ascii_case("-mumble"))
+        .and_then(|i| cmdline.get(i + 1))
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_MUMBLE_NAME.to_string())
+}
+
+impl GameClient {
+    /// The client with `index` uses the port `base_port + index`. `None` if that is above
+    /// the last port
+    pub fn new(process: &ProcessInfo, index: u8, base_port: u16) -> Option<Self> {
+        Some(Self {
+            pid: process.pid,
+            start_time: process.start_time,
+            mumble_name: get_mumble_name(&process.argv),
+            port: base_port.checked_add(index as u16)?,
+        })
+    }
+}
+
+/// Executable names of the game. Steam/Proton installs use a different spelling
+pub const DEFAULT_GAME_EXES: &[&str] = &["GW2-64.exe", "Gw2-64.exe"];
+/// Comma separated list of executable names that replaces the defaults
+pub const GAME_EXE_ENV: &str = "RUSTYGW2_GAME_EXE";
+
+/// The executable names of a `RUSTYGW2_GAME_EXE` value or the defaults
+pub fn parse_game_exes(value: Option<&str>) -> Vec<String> {
+    let exes: Vec<String> = value
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|exe| !exe.is_empty())
+        .map(str::to_string)
+        .collect();
+    if exes.is_empty() {
+        DEFAULT_GAME_EXES
+            .iter()
+            .map(|exe| exe.to_string())
+            .collect()
+    } else {
+        exes
+    }
+}
+
+/// Every running game with one of the `exe_names`, sorted by pid
+pub fn find_games(exe_names: &[String]) -> Vec<ProcessInfo> {
+    let scanner = ProcessScanner::new();
+    let mut games: Vec<ProcessInfo> = exe_names
+        .iter()
+        .flat_map(|exe| scanner.find_by_name(exe).collect::<Vec<_>>())
+        .collect();
+    games.sort_by_key(|game| game.pid);
+    // Names only differing in case match the same process
+    games.dedup_by_key(|game| game.pid);
+    games
+}
+
+/// The lowest index that no client uses yet
+pub fn get_free_index(used: &[u8]) -> Option<u8> {
+    (0..MAX_CLIENTS as u8).find(|index| !used.contains(index))
+}
+
+/// The entity with the link of one game
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Client {
+    pub index: u8,
+    /// `None` if the link doesn't come from a running game e.g. a replay
+    pub game_pid: Option<i32>,
+}
+
+impl Client {
+    /// The camera of a client only renders the markers of the same client
+    pub fn render_layers(&self) -> RenderLayers {
+        RenderLayers::layer(self.index)
+    }
+}
+
+/// Links cameras, windows and markers to the client entity they belong to
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkedClient(pub Entity);
+
+#[cfg(test)]
+mod tests {
+    use super::{get_free_index, get_mumble_name, parse_game_exes, GameClient};
+    use crate::processutils::ProcessInfo;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_mumble_name() {
+        assert_eq!(get_mumble_name(&args(&["GW2-64.exe"])), "MumbleLink");
+        assert_eq!(
+            get_mumble_name(&args(&["GW2-64.exe", "-Mumble", "Alt", "-windowed"])),
+            "Alt"
+        );
+        // Missing value
+        assert_eq!(
+            get_mumble_name(&args(&["GW2-64.exe", "-mumble"])),
+            "MumbleLink"
+        );
+    }
+
+    #[test]
+    fn test_game_clients() {
+        let process = ProcessInfo {
+            pid: 200,
+            exe: None,
+            argv: args(&["GW2-64.exe", "-mumble", "Second"]),
+            cwd: None,
+            start_time: 42,
+        };
+        assert_eq!(
+            GameClient::new(&process, 1, 7070),
+            Some(GameClient {
+                pid: 200,
+                start_time: 42,
+                mumble_name: "Second".to_string(),
+                port: 7071
+            })
+        );
+        assert_eq!(GameClient::new(&process, 1, u16::MAX), None);
+        assert_eq!(get_free_index(&[]), Some(0));
+        assert_eq!(get_free_index(&[0, 2]), Some(1));
+        assert_eq!(get_free_index(&(0..32).collect::<Vec<u8>>()), None);
+
+        assert_eq!(parse_game_exes(None), vec!["GW2-64.exe", "Gw2-64.exe"]);
+        assert_eq!(parse_game_exes(Some(" ")), vec!["GW2-64.exe", "Gw2-64.exe"]);
+        assert_eq!(
+            parse_game_exes(Some("Gw2-64.exe, launcher.exe")),
+            vec!["Gw2-64.exe", "launcher.exe"]
+        );
+    }
+}
@@ -0,0 +1,445 @@
+//! Settings of the overlay.
+//!
+//! The config is merged from these layers, later ones override earlier ones:
+//! 1. the defaults
+//! 2. `rustygw2/config.toml` in `$XDG_CONFIG_DIRS` and then `$XDG_CONFIG_HOME`
+//! 3. the file passed with `--config <file>`
+//! 4. `RUSTYGW2__<SECTION>__<KEY>=<value>` environment variables
+//! 5. `--set <section>.<key>=<value>` arguments
+//!
+//! ```toml
+//! [window]
+//! x = 0
+//! y = 0
+//! width = 2560
+//! height = 1440
+//!
+//! [camera]
+//! fov = 1.222
+//! ```
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::{mpsc::Receiver, Mutex},
+};
+
+use bevy::prelude::*;
+use custom_window_plugin::WindowGeometry;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+
+use crate::clients;
+
+/// Location of the config in every XDG config dir
+pub const CONFIG_FILE: &str = "rustygw2/config.toml";
+/// Prefix of the environment variables that override single values
+pub const ENV_PREFIX: &str = "RUSTYGW2__";
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub markers: MarkerConfig,
+    pub camera: CameraConfig,
+    pub window: WindowConfig,
+    pub game: GameConfig,
+    pub link: LinkConfig,
+    pub text: TextConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarkerConfig {
+    /// Directory that is searched for marker packs
+    pub path: PathBuf,
+    /// Names of the packs that aren't shown
+    pub disabled_packs: Vec<String>,
+}
+
+impl Default for MarkerConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("pois"),
+            disabled_packs: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    /// Vertical field of view in radians. Should match the one of the game
+    pub fov: f32,
+    pub far: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            fov: 1.222,
+            far: 1000.0,
+        }
+    }
+}
+
+/// Where the overlay windows are placed on the X screen
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Covers the window of the game instead. The geometry above is used until it is found
+    pub follow_game: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            x: 1680,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            follow_game: true,
+        }
+    }
+}
+
+impl WindowConfig {
+    pub fn get_geometry(&self) -> WindowGeometry {
+        WindowGeometry {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    /// Executable names of the game
+    pub exe_names: Vec<String>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            exe_names: clients::DEFAULT_GAME_EXES
+                .iter()
+                .map(|exe| exe.to_string())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkConfig {
+    /// Port of the first game. Every further game uses the next one. Only used by games
+    /// attached afterwards
+    pub port: u16,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            port: gw2_link::DEFAULT_PORT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TextConfig {
+    pub font: String,
+    pub mono_font: String,
+    pub fps_size: f32,
+    pub status_size: f32,
+}
+
+impl Default for TextConfig {
+    fn default() -> Self {
+        Self {
+            font: "fonts/FiraSans-Bold.ttf".to_string(),
+            mono_font: "fonts/FiraMono-Medium.ttf".to_string(),
+            fps_size: 60.0,
+            status_size: 40.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(String, toml::de::Error),
+    /// An override isn't `<section>.<key>=<value>`
+    BadOverride(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            ConfigError::Parse(source, e) => write!(f, "invalid config in {}: {}", source, e),
+            ConfigError::BadOverride(value) => {
+                write!(f, "override {} is not <section>.<key>=<value>", value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Everything the config is merged from
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSources {
+    /// Candidates in increasing priority. Missing files are skipped
+    pub files: Vec<PathBuf>,
+    /// Set with `--config`. Unlike the other files it has to exist
+    pub config_file: Option<PathBuf>,
+    /// `(name, value)` of the environment
+    pub env: Vec<(String, String)>,
+    /// `<section>.<key>=<value>` from the command line
+    pub overrides: Vec<String>,
+}
+
+/// The config files in the XDG config dirs, in increasing priority
+pub fn get_xdg_config_files() -> Vec<PathBuf> {
+    let system_dirs = std::env::var("XDG_CONFIG_DIRS").unwrap_or_else(|_| "/etc/xdg".to_string());
+    // The first system dir is the most important one
+    let mut files: Vec<PathBuf> = system_dirs
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .rev()
+        .map(|dir| Path::new(dir).join(CONFIG_FILE))
+        .collect();
+    files.extend(dirs::config_dir().map(|dir| dir.join(CONFIG_FILE)));
+    files
+}
+
+/// Parses a value like toml does. Everything that isn't valid toml is a string
+fn parse_value(value: &str) -> toml::Value {
+    format!("value = {}", value)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()))
+}
+
+/// Sets `value` at `path`, creating the tables in between
+fn set_value(table: &mut toml::Table, path: &[String], value: toml::Value) {
+    match path {
+        [] => (),
+        [key] => {
+            table.insert(key.clone(), value);
+        }
+        [key, rest @ ..] => {
+            let entry = table
+                .entry(key.clone())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if !entry.is_table() {
+                *entry = toml::Value::Table(toml::Table::new());
+            }
+            if let toml::Value::Table(inner) = entry {
+                set_value(inner, rest, value);
+            }
+        }
+    }
+}
+
+/// Merges `other` into `table`. Tables are merged, everything else is replaced
+fn merge(table: &mut toml::Table, other: toml::Table) {
+    for (key, value) in other {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(own)), toml::Value::Table(other)) => merge(own, other),
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+impl ConfigSources {
+    /// The XDG files, the environment of the process and the given command line values
+    pub fn new(config_file: Option<PathBuf>, overrides: Vec<String>) -> Self {
+        Self {
+            files: get_xdg_config_files(),
+            config_file,
+            env: std::env::vars().collect(),
+            overrides,
+        }
+    }
+
+    /// Every file that can change the config
+    pub fn get_watched_files(&self) -> Vec<PathBuf> {
+        self.files
+            .iter()
+            .chain(self.config_file.iter())
+            .cloned()
+            .collect()
+    }
+
+    pub fn load(&self) -> Result<Config, ConfigError> {
+        let mut table = toml::Table::new();
+        let existing = self.files.iter().filter(|file| file.exists());
+        for file in existing.chain(self.config_file.iter()) {
+            let data =
+                std::fs::read_to_string(file).map_err(|e| ConfigError::Io(file.clone(), e))?;
+            let layer: toml::Table = data
+                .parse()
+                .map_err(|e| ConfigError::Parse(file.display().to_string(), e))?;
+            merge(&mut table, layer);
+        }
+
+        for (name, value) in &self.env {
+            if name == clients::GAME_EXE_ENV {
+                let exes = clients::parse_game_exes(Some(value));
+                let exes = exes.into_iter().map(toml::Value::String).collect();
+                set_value(
+                    &mut table,
+                    &["game".to_string(), "exe_names".to_string()],
+                    toml::Value::Array(exes),
+                );
+            } else if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                let path: Vec<String> = key.split("__").map(str::to_lowercase).collect();
+                set_value(&mut table, &path, parse_value(value));
+            }
+        }
+        for value in &self.overrides {
+            let (key, value) = value
+                .split_once('=')
+                .ok_or_else(|| ConfigError::BadOverride(value.clone()))?;
+            let path: Vec<String> = key.trim().split('.').map(str::to_string).collect();
+            set_value(&mut table, &path, parse_value(value.trim()));
+        }
+
+        Config::deserialize(table).map_err(|e| ConfigError::Parse("overrides".to_string(), e))
+    }
+}
+
+/// Reloads the config when one of its files changes
+#[derive(Resource)]
+pub struct ConfigWatcher {
+    sources: ConfigSources,
+    events: Mutex<Receiver<notify::Result<notify::Event>>>,
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+}
+
+impl ConfigWatcher {
+    /// Watches the directories of the config files, so files that are created later are seen
+    pub fn new(sources: ConfigSources) -> notify::Result<Self> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        let mut dirs: Vec<PathBuf> = sources
+            .get_watched_files()
+            .iter()
+            .filter_map(|file| Some(file.parent()?.to_path_buf()))
+            .filter(|dir| dir.is_dir())
+            .collect();
+        dirs.dedup();
+        for dir in dirs {
+            watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        }
+        Ok(Self {
+            sources,
+            events: Mutex::new(receiver),
+            _watcher: watcher,
+        })
+    }
+
+    /// True if a config file changed since the last call
+    fn has_changed(&self) -> bool {
+        let files = self.sources.get_watched_files();
+        let events = self.events.lock().unwrap();
+        let mut changed = false;
+        for event in events.try_iter().flatten() {
+            changed |= event.paths.iter().any(|path| files.contains(path));
+        }
+        changed
+    }
+}
+
+/// Replaces the config after a change. Invalid configs are reported and ignored
+pub fn reload_config(watcher: Option<Res<ConfigWatcher>>, mut config: ResMut<Config>) {
+    let Some(watcher) = watcher else {
+        return;
+    };
+    if !watcher.has_changed() {
+        return;
+    }
+    match watcher.sources.load() {
+        Ok(new_config) if new_config != *config => {
+            info!("Reloaded the config");
+            *config = new_config;
+        }
+        Ok(_) => (),
+        Err(e) => error!("Keeping the old config: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{Config, ConfigSources};
+
+    #[test]
+    fn test_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("system.toml");
+        let user = dir.path().join("user.toml");
+        std::fs::write(&system, "[window]\nx = 10\ny = 20\n[camera]\nfov = 1.0\n").unwrap();
+        std::fs::write(&user, "[window]\nx = 30\n").unwrap();
+
+        let sources = ConfigSources {
+            files: vec![system, dir.path().join("missing.toml"), user],
+            config_file: None,
+            env: vec![
+                ("RUSTYGW2__WINDOW__WIDTH".to_string(), "2560".to_string()),
+                ("RUSTYGW2__MARKERS__PATH".to_string(), "/packs".to_string()),
+                ("RUSTYGW2_GAME_EXE".to_string(), "Gw2-64.exe".to_string()),
+                ("HOME".to_string(), "/home/user".to_string()),
+            ],
+            overrides: vec!["window.height=1440".to_string()],
+        };
+        let config = sources.load().unwrap();
+        assert_eq!(config.window.x, 30);
+        assert_eq!(config.window.y, 20);
+        assert_eq!(config.window.width, 2560);
+        assert_eq!(config.window.height, 1440);
+        assert_eq!(config.camera.fov, 1.0);
+        assert_eq!(config.camera.far, Config::default().camera.far);
+        assert_eq!(config.markers.path, PathBuf::from("/packs"));
+        assert_eq!(config.game.exe_names, vec!["Gw2-64.exe"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.toml");
+        std::fs::write(&file, "[window]\nwidht = 10\n").unwrap();
+        let sources = ConfigSources {
+            config_file: Some(file),
+            ..Default::default()
+        };
+        // Typos are reported instead of silently ignored
+        assert!(sources.load().is_err());
+
+        // The file passed explicitly has to exist
+        let sources = ConfigSources {
+            config_file: Some(dir.path().join("missing.toml")),
+            ..Default::default()
+        };
+        assert!(sources.load().is_err());
+
+        let sources = ConfigSources {
+            overrides: vec!["window.width".to_string()],
+            ..Default::default()
+        };
+        assert!(sources.load().is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! Problems found while loading a marker file.
+//!
+//! A broken element is skipped and recorded, the rest of the file is still loaded. Players
+//! get everything that could be loaded, pack authors ask for the strict result instead.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::warn;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    /// Starts at 1
+    pub line: u64,
+    /// Starts at 1
+    pub column: u64,
+    /// e.g. `OverlayData/POIs/POI[3]`
+    pub element: String,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}: {}",
+            self.file.display(),
+            self.line,
+            self.column,
+            self.element,
+            self.message
+        )
+    }
+}
+
+/// Collects the problems of one file
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    file: PathBuf,
+    entries: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new(file: &Path) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            entries: vec![],
+        }
+    }
+
+    /// `line` and `column` start at 0, like the ones of the XML reader
+    pub fn record(&mut self, line: u64, column: u64, element: &str, message: impl Display) {
+        self.entries.push(Diagnostic {
+            file: self.file.clone(),
+            line: line + 1,
+            column: column + 1,
+            element: element.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    pub fn finish<T>(self, data: T) -> Parsed<T> {
+        Parsed {
+            data,
+            diagnostics: self.entries,
+        }
+    }
+}
+
+/// Whatever could be loaded and the problems that were skipped
+#[derive(Debug)]
+pub struct Parsed<T> {
+    pub data: T,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<T> Parsed<T> {
+    /// Logs the problems and keeps everything that could be loaded
+    pub fn lenient(self) -> T {
+        for diagnostic in &self.diagnostics {
+            warn!("Skipped {}", diagnostic);
+        }
+        self.data
+    }
+
+    /// Fails on any problem, so pack authors see every broken element
+    pub fn strict(self) -> Result<T, Vec<Diagnostic>> {
+        if self.diagnostics.is_empty() {
+            Ok(self.data)
+        } else {
+            Err(self.diagnostics)
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Checks the parts the overlay depends on and explains what is missing.
+
+use std::{
+    fmt::Display,
+    net::{Ipv4Addr, UdpSocket},
+    path::Path,
+};
+
+use gw2_link::GW2LinkBuilder;
+use nix::unistd::{access, AccessFlags};
+
+use crate::{clients, config::Config, processutils, wine::WineEnvironment};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// Works for now, but may be the reason the overlay shows nothing
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub message: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, message: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self.status {
+            Status::Ok => " ok ",
+            Status::Warning => "warn",
+            Status::Error => "fail",
+        };
+        write!(f, "[{}] {:<6} {}", status, self.name, self.message)
+    }
+}
+
+/// The link mirrors the data of the game to the shared memory for other tools
+pub fn check_shm(builder: &GW2LinkBuilder) -> Check {
+    let shm_dir = Path::new("/dev/shm");
+    if access(shm_dir, AccessFlags::W_OK).is_err() {
+        return Check::new("shm", Status::Error, "/dev/shm is not writable");
+    }
+    let name = builder.get_shm_name();
+    match builder.build_shm_source() {
+        Ok(_) => Check::new("shm", Status::Ok, format!("{} is readable", name)),
+        Err(e) => Check::new(
+            "shm",
+            Status::Warning,
+            format!("{}: {}. It is created once the overlay runs", name, e),
+        ),
+    }
+}
+
+/// The helper sends its packets to `port`
+pub fn check_port(port: u16) -> Check {
+    match UdpSocket::bind((Ipv4Addr::LOCALHOST, port)) {
+        Ok(_) => Check::new("port", Status::Ok, format!("{} is free", port)),
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => Check::new(
+            "port",
+            Status::Warning,
+            format!("{} is in use. Is another overlay running?", port),
+        ),
+        Err(e) => Check::new("port", Status::Error, format!("{}: {}", port, e)),
+    }
+}
+
+/// Whether the helper can be started next to every running game
+pub fn check_wine(exe_names: &[String]) -> Vec<Check> {
+    let games = clients::find_games(exe_names);
+    if games.is_empty() {
+        return vec![Check::new(
+            "wine",
+            Status::Warning,
+            format!("No running game found. Looked for {}", exe_names.join(", ")),
+        )];
+    }
+    games
+        .iter()
+        .map(|game| match WineEnvironment::from_pid(game.pid) {
+            Err(e) => Check::new(
+                "wine",
+                Status::Error,
+                format!("Can't read the environment of game {}: {}", game.pid, e),
+            ),
+            // Paths of flatpak games are only valid inside of the sandbox
+            Ok(wine)
+                if wine.flatpak_id.is_none() && wine.wine.is_absolute() && !wine.wine.exists() =>
+            {
+                Check::new(
+                    "wine",
+                    Status::Error,
+                    format!("{} of game {} doesn't exist", wine.wine.display(), game.pid),
+                )
+            }
+            Ok(wine) if matches!(&wine.server_socket, Some(socket) if !socket.exists()) => {
+                Check::new(
+                    "wine",
+                    Status::Warning,
+                    format!(
+                        "Game {} uses {:?} with prefix {}, but its wineserver isn't visible",
+                        game.pid,
+                        wine.flavor,
+                        wine.prefix.display()
+                    ),
+                )
+            }
+            Ok(wine) => Check::new(
+                "wine",
+                Status::Ok,
+                format!(
+                    "Game {} uses {:?} {} with prefix {}",
+                    game.pid,
+                    wine.flavor,
+                    wine.wine.display(),
+                    wine.prefix.display()
+                ),
+            ),
+        })
+        .collect()
+}
+
+pub fn check_helper() -> Check {
+    match processutils::load_bridge() {
+        Ok(bridge) => Check::new(
+            "helper",
+            Status::Ok,
+            format!("mumble_bridge.exe found ({} bytes)", bridge.len()),
+        ),
+        Err(e) => Check::new(
+            "helper",
+            Status::Error,
+            format!(
+                "mumble_bridge.exe not found: {}. Set {} to its path",
+                e,
+                processutils::BRIDGE_ENV
+            ),
+        ),
+    }
+}
+
+/// Runs every check with the settings of `config`
+pub fn run_checks(config: &Config) -> Vec<Check> {
+    let builder = GW2LinkBuilder::new().port(config.link.port);
+    let mut checks = vec![check_shm(&builder), check_port(config.link.port)];
+    checks.extend(check_wine(&config.game.exe_names));
+    checks.push(check_helper());
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, UdpSocket};
+
+    use super::{check_port, check_wine, Status};
+
+    #[test]
+    fn test_check_port() {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let check = check_port(port);
+        assert_eq!(check.status, Status::Warning);
+        assert!(check.to_string().starts_with("[warn] port"));
+
+        drop(socket);
+        assert_eq!(check_port(port).status, Status::Ok);
+    }
+
+    #[test]
+    fn test_check_wine_without_game() {
+        let checks = check_wine(&["NoSuchGame-64.exe".to_string()]);
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].status, Status::Warning);
+    }
+}
@@ -0,0 +1,298 @@
+//! Finds the X window of a game, so the overlay covers exactly the game.
+//!
+//! Wine sets `_NET_WM_PID` of its windows to the pid of the windows process, which is the
+//! pid the game was found with.
+
+use bevy::prelude::{Component, Resource};
+use custom_window_plugin::WindowGeometry;
+use xcb::{x, Xid};
+
+/// The X window of the game of a client
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameWindow {
+    pub window: x::Window,
+    pub geometry: WindowGeometry,
+}
+
+/// Own connection to the X server to query the windows of the games
+#[derive(Resource)]
+pub struct X11Windows {
+    conn: xcb::Connection,
+    root: x::Window,
+    net_wm_pid: x::Atom,
+    net_client_list: x::Atom,
+}
+
+fn intern_atom(conn: &xcb::Connection, name: &[u8]) -> xcb::Result<x::Atom> {
+    let cookie = conn.send_request(&x::InternAtom {
+        only_if_exists: false,
+        name,
+    });
+    Ok(conn.wait_for_reply(cookie)?.atom())
+}
+
+impl X11Windows {
+    /// Connects to `display` or `$DISPLAY`
+    pub fn connect(display: Option<&str>) -> xcb::Result<Self> {
+        let (conn, screen_num) = xcb::Connection::connect(display)?;
+        let root = conn
+            .get_setup()
+            .roots()
+            .nth(screen_num as usize)
+            .map(|screen| screen.root())
+            .ok_or(xcb::ConnError::ClosedInvalidScreen)?;
+        let net_wm_pid = intern_atom(&conn, b"_NET_WM_PID")?;
+        let net_client_list = intern_atom(&conn, b"_NET_CLIENT_LIST")?;
+        Ok(Self {
+            conn,
+            root,
+            net_wm_pid,
+            net_client_list,
+        })
+    }
+
+    fn get_property<P: xcb::x::PropEl + Copy>(
+        &self,
+        window: x::Window,
+        property: x::Atom,
+        r#type: x::Atom,
+    ) -> xcb::Result<Vec<P>> {
+        let cookie = self.conn.send_request(&x::GetProperty {
+            delete: false,
+            window,
+            property,
+            r#type,
+            long_offset: 0,
+            long_length: u32::MAX / 4,
+        });
+        Ok(self.conn.wait_for_reply(cookie)?.value().to_vec())
+    }
+
+    /// The windows managed by the window manager or, without one, every window
+    fn get_candidates(&self) -> xcb::Result<Vec<x::Window>> {
+        let managed = self.get_property(self.root, self.net_client_list, x::ATOM_WINDOW)?;
+        if !managed.is_empty() {
+            return Ok(managed);
+        }
+        let mut windows = vec![];
+        let mut parents = vec![self.root];
+        while let Some(parent) = parents.pop() {
+            let cookie = self.conn.send_request(&x::QueryTree { window: parent });
+            let children = self.conn.wait_for_reply(cookie)?.children().to_vec();
+            windows.extend(&children);
+            parents.extend(children);
+        }
+        Ok(windows)
+    }
+
+    fn is_viewable(&self, window: x::Window) -> xcb::Result<bool> {
+        let cookie = self.conn.send_request(&x::GetWindowAttributes { window });
+        Ok(self.conn.wait_for_reply(cookie)?.map_state() == x::MapState::Viewable)
+    }
+
+    /// The biggest visible window of `pid`. Launchers and splash screens are smaller than
+    /// the game
+    pub fn find_by_pid(&self, pid: i32) -> xcb::Result<Option<x::Window>> {
+        let mut best: Option<(u64, x::Window)> = None;
+        for window in self.get_candidates()? {
+            // Windows can be destroyed while we look at them
+            let Ok(pids) = self.get_property::<u32>(window, self.net_wm_pid, x::ATOM_CARDINAL)
+            else {
+                continue;
+            };
+            if pids.first() != Some(&(pid as u32)) || !self.is_viewable(window).unwrap_or(false) {
+                continue;
+            }
+            let Ok(geometry) = self.get_geometry(window) else {
+                continue;
+            };
+            let area = geometry.width as u64 * geometry.height as u64;
+            if best.is_none_or(|(best_area, _)| area > best_area) {
+                best = Some((area, window));
+            }
+        }
+        Ok(best.map(|(_, window)| window))
+    }
+
+    /// Position on the screen and size of the inside of `window`
+    pub fn get_geometry(&self, window: x::Window) -> xcb::Result<WindowGeometry> {
+        let cookie = self.conn.send_request(&x::GetGeometry {
+            drawable: x::Drawable::Window(window),
+        });
+        let geometry = self.conn.wait_for_reply(cookie)?;
+        // The position is relative to the parent, which is the frame of the window manager
+        let cookie = self.conn.send_request(&x::TranslateCoordinates {
+            src_window: window,
+            dst_window: self.root,
+            src_x: 0,
+            src_y: 0,
+        });
+        let position = self.conn.wait_for_reply(cookie)?;
+        Ok(WindowGeometry {
+            x: position.dst_x() as i32,
+            y: position.dst_y() as i32,
+            width: geometry.width() as u32,
+            height: geometry.height() as u32,
+        })
+    }
+
+    /// Reports moves, resizes and the destruction of `window` to [`Self::poll_changes`]
+    pub fn watch(&self, window: x::Window) -> xcb::Result<()> {
+        let cookie = self.conn.send_request_checked(&x::ChangeWindowAttributes {
+            window,
+            value_list: &[x::Cw::EventMask(x::EventMask::STRUCTURE_NOTIFY)],
+        });
+        self.conn.check_request(cookie)?;
+        Ok(())
+    }
+
+    /// The watched windows that changed since the last call
+    pub fn poll_changes(&self) -> xcb::Result<Vec<x::Window>> {
+        let mut changed = vec![];
+        while let Some(event) = self.conn.poll_for_event()? {
+            let window = match event {
+                xcb::Event::X(x::Event::ConfigureNotify(event)) => event.window(),
+                xcb::Event::X(x::Event::UnmapNotify(event)) => event.window(),
+                xcb::Event::X(x::Event::DestroyNotify(event)) => event.window(),
+                _ => continue,
+            };
+            if !changed.contains(&window) {
+                changed.push(window);
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Whether `window` still exists and is shown
+    pub fn is_alive(&self, window: x::Window) -> bool {
+        !window.is_none() && self.is_viewable(window).unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        process::{Child, Command},
+        thread,
+        time::Duration,
+    };
+
+    use custom_window_plugin::WindowGeometry;
+    use xcb::x;
+
+    use super::{intern_atom, X11Windows};
+
+    const DISPLAY: &str = ":87";
+
+    struct Xvfb(Child);
+
+    impl Drop for Xvfb {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    fn start_xvfb() -> Xvfb {
+        let child = Command::new("Xvfb")
+            .args([DISPLAY, "-screen", "0", "2560x1440x24"])
+            .spawn()
+            .expect("Xvfb is not installed");
+        let xvfb = Xvfb(child);
+        for _ in 0..50 {
+            if xcb::Connection::connect(Some(DISPLAY)).is_ok() {
+                return xvfb;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        panic!("Xvfb didn't start");
+    }
+
+    /// Creates a mapped window like wine does for the game
+    fn create_game_window(conn: &xcb::Connection, pid: u32, width: u16, height: u16) -> x::Window {
+        let screen = conn.get_setup().roots().next().unwrap();
+        let window = conn.generate_id();
+        conn.send_request(&x::CreateWindow {
+            depth: x::COPY_FROM_PARENT as u8,
+            wid: window,
+            parent: screen.root(),
+            x: 100,
+            y: 50,
+            width,
+            height,
+            border_width: 0,
+            class: x::WindowClass::InputOutput,
+            visual: screen.root_visual(),
+            value_list: &[],
+        });
+        conn.send_request(&x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window,
+            property: intern_atom(conn, b"_NET_WM_PID").unwrap(),
+            r#type: x::ATOM_CARDINAL,
+            data: &[pid],
+        });
+        conn.send_request(&x::MapWindow { window });
+        conn.flush().unwrap();
+        window
+    }
+
+    #[test]
+    #[ignore = "needs Xvfb"]
+    fn test_follow_game_window() {
+        let _xvfb = start_xvfb();
+        let (game, _) = xcb::Connection::connect(Some(DISPLAY)).unwrap();
+        let splash = create_game_window(&game, 4242, 300, 200);
+        let window = create_game_window(&game, 4242, 1280, 720);
+        create_game_window(&game, 1000, 1920, 1080);
+
+        let windows = X11Windows::connect(Some(DISPLAY)).unwrap();
+        assert_eq!(windows.find_by_pid(4242).unwrap(), Some(window));
+        assert_ne!(Some(splash), windows.find_by_pid(4242).unwrap());
+        assert_eq!(windows.find_by_pid(5000).unwrap(), None);
+        assert_eq!(
+            windows.get_geometry(window).unwrap(),
+            WindowGeometry {
+                x: 100,
+                y: 50,
+                width: 1280,
+                height: 720
+            }
+        );
+
+        windows.watch(window).unwrap();
+        game.send_request(&x::ConfigureWindow {
+            window,
+            value_list: &[
+                x::ConfigWindow::X(0),
+                x::ConfigWindow::Y(0),
+                x::ConfigWindow::Width(2560),
+                x::ConfigWindow::Height(1440),
+            ],
+        });
+        game.flush().unwrap();
+        let mut changed = vec![];
+        for _ in 0..50 {
+            changed = windows.poll_changes().unwrap();
+            if !changed.is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(changed, vec![window]);
+        assert_eq!(
+            windows.get_geometry(window).unwrap(),
+            WindowGeometry {
+                x: 0,
+                y: 0,
+                width: 2560,
+                height: 1440
+            }
+        );
+
+        game.send_request(&x::DestroyWindow { window });
+        game.flush().unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(!windows.is_alive(window));
+    }
+}
@@ -6,7 +6,8 @@ use std::{
     sync::{Arc, RwLock},
 };
 
-use serde::{Deserialize, Deserializer};
+use gw2_link::{Mount, Profession, Race};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 pub type MarkerCategoryContainer = Arc<RwLock<MarkerCategory>>;
 pub type PoiContainer = Arc<RwLock<POI>>;
@@ -24,64 +25,12 @@ where
     Ok(p)
 }
 
-pub fn deserialize_marker_category_hashmap<'de, D>(
-    deserializer: D,
-) -> Result<HashMap<String, MarkerCategoryContainer>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let cat = Vec::<MarkerCategory>::deserialize(deserializer)?;
-    let map: HashMap<String, MarkerCategory> =
-        cat.iter().map(|c| (c.name.clone(), c.clone())).collect();
-
-    let arc_map: HashMap<String, MarkerCategoryContainer> = map
-        .iter()
-        .map(|(key, val)| (key.clone(), Arc::new(RwLock::new(val.clone()))))
-        .collect();
-
-    Ok(arc_map)
-}
-
-pub fn deserialize_marker_category_vec<'de, D>(
-    deserializer: D,
-) -> Result<Vec<MarkerCategoryContainer>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let cat = Vec::<MarkerCategory>::deserialize(deserializer)?;
-    let new_vec = cat
-        .iter()
-        .map(|c| Arc::new(RwLock::new(c.clone())))
-        .collect();
-
-    Ok(new_vec)
-}
-
-pub fn deserialize_poi_vec<'de, D>(deserializer: D) -> Result<Vec<PoiContainer>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let poi = Vec::<POI>::deserialize(deserializer);
-    if poi.is_err() {
-        return Ok(vec![]);
-    }
-    let poi = poi.unwrap();
-    let new_vec: Vec<PoiContainer> = poi
-        .iter()
-        .map(|p| Arc::new(RwLock::new(p.clone())))
-        .collect();
-
-    Ok(new_vec)
-}
-
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct MarkerCategory {
+    #[serde(default)]
     pub name: String,
-    #[serde(
-        default,
-        rename = "MarkerCategory",
-        deserialize_with = "deserialize_marker_category_hashmap"
-    )]
+    /// Read from the nested `MarkerCategory` elements
+    #[serde(skip)]
     pub children: HashMap<String, MarkerCategoryContainer>,
     //children: Option<Vec<MarkerCategory>>,
     #[serde(flatten)]
@@ -94,6 +43,30 @@ impl MarkerCategory {
             ..Default::default()
         }
     }
+    /// Adds the children of `other`, another definition of the same category. The
+    /// attributes of the first definition are kept
+    pub fn merge(&mut self, other: &MarkerCategory) {
+        for (name, child) in &other.children {
+            match self.children.get(name) {
+                Some(existing) => existing.write().unwrap().merge(&child.read().unwrap()),
+                None => {
+                    self.children.insert(name.clone(), child.clone());
+                }
+            }
+        }
+    }
+
+    /// Adds a nested category. A second definition of it is merged into the first
+    pub fn add_child(&mut self, child: MarkerCategory) {
+        match self.children.get(&child.name) {
+            Some(existing) => existing.write().unwrap().merge(&child),
+            None => {
+                self.children
+                    .insert(child.name.clone(), Arc::new(RwLock::new(child)));
+            }
+        }
+    }
+
     /// XXX: only searches children!
     pub fn get_category_children(&self, name: &str) -> Option<MarkerCategoryContainer> {
         let next_name = name.split_once('.');
@@ -147,7 +120,7 @@ impl PoiTrait for MarkerCategory {
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 pub enum PoiBehavior {
     Default = 0,
     ReappearOnMapChange = 1,
@@ -166,16 +139,114 @@ impl Default for PoiBehavior {
     }
 }
 
+/// Written as its number, like packs do
+impl Serialize for PoiBehavior {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(*self as u32)
+    }
+}
+
+impl FromStr for PoiBehavior {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "0" => Ok(Self::Default),
+            "1" => Ok(Self::ReappearOnMapChange),
+            "2" => Ok(Self::ReappearOnDailyReset),
+            "3" => Ok(Self::OnlyVisibleBeforeActivation),
+            "4" => Ok(Self::ReappearAfterTimer),
+            "5" => Ok(Self::ReappearOnMapReset),
+            "6" => Ok(Self::OncePerInstance),
+            "7" => Ok(Self::OnceDailyPerCharacter),
+            "23732" => Ok(Self::ActionOnCombat),
+            _ => Err(format!("{} is not a behavior", s)),
+        }
+    }
+}
+
+/// Which side of a trail is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Cull {
+    None,
+    Clockwise,
+    CounterClockwise,
+}
+
+impl FromStr for Cull {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "clockwise" => Ok(Self::Clockwise),
+            "counterclockwise" => Ok(Self::CounterClockwise),
+            _ => Err(format!("{} is not a cull mode", s)),
+        }
+    }
+}
+
 fn deserialize_option_string_to_number<'de, D, N>(deserializer: D) -> Result<Option<N>, D::Error>
 where
     D: Deserializer<'de>,
     N: FromStr,
+    <N as FromStr>::Err: Display,
 {
     let buf = String::deserialize(deserializer)?;
-    let val = buf.parse::<N>();
+    let val = buf.trim().parse::<N>();
     match val {
         Ok(v) => Ok(Some(v)),
-        Err(_) => Ok(None),
+        Err(e) => Err(serde::de::Error::custom(format!("{:?}: {}", buf, e))),
+    }
+}
+
+/// Packs write booleans as `0`/`1` as well as `false`/`true`
+fn deserialize_option_bool<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let buf = String::deserialize(deserializer)?;
+    match buf.trim().to_lowercase().as_str() {
+        "1" | "true" => Ok(Some(true)),
+        "0" | "false" => Ok(Some(false)),
+        _ => Err(serde::de::Error::custom(format!(
+            "{:?} is not a boolean",
+            buf
+        ))),
+    }
+}
+
+/// Comma separated list, e.g. `profession="guardian,warrior"`
+fn deserialize_option_list<'de, D, N>(deserializer: D) -> Result<Option<Vec<N>>, D::Error>
+where
+    D: Deserializer<'de>,
+    N: FromStr,
+    <N as FromStr>::Err: Display,
+{
+    let buf = String::deserialize(deserializer)?;
+    let val = buf
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::parse::<N>)
+        .collect::<Result<Vec<N>, _>>();
+    match val {
+        Ok(v) => Ok(Some(v)),
+        Err(e) => Err(serde::de::Error::custom(format!("{:?}: {}", buf, e))),
+    }
+}
+
+fn serialize_option_list<S, N>(value: &Option<Vec<N>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    N: Display,
+{
+    match value {
+        Some(list) => {
+            let items: Vec<String> = list.iter().map(ToString::to_string).collect();
+            serializer.serialize_some(&items.join(","))
+        }
+        None => serializer.serialize_none(),
     }
 }
 
@@ -193,7 +264,7 @@ where
     }
 }
 
-#[derive(Debug, Default, Clone, Deserialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct Position {
     #[serde(default, deserialize_with = "deserialize_string_to_number")]
     pub xpos: f32,
@@ -204,7 +275,7 @@ pub struct Position {
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Default, Clone, Deserialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 struct InheritablePOIData {
     #[serde(
         default,
@@ -214,6 +285,7 @@ struct InheritablePOIData {
     pub map_id: Option<u32>,
     #[serde(rename = "iconFile")]
     pub icon_file: Option<PathBuf>,
+    #[serde(rename = "GUID")]
     pub guid: Option<String>,
     #[serde(
         default,
@@ -227,9 +299,18 @@ struct InheritablePOIData {
         deserialize_with = "deserialize_option_string_to_number"
     )]
     pub alpha: Option<f32>, // = 1.0f;
-    // TODO: don't skip
-    #[serde(skip)]
+    #[serde(
+        default,
+        rename = "behavior",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
     pub behavior: Option<PoiBehavior>, // = poiBehavior::DEFAULT;
+    #[serde(
+        default,
+        rename = "invertBehavior",
+        deserialize_with = "deserialize_option_bool"
+    )]
+    pub invert_behavior: Option<bool>,
     #[serde(
         default,
         rename = "fadeNear",
@@ -248,23 +329,190 @@ struct InheritablePOIData {
         deserialize_with = "deserialize_option_string_to_number"
     )]
     pub height_offset: Option<f32>,
+    #[serde(
+        default,
+        rename = "resetLength",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
     pub reset_length: Option<f32>,
+    #[serde(
+        default,
+        rename = "resetOffset",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
+    pub reset_offset: Option<f32>,
+    #[serde(rename = "DisplayName")]
     pub display_name: Option<String>,
     pub color: Option<String>,
-    pub auto_trigger: Option<bool>,  // = false;
+    #[serde(
+        default,
+        rename = "autoTrigger",
+        deserialize_with = "deserialize_option_bool"
+    )]
+    pub auto_trigger: Option<bool>, // = false;
+    #[serde(
+        default,
+        rename = "hasCountdown",
+        deserialize_with = "deserialize_option_bool"
+    )]
     pub has_countdown: Option<bool>, // = false;
-    pub trigger_range: Option<f32>,  // = 5;
-    // TODO: max/min size
+    #[serde(
+        default,
+        rename = "triggerRange",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
+    pub trigger_range: Option<f32>, // = 5;
+    #[serde(
+        default,
+        rename = "minSize",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
+    pub min_size: Option<f32>, // = 5;
+    #[serde(
+        default,
+        rename = "maxSize",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
+    pub max_size: Option<f32>, // = 2048;
+    #[serde(
+        default,
+        rename = "achievementId",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
     pub achievement_id: Option<i32>,
+    #[serde(
+        default,
+        rename = "achievementBit",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
     pub achievement_bit: Option<i32>, // = -1;
     pub info: Option<String>,
+    #[serde(
+        default,
+        rename = "infoRange",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
     pub info_range: Option<f32>,
+    #[serde(
+        default,
+        rename = "isPOI",
+        deserialize_with = "deserialize_option_bool"
+    )]
     pub is_poi: Option<bool>, // = false;
+    #[serde(
+        default,
+        rename = "mapDisplaySize",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
+    pub map_display_size: Option<f32>, // = 20;
+    #[serde(
+        default,
+        rename = "mapVisibility",
+        deserialize_with = "deserialize_option_bool"
+    )]
+    pub map_visibility: Option<bool>, // = true;
+    #[serde(
+        default,
+        rename = "miniMapVisibility",
+        deserialize_with = "deserialize_option_bool"
+    )]
+    pub mini_map_visibility: Option<bool>, // = true;
+    #[serde(
+        default,
+        rename = "inGameVisibility",
+        deserialize_with = "deserialize_option_bool"
+    )]
+    pub in_game_visibility: Option<bool>, // = true;
+    #[serde(
+        default,
+        rename = "scaleOnMapWithZoom",
+        deserialize_with = "deserialize_option_bool"
+    )]
+    pub scale_on_map_with_zoom: Option<bool>, // = true;
+    #[serde(
+        default,
+        rename = "mapFadeoutScaleLevel",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
+    pub map_fade_out_scale_level: Option<f32>,
+    #[serde(
+        default,
+        rename = "keepOnMapEdge",
+        deserialize_with = "deserialize_option_bool"
+    )]
+    pub keep_on_map_edge: Option<bool>, // = false;
+    #[serde(
+        default,
+        rename = "festival",
+        deserialize_with = "deserialize_option_list",
+        serialize_with = "serialize_option_list"
+    )]
+    pub festival: Option<Vec<String>>,
+    #[serde(
+        default,
+        rename = "profession",
+        deserialize_with = "deserialize_option_list",
+        serialize_with = "serialize_option_list"
+    )]
+    pub profession: Option<Vec<Profession>>,
+    #[serde(
+        default,
+        rename = "specialization",
+        deserialize_with = "deserialize_option_list",
+        serialize_with = "serialize_option_list"
+    )]
+    pub specialization: Option<Vec<u32>>,
+    #[serde(
+        default,
+        rename = "race",
+        deserialize_with = "deserialize_option_list",
+        serialize_with = "serialize_option_list"
+    )]
+    pub race: Option<Vec<Race>>,
+    #[serde(
+        default,
+        rename = "mount",
+        deserialize_with = "deserialize_option_list",
+        serialize_with = "serialize_option_list"
+    )]
+    pub mount: Option<Vec<Mount>>,
+    /// Copied to the clipboard when the marker is triggered
+    pub copy: Option<String>,
+    #[serde(rename = "copy-message")]
+    pub copy_message: Option<String>,
+    pub bounce: Option<String>,
+    #[serde(
+        default,
+        rename = "bounce-height",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
+    pub bounce_height: Option<f32>,
+    #[serde(
+        default,
+        rename = "bounce-duration",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
+    pub bounce_duration: Option<f32>,
+    #[serde(
+        default,
+        rename = "bounce-delay",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
+    pub bounce_delay: Option<f32>,
+    /// Category that is shown or hidden when the marker is triggered
+    #[serde(rename = "toggleCategory")]
+    pub toggle_category: Option<String>,
+    #[serde(
+        default,
+        rename = "cull",
+        deserialize_with = "deserialize_option_string_to_number"
+    )]
+    pub cull: Option<Cull>,
 }
 
 // TODO: are POI and MarkerCategory effectively the same?
 #[allow(dead_code)]
-#[derive(Debug, Default, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct POI {
     #[serde(rename = "type")]
     pub poi_type: Option<String>,
@@ -276,6 +524,9 @@ pub struct POI {
     parent: Option<MarkerCategoryContainer>,
     #[serde(skip)]
     enabled: bool, // = true;
+    /// Id of the pack in the [`crate::pack_files::PackRegistry`] the POI was loaded from
+    #[serde(skip)]
+    pack: Option<u32>,
 }
 
 impl PoiTrait for POI {
@@ -295,9 +546,9 @@ macro_rules! getter_setter_poi {
     ($field: expr, $type: ty) => {
         paste::paste! {
             pub fn [<get_ $field>](&self) -> Option<$type>{
-                match self.data.clone().$field {
+                match self.data.$field.clone() {
                     Some(data) => Some(data),
-                    None => match self.parent.clone() {
+                    None => match &self.parent {
                         Some(parent) => parent.read().unwrap().data.[<get_ $field>](),
                         None => None,
                     },
@@ -334,4 +585,56 @@ impl POI {
     getter_setter_poi!(fade_far, f32);
     getter_setter_poi!(alpha, f32);
     getter_setter_poi!(icon_size, f32);
+    getter_setter_poi!(guid, String);
+    getter_setter_poi!(behavior, PoiBehavior);
+    getter_setter_poi!(invert_behavior, bool);
+    getter_setter_poi!(reset_length, f32);
+    getter_setter_poi!(reset_offset, f32);
+    getter_setter_poi!(color, String);
+    getter_setter_poi!(auto_trigger, bool);
+    getter_setter_poi!(has_countdown, bool);
+    getter_setter_poi!(trigger_range, f32);
+    getter_setter_poi!(min_size, f32);
+    getter_setter_poi!(max_size, f32);
+    getter_setter_poi!(achievement_id, i32);
+    getter_setter_poi!(achievement_bit, i32);
+    getter_setter_poi!(info, String);
+    getter_setter_poi!(info_range, f32);
+    getter_setter_poi!(is_poi, bool);
+    getter_setter_poi!(map_display_size, f32);
+    getter_setter_poi!(map_visibility, bool);
+    getter_setter_poi!(mini_map_visibility, bool);
+    getter_setter_poi!(in_game_visibility, bool);
+    getter_setter_poi!(scale_on_map_with_zoom, bool);
+    getter_setter_poi!(map_fade_out_scale_level, f32);
+    getter_setter_poi!(keep_on_map_edge, bool);
+    getter_setter_poi!(festival, Vec<String>);
+    getter_setter_poi!(profession, Vec<Profession>);
+    getter_setter_poi!(specialization, Vec<u32>);
+    getter_setter_poi!(race, Vec<Race>);
+    getter_setter_poi!(mount, Vec<Mount>);
+    getter_setter_poi!(copy, String);
+    getter_setter_poi!(copy_message, String);
+    getter_setter_poi!(bounce, String);
+    getter_setter_poi!(bounce_height, f32);
+    getter_setter_poi!(bounce_duration, f32);
+    getter_setter_poi!(bounce_delay, f32);
+    getter_setter_poi!(toggle_category, String);
+    getter_setter_poi!(cull, Cull);
+
+    pub fn get_pack(&self) -> Option<u32> {
+        self.pack
+    }
+
+    pub fn set_pack(&mut self, pack: Option<u32>) {
+        self.pack = pack;
+    }
+
+    /// The icon and the pack it's in. An inherited icon is in the pack of its category
+    pub fn get_icon_source(&self) -> Option<(Option<u32>, PathBuf)> {
+        match self.data.icon_file.clone() {
+            Some(icon_file) => Some((self.pack, icon_file)),
+            None => self.parent.as_ref()?.read().unwrap().data.get_icon_source(),
+        }
+    }
 }
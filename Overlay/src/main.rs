@@ -1,15 +1,20 @@
 //! This example shows various ways to configure texture materials in 3D.
 
-use overlay_data::OverlayData;
-use std::{f32::consts::PI, fs, path::Path, time::Instant};
+use std::{
+    collections::HashSet,
+    f32::consts::PI,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 use trail::TrailContainer;
-use walkdir::WalkDir;
 
 use bevy::{
     core_pipeline::tonemapping::{DebandDither, Tonemapping},
     diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
     prelude::{shape::Quad, *},
     render::{
+        camera::RenderTarget,
         camera::{
             camera_system, CameraProjection, CameraProjectionPlugin, CameraRenderGraph, Viewport,
         },
@@ -19,29 +24,75 @@ use bevy::{
         view::{update_frusta, ColorGrading, VisibilitySystems, VisibleEntities},
     },
     transform::TransformSystem,
-    window::PresentMode,
+    window::{PresentMode, PrimaryWindow, WindowPosition, WindowRef, WindowResolution},
 };
 use bevy_mod_billboard::prelude::*;
 
+mod cli;
+mod clients;
+mod config;
 #[cfg(feature = "custom_projection")]
 mod custom_camera;
+mod diagnostics;
+mod doctor;
+mod game_window;
 mod gw2poi;
 mod overlay_data;
+mod pack_files;
+mod packs;
 mod processutils;
+mod supervisor;
 mod trail;
 mod utils;
+mod wine;
+mod xml_element;
 
 #[cfg(feature = "custom_projection")]
 use custom_camera::PerspectiveProjectionGW2 as PerspectiveProjection;
 
-use gw2_link::GW2Link;
+use clap::Parser;
+use cli::{Cli, Command, GameSelection, LinkMode, RunArgs};
+use clients::{Client, GameClient, LinkedClient};
+use config::{Config, ConfigSources, ConfigWatcher};
+use custom_window_plugin::WindowGeometry;
+use game_window::{GameWindow, X11Windows};
+use gw2_link::{
+    record::{Recorder, RecordingSource, ReplaySource},
+    EventTracker, GW2LinkBuilder, GameEvent, GameEventKind, GameState, HealthMonitor, Identity,
+    InstanceChange, LinkHealth, LinkSource, LinkedMem,
+};
 use gw2poi::PoiContainer;
+use pack_files::{PackAssetPlugin, PackRegistry};
+use packs::MarkerPack;
+use supervisor::{HelperState, HelperSupervisor, WineLauncher};
 
 use utils::ToGw2Coordinate;
 
 #[derive(Component)]
 struct GlobalState {
-    gw2link: GW2Link,
+    link: Box<dyn LinkSource>,
+    gw2_data: LinkedMem,
+    events: EventTracker,
+    health: HealthMonitor,
+    /// Keeps the helper forwarding the link running, if we started one
+    helper: Option<HelperSupervisor>,
+}
+
+impl GlobalState {
+    fn new(link: Box<dyn LinkSource>) -> Self {
+        Self {
+            link,
+            gw2_data: LinkedMem::default(),
+            events: EventTracker::new(),
+            health: HealthMonitor::new(),
+            helper: None,
+        }
+    }
+
+    fn with_helper(mut self, helper: HelperSupervisor) -> Self {
+        self.helper = Some(helper);
+        self
+    }
 }
 
 #[derive(Component)]
@@ -53,36 +104,343 @@ struct DebugText;
 #[derive(Component)]
 struct FpsText;
 
+/// Tells the user what the overlay is waiting for
+#[derive(Component)]
+struct StatusText;
+
+/// Shows the status text while no client renders to the primary window
+#[derive(Component)]
+struct IdleCamera;
+
+/// How often `/proc` is searched for new games
+const GAME_SCAN_INTERVAL: Duration = Duration::from_secs(2);
+/// How often the X windows are searched for games without a known window
+const GAME_WINDOW_SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Attaches to every game that is started while the overlay runs
 #[derive(Resource)]
+struct GameWatcher {
+    exe_names: Vec<String>,
+    /// Only attaches to this game instead of every game
+    pid: Option<i32>,
+    link: LinkSettings,
+    last_scan: Option<Instant>,
+}
+
+#[derive(Component)]
 struct CurrentLevel(u32);
 
+/// The newest data of the link
+#[derive(Component, Default)]
+struct CurrentGameState(GameState);
+
+/// The character that is currently played. `None` until the game sent a valid identity
+#[derive(Component, Default)]
+struct CurrentIdentity(Option<Identity>);
+
+#[derive(Component)]
+struct CurrentLinkHealth(LinkHealth);
+
+/// Everything tracked for one game client
+#[derive(Bundle)]
+struct ClientBundle {
+    client: Client,
+    state: GlobalState,
+    level: CurrentLevel,
+    game_state: CurrentGameState,
+    identity: CurrentIdentity,
+    health: CurrentLinkHealth,
+}
+
+impl ClientBundle {
+    fn new(client: Client, state: GlobalState) -> Self {
+        Self {
+            client,
+            state,
+            level: CurrentLevel(0),
+            game_state: CurrentGameState::default(),
+            identity: CurrentIdentity::default(),
+            health: CurrentLinkHealth(LinkHealth::NeverConnected),
+        }
+    }
+}
+
 #[derive(Resource)]
 struct MapData {
-    data: OverlayData,
+    packs: Vec<MarkerPack>,
+    /// Directory the markers were loaded from
+    path: PathBuf,
+}
+
+/// Where the link data of the clients comes from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+enum LinkKind {
+    /// Receives the packets of the helper
+    #[default]
+    Udp,
+    /// Reads an existing MumbleLink shared memory
+    Shm,
+    Replay {
+        path: PathBuf,
+        looping: bool,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct LinkSettings {
+    kind: LinkKind,
+    /// Records the session of every client
+    record: Option<PathBuf>,
+}
+
+impl LinkSettings {
+    fn new(mode: LinkMode, record: Option<PathBuf>) -> Self {
+        let kind = match mode {
+            LinkMode::Udp => LinkKind::Udp,
+            LinkMode::Shm => LinkKind::Shm,
+        };
+        Self { kind, record }
+    }
+}
+
+/// Creates the link source of a client.
+/// Every client after the first one gets its own port, mirror and recording
+fn create_link_source(
+    link: &LinkSettings,
+    index: u8,
+    port: u16,
+) -> Result<Box<dyn LinkSource>, gw2_link::Error> {
+    let mut builder = GW2LinkBuilder::new().port(port);
+    if index > 0 {
+        let shm_name = format!("{}.{}", builder.get_shm_name(), index);
+        builder = builder.shm_name(shm_name);
+    }
+    let source: Box<dyn LinkSource> = match &link.kind {
+        LinkKind::Replay { path, looping } => Box::new(ReplaySource::open(path)?.looping(*looping)),
+        LinkKind::Shm => Box::new(builder.build_shm_source()?),
+        LinkKind::Udp => Box::new(builder.build()?),
+    };
+    match &link.record {
+        Some(path) => {
+            let path = match index {
+                0 => path.clone(),
+                _ => PathBuf::from(format!("{}.{}", path.display(), index)),
+            };
+            info!("Recording link to {}", path.display());
+            Ok(Box::new(RecordingSource::new(
+                source,
+                Recorder::create(path)?,
+            )))
+        }
+        None => Ok(source),
+    }
 }
 
 fn main() {
-    let pid = processutils::find_wine_process("GW2-64.exe");
-    info!("Got pid {:?}", pid);
-    let (_mumble_file, _) = processutils::start_gw2_helper(pid.unwrap());
+    let cli = Cli::parse();
+    let sources = ConfigSources::new(cli.config.config.clone(), cli.get_overrides());
+    let config = match sources.load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let command = cli.command.unwrap_or(Command::Run(RunArgs::default()));
+    let code = match command {
+        Command::Run(run) => {
+            let link = LinkSettings::new(run.link, None);
+            run_overlay(sources, config, link, run.game)
+        }
+        Command::Record { file, run } => {
+            let link = LinkSettings::new(run.link, Some(file));
+            run_overlay(sources, config, link, run.game)
+        }
+        Command::Replay { file, looping, .. } => {
+            let link = LinkSettings {
+                kind: LinkKind::Replay {
+                    path: file,
+                    looping,
+                },
+                record: None,
+            };
+            run_overlay(sources, config, link, GameSelection::Auto)
+        }
+        Command::ValidatePacks { packs } => validate_packs(&packs.unwrap_or(config.markers.path)),
+        Command::ListMaps { packs } => list_maps(&packs.unwrap_or(config.markers.path)),
+        Command::UnpackPacks { out, packs } => {
+            unpack_packs(&packs.unwrap_or(config.markers.path), &out)
+        }
+        Command::Doctor => doctor(&config),
+    };
+    std::process::exit(code);
+}
+
+fn validate_packs(path: &Path) -> i32 {
+    let (checked, errors) = match packs::validate_packs(path) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    for error in &errors {
+        println!("{}: {}", error.file.display(), error.message);
+    }
+    println!(
+        "Checked {} files in {}, {} broken",
+        checked,
+        path.display(),
+        errors.len()
+    );
+    i32::from(!errors.is_empty())
+}
+
+fn list_maps(path: &Path) -> i32 {
+    let packs = packs::load_packs(path, &PackRegistry::default());
+    let maps = packs::count_markers(packs.iter().map(|pack| &pack.data));
+    println!("{:>6} {:>6} {:>6}", "MapID", "POIs", "Trails");
+    for (map_id, markers) in maps {
+        println!("{:>6} {:>6} {:>6}", map_id, markers.pois, markers.trails);
+    }
+    0
+}
+
+/// Every pack goes to a directory named after it. Packs with the same name get their id
+/// appended
+fn unpack_packs(path: &Path, out: &Path) -> i32 {
+    let packs = packs::load_packs(path, &PackRegistry::default());
+    let mut names = HashSet::new();
+    let mut failed = false;
+    for pack in &packs {
+        let mut name = pack.manifest.name.replace(['/', '\\'], "_");
+        if !names.insert(name.clone()) {
+            name = format!("{}.{}", name, pack.id);
+        }
+        let dir = out.join(name);
+        match pack.write_to(&dir) {
+            Ok(()) => println!("{} -> {}", pack.root.display(), dir.display()),
+            Err(e) => {
+                eprintln!("{}: {}", dir.display(), e);
+                failed = true;
+            }
+        }
+    }
+    i32::from(failed)
+}
+
+fn doctor(config: &Config) -> i32 {
+    let checks = doctor::run_checks(config);
+    for check in &checks {
+        println!("{}", check);
+    }
+    let failed = checks
+        .iter()
+        .any(|check| check.status == doctor::Status::Error);
+    i32::from(failed)
+}
+
+/// Shows the overlay until the window is closed
+fn run_overlay(
+    sources: ConfigSources,
+    config: Config,
+    link: LinkSettings,
+    game: GameSelection,
+) -> i32 {
+    let mut app = App::new();
+    match ConfigWatcher::new(sources) {
+        Ok(watcher) => {
+            app.insert_resource(watcher);
+        }
+        Err(e) => warn!("Changes of the config won't be applied: {}", e),
+    }
+    // Only the udp link needs the helpers. They are started once the games show up
+    if link.kind == LinkKind::Udp {
+        let exe_names = config.game.exe_names.clone();
+        let pid = match game {
+            GameSelection::Auto => None,
+            GameSelection::Pid(pid) => Some(pid),
+        };
+        match pid {
+            Some(pid) => info!("Waiting for game {}", pid),
+            None => info!("Waiting for {}", exe_names.join(", ")),
+        }
+        app.insert_resource(GameWatcher {
+            exe_names,
+            pid,
+            link,
+            last_scan: None,
+        })
+        .add_systems(Update, discover_games.before(supervise_helpers));
+    } else {
+        let link = match create_link_source(&link, 0, config.link.port) {
+            Ok(link) => link,
+            Err(e) => {
+                eprintln!("Failed to create the gw2 link: {}", e);
+                return 1;
+            }
+        };
+        let client = Client {
+            index: 0,
+            game_pid: None,
+        };
+        app.world
+            .spawn(ClientBundle::new(client, GlobalState::new(link)));
+    }
 
     // TODO: instead of own plugin just change the attributes etc. of the existing window by
     // getting the raw handle
-    let mut app = App::new();
-    app.add_systems(Startup, setup)
-        .add_systems(Startup, setup_window)
-        .add_systems(Update, update_gw2)
+    let primary_window = create_overlay_window(config.window.get_geometry());
+    match X11Windows::connect(None) {
+        Ok(x11) => {
+            app.insert_resource(x11);
+        }
+        Err(e) => warn!("The overlay won't follow the game windows: {}", e),
+    }
+    app.insert_resource(config)
+        .add_systems(Startup, setup)
+        .add_systems(PreUpdate, config::reload_config)
+        .add_systems(
+            Update,
+            (
+                apply_camera_config,
+                apply_window_config,
+                apply_text_config,
+                apply_game_config,
+                apply_marker_config,
+            )
+                .run_if(resource_changed::<Config>()),
+        )
+        .add_systems(Update, (supervise_helpers, update_gw2).chain())
+        .add_systems(Update, spawn_client_views)
+        .add_systems(
+            Update,
+            (follow_game_windows, place_overlay_windows)
+                .chain()
+                .after(supervise_helpers),
+        )
+        .add_systems(Update, update_status.after(update_gw2))
+        .add_systems(Last, stop_helpers_on_exit)
         //.add_systems(Update, (update_text_fps, update_text_debug))
         .add_systems(Update, animate_texture)
         .add_systems(Update, fade_out_pois)
         //.add_systems(Update, draw_lines)
         .add_systems(Update, map_change_event)
+        .add_systems(Update, log_link_events)
+        .add_systems(Update, hide_markers_when_stale.after(update_gw2))
         .insert_resource(ClearColor(Color::NONE))
-        .insert_resource(CurrentLevel(0))
         .add_plugins(
             DefaultPlugins
                 .build()
                 .disable::<bevy::winit::WinitPlugin>()
+                .add_before::<AssetPlugin, _>(PackAssetPlugin {
+                    packs: PackRegistry::default(),
+                })
+                .set(WindowPlugin {
+                    primary_window: Some(primary_window),
+                    ..default()
+                })
                 // Set the sampler mode to repeat for the trails to work
                 // https://github.com/bevyengine/bevy/issues/399
                 .set(ImagePlugin {
@@ -97,7 +455,9 @@ fn main() {
         .add_plugins(custom_window_plugin::WinitPlugin)
         .add_plugins(BillboardPlugin)
         .add_plugins(FrameTimeDiagnosticsPlugin)
-        .add_event::<MapChangeEvent>();
+        .add_event::<LinkEvent>()
+        .add_event::<MapChangeEvent>()
+        .add_event::<InstanceChangeEvent>();
 
     #[cfg(feature = "custom_projection")]
     app.add_plugins(CameraProjectionPlugin::<PerspectiveProjection>::default())
@@ -110,59 +470,220 @@ fn main() {
         );
 
     app.run();
+    0
 }
 
-fn setup_window(mut window: Query<&mut Window>) {
-    let mut window = window.single_mut();
+/// Starts a client for every new game. A restarted game belongs to the client that waits for
+/// a game with the same MumbleLink name
+fn discover_games(
+    mut commands: Commands,
+    mut watcher: ResMut<GameWatcher>,
+    config: Res<Config>,
+    clients: Query<(&Client, &GlobalState)>,
+) {
+    let now = Instant::now();
+    if matches!(watcher.last_scan, Some(last) if now - last < GAME_SCAN_INTERVAL) {
+        return;
+    }
+    watcher.last_scan = Some(now);
+
+    let mut used = vec![];
+    let mut attached = vec![];
+    let mut waiting = vec![];
+    for (client, state) in &clients {
+        used.push(client.index);
+        attached.extend(client.game_pid);
+        if let Some(helper) = &state.helper {
+            if helper.get_state() == HelperState::WaitingForGame {
+                waiting.push(helper.get_client().mumble_name.clone());
+            }
+        }
+    }
+    for process in clients::find_games(&watcher.exe_names) {
+        let pid = process.pid;
+        if attached.contains(&pid) || watcher.pid.is_some_and(|only| only != pid) {
+            continue;
+        }
+        if waiting.contains(&clients::get_mumble_name(&process.argv)) {
+            continue;
+        }
+        let Some(index) = clients::get_free_index(&used) else {
+            warn!("Ignoring game {}. Too many clients", pid);
+            continue;
+        };
+        let Some(game) = GameClient::new(&process, index, config.link.port) else {
+            warn!(
+                "Ignoring game {}. No port left after {}",
+                pid, config.link.port
+            );
+            continue;
+        };
+        let link = match create_link_source(&watcher.link, index, game.port) {
+            Ok(link) => link,
+            Err(e) => {
+                error!("Failed to create the gw2 link for game {}: {}", pid, e);
+                continue;
+            }
+        };
+        info!("Attaching to game {:?}", game);
+        let helper = HelperSupervisor::new(WineLauncher::new(watcher.exe_names.clone()), game);
+        commands.spawn(ClientBundle::new(
+            Client {
+                index,
+                game_pid: Some(pid),
+            },
+            GlobalState::new(link).with_helper(helper),
+        ));
+        used.push(index);
+    }
+}
 
-    window.present_mode = PresentMode::AutoVsync;
-    window.resolution.set(1920.0, 1080.0);
+/// Restarts crashed helpers and follows restarted games
+fn supervise_helpers(mut clients: Query<(&mut GlobalState, &mut Client)>) {
+    let games: Vec<i32> = clients
+        .iter()
+        .filter_map(|(state, _)| Some(state.helper.as_ref()?.get_client().pid))
+        .collect();
+    for (mut state, mut client) in &mut clients {
+        let Some(helper) = state.helper.as_mut() else {
+            continue;
+        };
+        let own = helper.get_client().pid;
+        let taken: Vec<i32> = games.iter().copied().filter(|&pid| pid != own).collect();
+        helper.update(&taken);
+        let game_pid = Some(helper.get_client().pid);
+        if client.game_pid != game_pid {
+            client.game_pid = game_pid;
+        }
+    }
+}
+
+/// The runner exits the process without dropping the world
+fn stop_helpers_on_exit(
+    mut ev_exit: EventReader<bevy::app::AppExit>,
+    mut clients: Query<&mut GlobalState>,
+) {
+    if ev_exit.iter().last().is_none() {
+        return;
+    }
+    for mut state in &mut clients {
+        if let Some(helper) = state.helper.as_mut() {
+            info!(
+                "Stopping the helper of game {} after {} restarts",
+                helper.get_client().pid,
+                helper.get_restarts()
+            );
+            helper.stop();
+        }
+    }
 }
 
 fn update_gw2(
-    mut global_state_query: Query<&mut GlobalState>,
-    mut camera_query: Query<&mut Transform, With<Gw2Camera>>,
+    mut clients: Query<(
+        Entity,
+        &mut GlobalState,
+        &mut CurrentLevel,
+        &mut CurrentGameState,
+        &mut CurrentIdentity,
+        &mut CurrentLinkHealth,
+    )>,
+    mut camera_query: Query<(&mut Transform, &LinkedClient), With<Gw2Camera>>,
+    mut ev_link: EventWriter<LinkEvent>,
     mut ev_map_change: EventWriter<MapChangeEvent>,
-    mut current_level_query: ResMut<CurrentLevel>,
+    mut ev_instance_change: EventWriter<InstanceChangeEvent>,
 ) {
-    let before = Instant::now();
-    while global_state_query.single_mut().gw2link.update_gw2(false) {}
-    //global_state_query.single_mut().gw2link.update_gw2(false);
-    let after = Instant::now();
-    let data = global_state_query.single_mut().gw2link.get_gw2_data();
-
-    let mut cam = camera_query.single_mut();
-    let mut camera_pos = Vec3::from_array(data.get_camera_pos());
-    let mut camera_front = Vec3::from_array(data.get_camera_front());
-
-    #[cfg(not(feature = "custom_projection"))]
-    camera_pos.to_gw2_coordinate();
-    #[cfg(not(feature = "custom_projection"))]
-    camera_front.to_gw2_coordinate();
-
-    cam.translation = camera_pos;
-    #[cfg(not(feature = "custom_projection"))]
-    cam.look_to(camera_front, Vec3::Y);
-    #[cfg(feature = "custom_projection")]
-    cam.look_to(-camera_front, Vec3::Y);
+    for (
+        client,
+        mut state,
+        mut current_level,
+        mut game_state,
+        mut current_identity,
+        mut link_health,
+    ) in &mut clients
+    {
+        let before = Instant::now();
+        let state = &mut *state;
+        let mut events = vec![];
+        while let Some(data) = state.link.poll() {
+            state.gw2_data = data;
+            state.health.record(&data);
+            // Check every packet to not miss short visits of an instance
+            events.extend(state.events.update(&data));
+        }
+        if let Some(e) = state.link.take_error() {
+            warn!("Link of {:?}: {}", client, e);
+        }
+        events.extend(state.events.check_ticks());
+        if let Some(helper) = &state.helper {
+            let alive = matches!(helper.get_state(), HelperState::Running(_));
+            state.health.set_helper_alive(alive);
+        }
+        let health = state.health.get_health();
+        if link_health.0 != health {
+            info!("Link health of {:?} changed to {:?}", client, health);
+            link_health.0 = health;
+        }
+        let after = Instant::now();
+
+        let mut new_map = None;
+        for event in events {
+            match &event.kind {
+                GameEventKind::MapChanged { to, .. } => new_map = Some(*to),
+                GameEventKind::InstanceChanged(change) => {
+                    ev_instance_change.send(InstanceChangeEvent {
+                        client,
+                        change: *change,
+                    });
+                }
+                _ => (),
+            }
+            ev_link.send(LinkEvent { client, event });
+        }
+        // Only the newest map matters. The markers of maps in between would never be despawned
+        if let Some(map_id) = new_map.filter(|&map_id| map_id != current_level.0) {
+            current_level.0 = map_id;
+            ev_map_change.send(MapChangeEvent { client, map_id });
+        }
+        let identity = state.events.get_identity_cache().get_identity();
+        if current_identity.0.as_ref() != identity {
+            current_identity.0 = identity.cloned();
+        }
+        let gw2_data = state.gw2_data;
+        let data = gw2_data.get_game_state();
+        if game_state.0 != data {
+            game_state.0 = data;
+        }
+
+        let mut camera_pos = Vec3::from_array(data.camera_position);
+        let mut camera_front = Vec3::from_array(data.camera_front);
 
-    let map_id = data.get_context().map_id;
-    if current_level_query.0 != map_id {
-        current_level_query.0 = map_id;
-        ev_map_change.send(MapChangeEvent(map_id));
+        #[cfg(not(feature = "custom_projection"))]
+        camera_pos.to_gw2_coordinate();
+        #[cfg(not(feature = "custom_projection"))]
+        camera_front.to_gw2_coordinate();
+
+        for (mut cam, _) in camera_query
+            .iter_mut()
+            .filter(|(_, linked)| linked.0 == client)
+        {
+            cam.translation = camera_pos;
+            #[cfg(not(feature = "custom_projection"))]
+            cam.look_to(camera_front, Vec3::Y);
+            #[cfg(feature = "custom_projection")]
+            cam.look_to(-camera_front, Vec3::Y);
+        }
     }
 }
 
 /// sets up a scene with textured entities
 fn setup(
     mut commands: Commands,
+    config: Res<Config>,
     asset_server: Res<AssetServer>,
+    pack_registry: Res<PackRegistry>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    let link = GW2Link::new().unwrap();
-    let state = GlobalState { gw2link: link };
-    commands.spawn(state);
     // load a texture and retrieve its aspect ratio
 
     //commands.spawn((
@@ -194,61 +715,342 @@ fn setup(
             TextSection::new(
                 "FPS: ",
                 TextStyle {
-                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                    font_size: 60.0,
+                    font: asset_server.load(&config.text.font),
+                    font_size: config.text.fps_size,
                     color: Color::WHITE,
                 },
             ),
             TextSection::from_style(TextStyle {
-                font: asset_server.load("fonts/FiraMono-Medium.ttf"),
-                font_size: 60.0,
+                font: asset_server.load(&config.text.mono_font),
+                font_size: config.text.fps_size,
                 color: Color::GOLD,
             }),
         ]),
         FpsText,
     ));
 
-    // camera
-    let projection = PerspectiveProjection {
-        fov: 1.222,
-        far: 1000.0,
-        ..Default::default()
-    };
-
     commands.spawn((
-        CameraRenderGraph::new(bevy::core_pipeline::core_3d::graph::NAME),
-        Camera::default(),
-        projection,
-        VisibleEntities::default(),
-        Frustum::default(),
-        Transform::default(),
-        GlobalTransform::default(),
-        Camera3d::default(),
-        Tonemapping::default(),
-        DebandDither::Enabled,
-        ColorGrading::default(),
-        Gw2Camera,
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load(&config.text.font),
+                font_size: config.text.status_size,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.0),
+            right: Val::Px(15.0),
+            ..default()
+        }),
+        StatusText,
     ));
+    commands.spawn((Camera2dBundle::default(), IdleCamera));
 
-    let path = Path::new("pois");
+    let path = config.markers.path.clone();
+    let mut packs = packs::load_packs(&path, &pack_registry);
+    packs::set_disabled_packs(&mut packs, &config.markers.disabled_packs);
+    let map_data = MapData { packs, path };
+    commands.insert_resource(map_data);
+}
+
+/// Creates the camera of every new client. The first client uses the primary window, every
+/// other client gets its own overlay window
+fn spawn_client_views(
+    mut commands: Commands,
+    clients: Query<(Entity, &Client), Added<Client>>,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    config: Res<Config>,
+) {
+    for (entity, client) in &clients {
+        info!(
+            "Creating the overlay of client {} for game {:?}",
+            client.index, client.game_pid
+        );
+        let window = match (client.index, primary_window.get_single()) {
+            (0, Ok(window)) => {
+                commands.entity(window).insert(LinkedClient(entity));
+                WindowRef::Primary
+            }
+            _ => WindowRef::Entity(
+                commands
+                    .spawn((
+                        Window {
+                            title: format!("RustyGw2 {}", client.index),
+                            ..create_overlay_window(config.window.get_geometry())
+                        },
+                        LinkedClient(entity),
+                    ))
+                    .id(),
+            ),
+        };
+
+        let projection = PerspectiveProjection {
+            fov: config.camera.fov,
+            far: config.camera.far,
+            ..Default::default()
+        };
+
+        commands.spawn((
+            CameraRenderGraph::new(bevy::core_pipeline::core_3d::graph::NAME),
+            Camera {
+                target: RenderTarget::Window(window),
+                ..default()
+            },
+            projection,
+            VisibleEntities::default(),
+            Frustum::default(),
+            Transform::default(),
+            GlobalTransform::default(),
+            Camera3d::default(),
+            Tonemapping::default(),
+            DebandDither::Enabled,
+            ColorGrading::default(),
+            Gw2Camera,
+            LinkedClient(entity),
+            client.render_layers(),
+        ));
+    }
+}
 
-    let mut overlay_data: OverlayData = OverlayData {
-        ..Default::default()
+fn apply_camera_config(
+    config: Res<Config>,
+    mut cameras: Query<&mut PerspectiveProjection, With<Gw2Camera>>,
+) {
+    for mut projection in &mut cameras {
+        projection.fov = config.camera.fov;
+        projection.far = config.camera.far;
+    }
+}
+
+/// Every overlay window that doesn't follow a game covers the configured area
+fn apply_window_config(
+    mut commands: Commands,
+    config: Res<Config>,
+    game_windows: Query<Entity, With<GameWindow>>,
+    mut windows: Query<(&mut Window, Option<&LinkedClient>)>,
+) {
+    if !config.window.follow_game {
+        for client in &game_windows {
+            commands.entity(client).remove::<GameWindow>();
+        }
+    }
+    for (mut window, linked) in &mut windows {
+        let follows_game = linked.is_some_and(|linked| game_windows.contains(linked.0));
+        if !(config.window.follow_game && follows_game) {
+            set_window_geometry(&mut window, config.window.get_geometry());
+        }
+    }
+}
+
+/// The window plugin moves the X window once the position or resolution changed
+fn set_window_geometry(window: &mut Window, geometry: WindowGeometry) {
+    let position = WindowPosition::At(IVec2::new(geometry.x, geometry.y));
+    if window.position != position {
+        window.position = position;
+    }
+    if window.resolution.physical_width() != geometry.width
+        || window.resolution.physical_height() != geometry.height
+    {
+        window
+            .resolution
+            .set_physical_resolution(geometry.width, geometry.height);
+    }
+}
+
+fn create_overlay_window(geometry: WindowGeometry) -> Window {
+    Window {
+        present_mode: PresentMode::AutoVsync,
+        position: WindowPosition::At(IVec2::new(geometry.x, geometry.y)),
+        resolution: WindowResolution::new(geometry.width as f32, geometry.height as f32)
+            .with_scale_factor_override(1.0),
+        ..default()
+    }
+}
+
+/// Finds the X window of every game and reports its moves and resizes
+fn follow_game_windows(
+    mut commands: Commands,
+    x11: Option<Res<X11Windows>>,
+    config: Res<Config>,
+    mut last_scan: Local<Option<Instant>>,
+    mut clients: Query<(Entity, &Client, Option<&mut GameWindow>)>,
+) {
+    let Some(x11) = x11 else {
+        return;
     };
-    for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file() && entry.path().extension().unwrap_or_default() == "xml" {
-            info!("Found XML file: {:?}", entry.path());
-            let file_path = entry.path().to_string_lossy().to_string();
-            let data = OverlayData::from_file(&file_path);
-            match data {
-                Ok(data) => overlay_data.merge(data),
-                Err(e) => error!("Failed to load file {} with error {}", file_path, e),
+    if !config.window.follow_game {
+        return;
+    }
+    let changed = x11.poll_changes().unwrap_or_else(|e| {
+        warn!("Failed to read the events of the game windows: {}", e);
+        vec![]
+    });
+    let now = Instant::now();
+    let scan = !matches!(*last_scan, Some(last) if now - last < GAME_WINDOW_SCAN_INTERVAL);
+    if scan {
+        *last_scan = Some(now);
+    }
+
+    for (entity, client, game_window) in &mut clients {
+        match game_window {
+            Some(mut game_window) => {
+                if !changed.contains(&game_window.window) {
+                    continue;
+                }
+                if !x11.is_alive(game_window.window) {
+                    info!("Window of client {} is gone", client.index);
+                    commands.entity(entity).remove::<GameWindow>();
+                    continue;
+                }
+                match x11.get_geometry(game_window.window) {
+                    Ok(geometry) if geometry != game_window.geometry => {
+                        game_window.geometry = geometry;
+                    }
+                    Ok(_) => (),
+                    Err(e) => warn!("Failed to get the window of client {}: {}", client.index, e),
+                }
+            }
+            None => {
+                let Some(pid) = client.game_pid.filter(|_| scan) else {
+                    continue;
+                };
+                let found = x11.find_by_pid(pid).and_then(|window| match window {
+                    Some(window) => {
+                        x11.watch(window)?;
+                        Ok(Some((window, x11.get_geometry(window)?)))
+                    }
+                    None => Ok(None),
+                });
+                match found {
+                    Ok(Some((window, geometry))) => {
+                        info!("Found window of game {} at {:?}", pid, geometry);
+                        commands
+                            .entity(entity)
+                            .insert(GameWindow { window, geometry });
+                    }
+                    Ok(None) => (),
+                    Err(e) => warn!("Failed to find the window of game {}: {}", pid, e),
+                }
             }
         }
     }
-    overlay_data.fill_poi_parents();
-    let map_data = MapData { data: overlay_data };
-    commands.insert_resource(map_data);
+}
+
+/// Moves the overlay of a client onto its game or back to the configured area once the
+/// game window is gone
+fn place_overlay_windows(
+    config: Res<Config>,
+    game_windows: Query<(Entity, &GameWindow), Changed<GameWindow>>,
+    mut removed: RemovedComponents<GameWindow>,
+    mut windows: Query<(&mut Window, &LinkedClient)>,
+) {
+    let mut geometries: Vec<(Entity, WindowGeometry)> = game_windows
+        .iter()
+        .map(|(client, game_window)| (client, game_window.geometry))
+        .collect();
+    geometries.extend(
+        removed
+            .iter()
+            .map(|client| (client, config.window.get_geometry())),
+    );
+    for (client, geometry) in geometries {
+        for (mut window, _) in windows.iter_mut().filter(|(_, linked)| linked.0 == client) {
+            set_window_geometry(&mut window, geometry);
+        }
+    }
+}
+
+fn apply_text_config(
+    config: Res<Config>,
+    asset_server: Res<AssetServer>,
+    mut fps_text: Query<&mut Text, (With<FpsText>, Without<StatusText>)>,
+    mut status_text: Query<&mut Text, With<StatusText>>,
+) {
+    let font: Handle<Font> = asset_server.load(&config.text.font);
+    let mono_font: Handle<Font> = asset_server.load(&config.text.mono_font);
+    for mut text in &mut fps_text {
+        for (section, font) in text.sections.iter_mut().zip([&font, &mono_font]) {
+            section.style.font = font.clone();
+            section.style.font_size = config.text.fps_size;
+        }
+    }
+    for mut text in &mut status_text {
+        for section in &mut text.sections {
+            section.style.font = font.clone();
+            section.style.font_size = config.text.status_size;
+        }
+    }
+}
+
+fn apply_game_config(config: Res<Config>, watcher: Option<ResMut<GameWatcher>>) {
+    if let Some(mut watcher) = watcher {
+        if watcher.exe_names != config.game.exe_names {
+            watcher.exe_names = config.game.exe_names.clone();
+        }
+    }
+}
+
+/// Reloads the markers from a new directory, toggles the packs and shows the markers again on
+/// the current maps
+fn apply_marker_config(
+    config: Res<Config>,
+    map_data: Option<ResMut<MapData>>,
+    pack_registry: Res<PackRegistry>,
+    clients: Query<(Entity, &CurrentLevel)>,
+    mut ev_map_change: EventWriter<MapChangeEvent>,
+) {
+    let Some(mut map_data) = map_data else {
+        return;
+    };
+    let mut changed = false;
+    if map_data.path != config.markers.path {
+        info!("Loading markers from {}", config.markers.path.display());
+        map_data.path = config.markers.path.clone();
+        pack_registry.clear();
+        map_data.packs = packs::load_packs(&map_data.path, &pack_registry);
+        changed = true;
+    }
+    changed |= packs::set_disabled_packs(&mut map_data.packs, &config.markers.disabled_packs);
+    if !changed {
+        return;
+    }
+    for (client, level) in &clients {
+        ev_map_change.send(MapChangeEvent {
+            client,
+            map_id: level.0,
+        });
+    }
+}
+
+/// Shows what the overlay waits for while no game is connected
+fn update_status(
+    watcher: Option<Res<GameWatcher>>,
+    clients: Query<(&Client, &CurrentLinkHealth)>,
+    mut status: Query<&mut Text, With<StatusText>>,
+    mut idle_camera: Query<&mut Camera, With<IdleCamera>>,
+) {
+    let connected = clients
+        .iter()
+        .any(|(_, health)| health.0 == LinkHealth::Connected);
+    let value = match (watcher, connected) {
+        (_, true) => String::new(),
+        (Some(watcher), false) => format!("Waiting for {}", watcher.exe_names.join(", ")),
+        (None, false) => "Waiting for the link".to_string(),
+    };
+    for mut text in &mut status {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+    // The camera of the first client shows the text once it exists
+    let idle = !clients.iter().any(|(client, _)| client.index == 0);
+    for mut camera in &mut idle_camera {
+        if camera.is_active != idle {
+            camera.is_active = idle;
+        }
+    }
 }
 
 fn update_text_fps(diagnostics: Res<DiagnosticsStore>, mut query: Query<&mut Text, With<FpsText>>) {
@@ -313,8 +1115,56 @@ struct BevyTrail {
     trail: TrailContainer,
 }
 
+/// Every transition reported by the link of `client`
 #[derive(Event)]
-struct MapChangeEvent(u32);
+struct LinkEvent {
+    client: Entity,
+    event: GameEvent,
+}
+
+#[derive(Event)]
+struct MapChangeEvent {
+    client: Entity,
+    map_id: u32,
+}
+
+/// Sent when the player enters another map or another instance of the same map
+#[derive(Event)]
+struct InstanceChangeEvent {
+    client: Entity,
+    change: InstanceChange,
+}
+
+/// Markers would stay frozen on the screen after the game closed
+fn hide_markers_when_stale(
+    clients: Query<&CurrentLinkHealth>,
+    mut markers: Query<(&mut Visibility, &LinkedClient), Or<(With<BevyPOI>, With<BevyTrail>)>>,
+) {
+    for (mut marker, linked) in &mut markers {
+        let visibility = match clients.get(linked.0).map(|health| health.0) {
+            Ok(LinkHealth::Connected) => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+        if *marker != visibility {
+            *marker = visibility;
+        }
+    }
+}
+
+fn log_link_events(
+    mut ev_link: EventReader<LinkEvent>,
+    mut ev_instance_change: EventReader<InstanceChangeEvent>,
+) {
+    for event in ev_instance_change.iter() {
+        info!(
+            "Client {:?} changed instance to {:?}",
+            event.client, event.change.to
+        );
+    }
+    for event in ev_link.iter() {
+        info!("Link event of {:?}: {:?}", event.client, event.event.kind);
+    }
+}
 
 fn map_change_event(
     mut commands: Commands,
@@ -323,25 +1173,34 @@ fn map_change_event(
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut billboard_textures: ResMut<Assets<BillboardTexture>>,
     mut ev_map_change: EventReader<MapChangeEvent>,
-    pois: Query<(Entity, With<BevyPOI>)>,
+    pois: Query<(Entity, &LinkedClient), With<BevyPOI>>,
+    trails: Query<(Entity, &LinkedClient), With<BevyTrail>>,
+    clients: Query<&Client>,
     map_data: Res<MapData>,
 ) {
     for event in ev_map_change.iter() {
-        let current_map: u32 = event.0;
-        info!("Changed map to {}", current_map);
+        let current_map: u32 = event.map_id;
+        let Ok(client) = clients.get(event.client) else {
+            continue;
+        };
+        let linked = LinkedClient(event.client);
+        info!("Client {} changed map to {}", client.index, current_map);
         pois.iter()
+            .chain(trails.iter())
+            .filter(|(_, marker_client)| **marker_client == linked)
             .for_each(|(entity, _)| commands.entity(entity).despawn());
 
-        map_data.data.pois.poi_list.iter().for_each(|poi_lock| {
+        let enabled_packs = || map_data.packs.iter().filter(|pack| pack.is_enabled());
+        let pois = enabled_packs().flat_map(|pack| &pack.data.pois.poi_list);
+        pois.for_each(|poi_lock| {
             let poi = poi_lock.read().unwrap();
             if current_map == poi.get_map_id().unwrap_or(0) {
-                let icon_path = poi.get_icon_file();
-                if icon_path.is_none() {
+                let Some((pack, icon_path)) = poi.get_icon_source() else {
                     error!("Poi {:?} didn't have a icon path!", poi.get_display_name());
                     return ();
-                }
+                };
                 let texture_handle =
-                    asset_server.load(icon_path.unwrap().to_string_lossy().replace(r"\", "/"));
+                    asset_server.load(pack_files::get_asset_path(pack, &icon_path));
 
                 let entity = BevyPOI {
                     poi: poi_lock.clone(),
@@ -374,17 +1233,23 @@ fn map_change_event(
                         ..default()
                     },
                     entity,
+                    linked,
+                    client.render_layers(),
                 ));
             }
         });
 
-        info!("Number of trails: {}", map_data.data.pois.trail_list.len());
-        map_data.data.pois.trail_list.iter().for_each(|trail_lock| {
+        let trails: Vec<&TrailContainer> = enabled_packs()
+            .flat_map(|pack| &pack.data.pois.trail_list)
+            .collect();
+        info!("Number of trails: {}", trails.len());
+        trails.into_iter().for_each(|trail_lock| {
             let trail = trail_lock.read().unwrap();
             if current_map == trail.poi.get_map_id().unwrap_or(0) {
-                let texture = trail.texture.clone();
-                let texture_handle =
-                    asset_server.load(texture.to_string_lossy().replace(r"\", "/"));
+                let texture_handle = asset_server.load(pack_files::get_asset_path(
+                    trail.poi.get_pack(),
+                    &trail.texture,
+                ));
 
                 let entity = BevyTrail {
                     trail: trail_lock.clone(),
@@ -407,7 +1272,7 @@ fn map_change_event(
                     .collect();
 
                 for bundle in pbr_bundles {
-                    commands.spawn((bundle, entity.clone()));
+                    commands.spawn((bundle, entity.clone(), linked, client.render_layers()));
                 }
             }
         });
@@ -439,30 +1304,337 @@ fn animate_texture(
 }
 
 fn fade_out_pois(
-    poi_query: Query<(&mut BillboardMeshHandle, &Transform, &BevyPOI)>,
-    camera_query: Query<&Transform, With<Gw2Camera>>,
+    poi_query: Query<(
+        &mut BillboardMeshHandle,
+        &Transform,
+        &BevyPOI,
+        &LinkedClient,
+    )>,
+    camera_query: Query<(&Transform, &LinkedClient), With<Gw2Camera>>,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
-    poi_query.iter().for_each(|(mesh_handle, transform, poi)| {
-        // a
-        let mesh = meshes.get_mut(&mesh_handle.0).unwrap();
-        let color_attribute = mesh.attribute_mut(Mesh::ATTRIBUTE_COLOR).unwrap();
+    poi_query
+        .iter()
+        .for_each(|(mesh_handle, transform, poi, linked)| {
+            let Some((camera_pos, _)) = camera_query.iter().find(|(_, camera)| *camera == linked)
+            else {
+                return;
+            };
+            // a
+            let mesh = meshes.get_mut(&mesh_handle.0).unwrap();
+            let color_attribute = mesh.attribute_mut(Mesh::ATTRIBUTE_COLOR).unwrap();
+
+            let VertexAttributeValues::Float32x4(color_attribute) = color_attribute else {
+                panic!("Unexpected vertex format, expected Float32x4.");
+            };
+
+            let distance = camera_pos.translation.distance(transform.translation);
+            let far = poi.poi.read().unwrap().get_fade_far().unwrap_or(f32::MAX) / 39.37;
+            let near = poi.poi.read().unwrap().get_fade_near().unwrap_or(0.0) / 39.37;
+
+            let a = (1.0 - (distance - near) / (far - near))
+                .clamp(0.0, poi.poi.read().unwrap().get_alpha().unwrap_or(1.0));
+            for color in color_attribute.iter_mut() {
+                color[3] = a;
+            }
+
+            //// Iterate over the UV coordinates, and change them as we want.
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{prelude::*, window::WindowPosition};
+    use bevy_mod_billboard::prelude::BillboardTexture;
+    use custom_window_plugin::WindowGeometry;
+    use gw2_link::{
+        GameEventKind, LinkHealth, LinkedMem, MapType, MockSource, MumbleContext, UiState,
+    };
+
+    use crate::{
+        apply_marker_config,
+        clients::{Client, LinkedClient},
+        config::Config,
+        game_window::GameWindow,
+        map_change_event,
+        pack_files::{tests::write_archive, PackRegistry},
+        packs, place_overlay_windows,
+        trail::TrailFile,
+        update_gw2, update_status, BevyTrail, ClientBundle, CurrentGameState, CurrentIdentity,
+        CurrentLevel, CurrentLinkHealth, GameWatcher, GlobalState, Gw2Camera, IdleCamera,
+        InstanceChangeEvent, LinkEvent, MapChangeEvent, MapData, StatusText,
+    };
+
+    fn mock_mem(tick: u32, map_id: u32, camera_position: [f32; 3]) -> LinkedMem {
+        let mut mem = LinkedMem {
+            ui_tick: tick,
+            camera_position,
+            camera_front: [0.0, 0.0, 1.0],
+            ..Default::default()
+        };
+        mem.set_context(&MumbleContext {
+            map_id,
+            map_type: 5,
+            ui_state: UiState::GAME_FOCUS.bits(),
+            ..Default::default()
+        });
+        mem.set_identity(r#"{"name":"Test","profession":1,"map_id":50}"#);
+        mem
+    }
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_event::<LinkEvent>()
+            .add_event::<MapChangeEvent>()
+            .add_event::<InstanceChangeEvent>()
+            .add_systems(Update, update_gw2);
+        app
+    }
+
+    /// Spawns a client with its camera
+    fn spawn_client(app: &mut App, index: u8, source: MockSource) -> (Entity, Entity) {
+        let client = app
+            .world
+            .spawn(ClientBundle::new(
+                Client {
+                    index,
+                    game_pid: None,
+                },
+                GlobalState::new(Box::new(source)),
+            ))
+            .id();
+        let camera = app
+            .world
+            .spawn((Transform::default(), Gw2Camera, LinkedClient(client)))
+            .id();
+        (client, camera)
+    }
+
+    #[test]
+    fn update_gw2_test() {
+        let mut app = test_app();
+        let source: MockSource = [
+            mock_mem(1, 15, [1.0, 2.0, 3.0]),
+            mock_mem(2, 50, [4.0, 5.0, 6.0]),
+        ]
+        .into_iter()
+        .collect();
+        let (client, camera) = spawn_client(&mut app, 0, source);
+
+        app.update();
+
+        // Only the newest data is used
+        let translation = app.world.get::<Transform>(camera).unwrap().translation;
+        #[cfg(not(feature = "custom_projection"))]
+        assert_eq!(translation, Vec3::new(4.0, 5.0, -6.0));
+        #[cfg(feature = "custom_projection")]
+        assert_eq!(translation, Vec3::new(4.0, 5.0, 6.0));
+        assert_eq!(app.world.get::<CurrentLevel>(client).unwrap().0, 50);
+
+        let game_state = app.world.get::<CurrentGameState>(client).unwrap().0;
+        assert_eq!(game_state.ui_tick, 2);
+        assert_eq!(game_state.map_type, MapType::Public);
+        assert!(game_state.ui_state.has_game_focus());
+        let identity = app
+            .world
+            .get::<CurrentIdentity>(client)
+            .unwrap()
+            .0
+            .clone()
+            .unwrap();
+        assert_eq!(identity.name, "Test");
+        assert_eq!(
+            app.world.get::<CurrentLinkHealth>(client).unwrap().0,
+            LinkHealth::Connected
+        );
+
+        let events = app.world.resource::<Events<MapChangeEvent>>();
+        let mut reader = events.get_reader();
+        let map_ids: Vec<u32> = reader.iter(events).map(|e| e.map_id).collect();
+        assert_eq!(map_ids, vec![50]);
+
+        // Both maps are reported as instance changes
+        let events = app.world.resource::<Events<InstanceChangeEvent>>();
+        let mut reader = events.get_reader();
+        let map_ids: Vec<u32> = reader.iter(events).map(|e| e.change.to.map_id).collect();
+        assert_eq!(map_ids, vec![15, 50]);
+
+        let events = app.world.resource::<Events<LinkEvent>>();
+        let mut reader = events.get_reader();
+        assert!(reader
+            .iter(events)
+            .any(|e| e.client == client && e.event.kind == GameEventKind::FocusGained));
+    }
+
+    #[test]
+    fn update_gw2_multiple_clients_test() {
+        let mut app = test_app();
+        let first: MockSource = [mock_mem(1, 15, [1.0, 2.0, 3.0])].into_iter().collect();
+        let second: MockSource = [mock_mem(1, 50, [4.0, 5.0, 6.0])].into_iter().collect();
+        let (first, first_camera) = spawn_client(&mut app, 0, first);
+        let (second, second_camera) = spawn_client(&mut app, 1, second);
+
+        app.update();
+
+        // Every camera follows its own game
+        let first_translation = app
+            .world
+            .get::<Transform>(first_camera)
+            .unwrap()
+            .translation;
+        let second_translation = app
+            .world
+            .get::<Transform>(second_camera)
+            .unwrap()
+            .translation;
+        assert_eq!(first_translation.x, 1.0);
+        assert_eq!(second_translation.x, 4.0);
+        assert_eq!(app.world.get::<CurrentLevel>(first).unwrap().0, 15);
+        assert_eq!(app.world.get::<CurrentLevel>(second).unwrap().0, 50);
 
-        let VertexAttributeValues::Float32x4(color_attribute) = color_attribute else {
-            panic!("Unexpected vertex format, expected Float32x4.");
+        let events = app.world.resource::<Events<MapChangeEvent>>();
+        let mut reader = events.get_reader();
+        let mut changes: Vec<(Entity, u32)> =
+            reader.iter(events).map(|e| (e.client, e.map_id)).collect();
+        changes.sort();
+        let mut expected = vec![(first, 15), (second, 50)];
+        expected.sort();
+        assert_eq!(changes, expected);
+    }
+
+    #[test]
+    fn update_status_test() {
+        let mut app = test_app();
+        app.insert_resource(GameWatcher {
+            exe_names: vec!["GW2-64.exe".to_string()],
+            pid: None,
+            link: Default::default(),
+            last_scan: None,
+        })
+        .add_systems(Update, update_status.after(update_gw2));
+        let text = app
+            .world
+            .spawn((Text::from_section("", TextStyle::default()), StatusText))
+            .id();
+        let idle_camera = app.world.spawn((Camera::default(), IdleCamera)).id();
+
+        // Idle until a game is found
+        app.update();
+        let value = &app.world.get::<Text>(text).unwrap().sections[0].value;
+        assert_eq!(value, "Waiting for GW2-64.exe");
+        assert!(app.world.get::<Camera>(idle_camera).unwrap().is_active);
+
+        let source: MockSource = [mock_mem(1, 15, [1.0, 2.0, 3.0])].into_iter().collect();
+        spawn_client(&mut app, 0, source);
+        app.update();
+        assert!(app.world.get::<Text>(text).unwrap().sections[0]
+            .value
+            .is_empty());
+        assert!(!app.world.get::<Camera>(idle_camera).unwrap().is_active);
+    }
+
+    #[test]
+    fn place_overlay_windows_test() {
+        let mut app = test_app();
+        app.insert_resource(Config::default())
+            .add_systems(Update, place_overlay_windows);
+        let source: MockSource = [mock_mem(1, 15, [1.0, 2.0, 3.0])].into_iter().collect();
+        let (client, _) = spawn_client(&mut app, 1, source);
+        let window = app
+            .world
+            .spawn((Window::default(), LinkedClient(client)))
+            .id();
+        let other = app.world.spawn(Window::default()).id();
+
+        let geometry = WindowGeometry {
+            x: 100,
+            y: 50,
+            width: 1280,
+            height: 720,
         };
+        app.world.entity_mut(client).insert(GameWindow {
+            window: xcb::Xid::none(),
+            geometry,
+        });
+        app.update();
+        let placed = app.world.get::<Window>(window).unwrap();
+        assert_eq!(placed.position, WindowPosition::At(IVec2::new(100, 50)));
+        assert_eq!(placed.resolution.physical_width(), 1280);
+        assert_eq!(placed.resolution.physical_height(), 720);
+        // Windows of other clients stay where they are
+        let untouched = app.world.get::<Window>(other).unwrap();
+        assert_eq!(untouched.position, WindowPosition::Automatic);
 
-        let camera_pos = camera_query.get_single().unwrap();
-        let distance = camera_pos.translation.distance(transform.translation);
-        let far = poi.poi.read().unwrap().get_fade_far().unwrap_or(f32::MAX) / 39.37;
-        let near = poi.poi.read().unwrap().get_fade_near().unwrap_or(0.0) / 39.37;
+        // Back to the configured area once the game window is gone
+        app.world.entity_mut(client).remove::<GameWindow>();
+        app.update();
+        let placed = app.world.get::<Window>(window).unwrap();
+        let config = Config::default().window;
+        assert_eq!(
+            placed.position,
+            WindowPosition::At(IVec2::new(config.x, config.y))
+        );
+        assert_eq!(placed.resolution.physical_width(), config.width);
+    }
 
-        let a = (1.0 - (distance - near) / (far - near))
-            .clamp(0.0, poi.poi.read().unwrap().get_alpha().unwrap_or(1.0));
-        for color in color_attribute.iter_mut() {
-            color[3] = a;
+    #[test]
+    fn toggle_pack_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let trail = TrailFile {
+            version: 0,
+            map_id: 15,
+            points: vec![Vec3::new(1.0, 2.0, 3.0), Vec3::new(4.0, 2.0, 3.0)],
+            trailing_bytes: 0,
+        }
+        .to_bytes();
+        let markers = br#"<OverlayData><POIs><Trail trailData="trail.trl" texture="trail.png"/></POIs></OverlayData>"#;
+        for name in ["a.zip", "b.zip"] {
+            write_archive(
+                &dir.path().join(name),
+                &[("markers.xml", markers), ("trail.trl", &trail)],
+            );
         }
+        let registry = PackRegistry::default();
+        let packs = packs::load_packs(dir.path(), &registry);
+        let first_pack = packs[0].id;
+        let mut config = Config::default();
+        config.markers.path = dir.path().to_path_buf();
 
-        //// Iterate over the UV coordinates, and change them as we want.
-    });
+        let mut app = test_app();
+        app.add_plugins((TaskPoolPlugin::default(), AssetPlugin::default()))
+            .add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>()
+            .add_asset::<BillboardTexture>()
+            .insert_resource(config)
+            .insert_resource(registry)
+            .insert_resource(MapData {
+                packs,
+                path: dir.path().to_path_buf(),
+            })
+            .add_systems(Update, (apply_marker_config, map_change_event).chain());
+        let (client, _) = spawn_client(&mut app, 0, MockSource::new());
+        app.world.get_mut::<CurrentLevel>(client).unwrap().0 = 15;
+        let trail_packs = |app: &mut App| {
+            let mut query = app.world.query::<&BevyTrail>();
+            let mut packs: Vec<Option<u32>> = query
+                .iter(&app.world)
+                .map(|trail| trail.trail.read().unwrap().poi.get_pack())
+                .collect();
+            packs.sort();
+            packs
+        };
+
+        app.world.send_event(MapChangeEvent { client, map_id: 15 });
+        app.update();
+        assert_eq!(trail_packs(&mut app).len(), 2);
+
+        // The trails of the disabled pack go away, the others aren't spawned twice
+        app.world.resource_mut::<Config>().markers.disabled_packs = vec!["b".to_string()];
+        app.update();
+        assert_eq!(trail_packs(&mut app), vec![Some(first_pack)]);
+
+        app.world.resource_mut::<Config>().markers.disabled_packs = vec![];
+        app.update();
+        assert_eq!(trail_packs(&mut app).len(), 2);
+    }
 }
@@ -1,60 +1,197 @@
-use std::{error::Error, fs};
+use std::{
+    error::Error,
+    io::{Read, Write},
+    path::Path,
+    sync::{Arc, RwLock},
+};
 
-use bevy::prelude::info;
-use serde::Deserialize;
+use bevy::prelude::{error, info};
+use serde::de::value;
 
 use crate::{
-    gw2poi::{
-        deserialize_marker_category_vec, deserialize_poi_vec, MarkerCategoryContainer,
-        PoiContainer, PoiTrait,
-    },
-    trail::{deserialize_trail_vec, TrailContainer},
+    diagnostics::{Diagnostics, Parsed},
+    gw2poi::{MarkerCategory, MarkerCategoryContainer, PoiContainer, PoiTrait, POI},
+    pack_files::PackFiles,
+    trail::{Trail, TrailContainer},
+    xml_element::XmlElement,
 };
 
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct OverlayData {
-    #[serde(
-        rename = "MarkerCategory",
-        deserialize_with = "deserialize_marker_category_vec",
-        default
-    )]
     pub marker_category: Vec<MarkerCategoryContainer>,
-    #[serde(rename = "POIs", default)]
     pub pois: POIs,
 }
 
+/// Reads a `MarkerCategory` element and the categories nested in it
+fn read_category(
+    element: &XmlElement,
+    path: &str,
+    diagnostics: &mut Diagnostics,
+) -> Option<MarkerCategory> {
+    let mut category: MarkerCategory = element.deserialize(path, diagnostics)?;
+    if category.name.is_empty() {
+        diagnostics.record(element.line, element.column, path, "missing name");
+        return None;
+    }
+    for (child_path, child) in element.get_children(path) {
+        if child.name != "MarkerCategory" {
+            continue;
+        }
+        if let Some(child) = read_category(child, &child_path, diagnostics) {
+            category.add_child(child);
+        }
+    }
+    Some(category)
+}
+
+/// Only POIs have a position, categories and trails inherit the fields from [`POI`]
+const POSITION_ATTRIBUTES: [&str; 3] = ["xpos", "ypos", "zpos"];
+
+fn remove_position(element: &mut XmlElement) {
+    element
+        .attributes
+        .retain(|(name, _)| !POSITION_ATTRIBUTES.contains(&name.as_str()));
+}
+
+/// Writes a category and the categories nested in it. The children are sorted by name, so
+/// the same categories are always written the same way
+fn write_category(category: &MarkerCategory) -> Result<XmlElement, value::Error> {
+    let mut element = XmlElement::from_value("MarkerCategory", category)?;
+    remove_position(&mut element);
+    let mut children: Vec<_> = category.children.values().collect();
+    children.sort_by_key(|child| child.read().unwrap().name.clone());
+    for child in children {
+        element
+            .children
+            .push(write_category(&child.read().unwrap())?);
+    }
+    Ok(element)
+}
+
 impl OverlayData {
+    /// Categories with the same name are merged, so a pack can spread them over its files
     pub fn merge(&mut self, mut other: OverlayData) {
         self.pois.poi_list.append(&mut other.pois.poi_list);
         self.pois.trail_list.append(&mut other.pois.trail_list);
-        self.marker_category.append(&mut other.marker_category);
+        for category in other.marker_category {
+            self.add_category(category);
+        }
+    }
+
+    fn add_category(&mut self, category: MarkerCategoryContainer) {
+        let name = category.read().unwrap().name.clone();
+        let existing = self
+            .marker_category
+            .iter()
+            .find(|existing| existing.read().unwrap().name == name);
+        match existing {
+            Some(existing) => existing.write().unwrap().merge(&category.read().unwrap()),
+            None => self.marker_category.push(category),
+        }
     }
-    pub fn from_file(file_path: &str) -> Result<Self, Box<dyn Error>> {
-        let file_handle = fs::File::open(file_path).unwrap();
 
-        let mut de = serde_xml_rs::Deserializer::new_from_reader(file_handle)
-            .non_contiguous_seq_elements(true);
-        let data = OverlayData::deserialize(&mut de)?;
+    /// Reads a marker file. Broken elements are skipped and recorded with their position in
+    /// `file`
+    pub fn parse(reader: impl Read, file: &Path) -> Parsed<Self> {
+        let mut diagnostics = Diagnostics::new(file);
+        let mut data = OverlayData::default();
+        match XmlElement::read(reader, &mut diagnostics) {
+            Some(root) if root.name == "OverlayData" => data.read_root(&root, &mut diagnostics),
+            Some(root) => diagnostics.record(
+                root.line,
+                root.column,
+                &root.name,
+                "the root element has to be OverlayData",
+            ),
+            None => (),
+        }
         info!(
             "Loaded {} POIs and {} Trails",
             data.pois.poi_list.len(),
             data.pois.trail_list.len()
         );
-        Ok(data)
+        diagnostics.finish(data)
     }
 
-    pub fn from_string(data: &str) -> Self {
-        let mut de = serde_xml_rs::Deserializer::new_from_reader(data.as_bytes())
-            .non_contiguous_seq_elements(true);
-        OverlayData::deserialize(&mut de).unwrap()
+    pub fn from_string(data: &str) -> Parsed<Self> {
+        Self::parse(data.as_bytes(), Path::new("<string>"))
     }
 
-    pub fn fill_poi_parents(&mut self) {
+    /// The element tree [`Self::parse`] reads back into the same data
+    pub fn to_element(&self) -> Result<XmlElement, value::Error> {
+        let mut root = XmlElement {
+            name: "OverlayData".to_string(),
+            ..Default::default()
+        };
+        for category in &self.marker_category {
+            root.children
+                .push(write_category(&category.read().unwrap())?);
+        }
+        root.children.push(self.pois.to_element()?);
+        Ok(root)
+    }
+
+    /// Writes a marker file. The points of the trails go to their own files, see
+    /// [`Trail::get_trail_file`]
+    pub fn write(&self, writer: impl Write) -> Result<(), Box<dyn Error>> {
+        self.to_element()?.write(writer)?;
+        Ok(())
+    }
+
+    fn read_root(&mut self, root: &XmlElement, diagnostics: &mut Diagnostics) {
+        for (path, element) in root.get_children("OverlayData") {
+            match element.name.as_str() {
+                "MarkerCategory" => {
+                    if let Some(category) = read_category(element, &path, diagnostics) {
+                        self.add_category(Arc::new(RwLock::new(category)));
+                    }
+                }
+                "POIs" => self.pois.read(element, &path, diagnostics),
+                _ => (),
+            }
+        }
+    }
+
+    /// Marks every POI, trail and category as loaded from `pack`
+    pub fn set_pack(&self, pack: u32) {
+        fn set_category_pack(category: &MarkerCategoryContainer, pack: u32) {
+            let mut category = category.write().unwrap();
+            category.data.set_pack(Some(pack));
+            category
+                .children
+                .values()
+                .for_each(|child| set_category_pack(child, pack));
+        }
+        self.marker_category
+            .iter()
+            .for_each(|category| set_category_pack(category, pack));
+        self.pois
+            .poi_list
+            .iter()
+            .for_each(|poi| poi.write().unwrap().set_pack(Some(pack)));
+        self.pois
+            .trail_list
+            .iter()
+            .for_each(|trail| trail.write().unwrap().poi.set_pack(Some(pack)));
+    }
+
+    /// Reads the points of every trail from the pack the trails were loaded from. A broken
+    /// trail is logged and stays without points
+    pub fn load_trails(&self, files: &PackFiles) {
         self.pois.trail_list.iter().for_each(|trail_lock| {
             let mut trail = trail_lock.write().unwrap();
             info!("Filling trail {:?}", trail.texture);
-            trail.load_map_trail().unwrap();
+            if let Err(e) = trail.load_map_trail(files) {
+                error!(
+                    "Failed to load trail data from {}: {}",
+                    files.get_root().display(),
+                    e
+                );
+            }
         });
+    }
+
+    pub fn fill_poi_parents(&mut self) {
         self.pois.poi_list.iter_mut().for_each(|poi| {
             self.marker_category.iter().for_each(|category| {
                 let category_name = poi.read().unwrap().poi_type.clone();
@@ -83,25 +220,204 @@ impl OverlayData {
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct POIs {
-    #[serde(rename = "POI", deserialize_with = "deserialize_poi_vec", default)]
     pub poi_list: Vec<PoiContainer>,
-    #[serde(rename = "Trail", deserialize_with = "deserialize_trail_vec", default)]
     pub trail_list: Vec<TrailContainer>,
 }
 
+impl POIs {
+    fn read(&mut self, element: &XmlElement, path: &str, diagnostics: &mut Diagnostics) {
+        for (path, element) in element.get_children(path) {
+            match element.name.as_str() {
+                "POI" => {
+                    if let Some(poi) = element.deserialize::<POI>(&path, diagnostics) {
+                        self.poi_list.push(Arc::new(RwLock::new(poi)));
+                    }
+                }
+                "Trail" => match element.deserialize::<Trail>(&path, diagnostics) {
+                    Some(trail) if trail.trail_file.as_os_str().is_empty() => {
+                        diagnostics.record(element.line, element.column, &path, "missing trailData")
+                    }
+                    Some(trail) => self.trail_list.push(Arc::new(RwLock::new(trail))),
+                    None => (),
+                },
+                _ => (),
+            }
+        }
+    }
+
+    fn to_element(&self) -> Result<XmlElement, value::Error> {
+        let mut element = XmlElement {
+            name: "POIs".to_string(),
+            ..Default::default()
+        };
+        for poi in &self.poi_list {
+            element
+                .children
+                .push(XmlElement::from_value("POI", &*poi.read().unwrap())?);
+        }
+        for trail in &self.trail_list {
+            let trail = trail.read().unwrap();
+            let mut trail_element = XmlElement::from_value("Trail", &*trail)?;
+            remove_position(&mut trail_element);
+            // Loading copies the map of the `.trl` file to the trail, the file keeps it
+            if trail.has_trail_file() {
+                trail_element.attributes.retain(|(name, _)| name != "MapID");
+            }
+            element.children.push(trail_element);
+        }
+        Ok(element)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::sync::{Arc, RwLock};
+    use std::{
+        collections::BTreeMap,
+        fs,
+        path::Path,
+        sync::{Arc, RwLock},
+    };
 
+    use gw2_link::{Mount, Profession, Race};
+    use proptest::prelude::*;
     use walkdir::WalkDir;
 
     use crate::{
-        gw2poi::{MarkerCategory, PoiTrait, POI},
+        diagnostics::Diagnostics,
+        gw2poi::{Cull, MarkerCategory, PoiBehavior, PoiTrait, POI},
         overlay_data::OverlayData,
+        pack_files::PackFiles,
+        trail::TrailFile,
+        xml_element::XmlElement,
     };
 
+    type Attributes = Vec<(String, String)>;
+
+    fn number() -> impl Strategy<Value = String> {
+        (-1e5f32..1e5f32).prop_map(|value| value.to_string())
+    }
+
+    fn list<T: Strategy>(item: T) -> impl Strategy<Value = String>
+    where
+        T::Value: ToString,
+    {
+        proptest::collection::vec(item, 1..4).prop_map(|items| {
+            let items: Vec<String> = items.iter().map(ToString::to_string).collect();
+            items.join(",")
+        })
+    }
+
+    /// Attributes of POIs, trails and categories, with the text the writer produces for them
+    fn attributes() -> impl Strategy<Value = Attributes> {
+        let attribute = prop_oneof![
+            any::<u32>().prop_map(|value| ("MapID", value.to_string())),
+            r"[a-zA-Z0-9_\\/.]{1,16}".prop_map(|value| ("iconFile", value)),
+            "[ -~äöü]{0,12}".prop_map(|value| ("DisplayName", value)),
+            number().prop_map(|value| ("iconSize", value)),
+            number().prop_map(|value| ("fadeFar", value)),
+            any::<i32>().prop_map(|value| ("achievementBit", value.to_string())),
+            any::<bool>().prop_map(|value| ("autoTrigger", (value as u8).to_string())),
+            any::<bool>().prop_map(|value| ("mapVisibility", (value as u8).to_string())),
+            prop::sample::select(vec!["0", "1", "4", "7", "23732"])
+                .prop_map(|value| ("behavior", value.to_string())),
+            prop::sample::select(vec!["None", "Clockwise", "CounterClockwise"])
+                .prop_map(|value| ("cull", value.to_string())),
+            list(prop::sample::select(Profession::NAMES).prop_map(|(_, name)| name))
+                .prop_map(|value| ("profession", value)),
+            list(any::<u32>()).prop_map(|value| ("specialization", value)),
+            "[ -~]{0,12}".prop_map(|value| ("copy-message", value)),
+        ];
+        // A later value of an attribute replaces the earlier one
+        proptest::collection::vec(attribute, 0..6).prop_map(|attributes| {
+            let attributes: BTreeMap<String, String> = attributes
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect();
+            attributes.into_iter().collect()
+        })
+    }
+
+    fn element(name: &str, attributes: Attributes, children: Vec<XmlElement>) -> XmlElement {
+        XmlElement {
+            name: name.to_string(),
+            attributes,
+            children,
+            ..Default::default()
+        }
+    }
+
+    fn category() -> impl Strategy<Value = (Attributes, Vec<Attributes>)> {
+        (attributes(), proptest::collection::vec(attributes(), 0..3))
+    }
+
+    fn marker_file() -> impl Strategy<Value = XmlElement> {
+        let poi = (
+            attributes(),
+            "[a-z]{1,8}(\\.[a-z]{1,8})?",
+            proptest::array::uniform3(number()),
+        )
+            .prop_map(|(mut attributes, poi_type, [x, y, z])| {
+                attributes.push(("type".to_string(), poi_type));
+                attributes.push(("xpos".to_string(), x));
+                attributes.push(("ypos".to_string(), y));
+                attributes.push(("zpos".to_string(), z));
+                element("POI", attributes, vec![])
+            });
+        let trail = (attributes(), "[a-z]{1,8}\\.trl", number()).prop_map(
+            |(mut attributes, file, speed)| {
+                attributes.push(("trailData".to_string(), file));
+                attributes.push(("animSpeed".to_string(), speed));
+                element("Trail", attributes, vec![])
+            },
+        );
+        (
+            proptest::collection::vec(category(), 0..3),
+            proptest::collection::vec(poi, 0..4),
+            proptest::collection::vec(trail, 0..3),
+        )
+            .prop_map(|(categories, pois, trails)| {
+                let mut children: Vec<XmlElement> = categories
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (mut attributes, children))| {
+                        attributes.push(("name".to_string(), format!("category{}", i)));
+                        let children = children
+                            .into_iter()
+                            .enumerate()
+                            .map(|(j, mut attributes)| {
+                                attributes.push(("name".to_string(), format!("child{}", j)));
+                                element("MarkerCategory", attributes, vec![])
+                            })
+                            .collect();
+                        element("MarkerCategory", attributes, children)
+                    })
+                    .collect();
+                children.push(element(
+                    "POIs",
+                    vec![],
+                    pois.into_iter().chain(trails).collect(),
+                ));
+                element("OverlayData", vec![], children)
+            })
+    }
+
+    /// Without positions and with sorted attributes, to compare what was written
+    fn normalize(mut element: XmlElement) -> XmlElement {
+        element.line = 0;
+        element.column = 0;
+        element.attributes.sort();
+        element.children = element.children.into_iter().map(normalize).collect();
+        element
+    }
+
+    fn write(data: &OverlayData) -> Vec<u8> {
+        let mut written = vec![];
+        data.write(&mut written).unwrap();
+        written
+    }
+
     #[test]
     fn xml_file_test() {
         let mut overlay_data: OverlayData = OverlayData::default();
@@ -109,9 +425,8 @@ mod tests {
             if entry.file_type().is_file() && entry.path().extension().unwrap_or_default() == "xml"
             {
                 println!("Found XML file: {:?}", entry.path());
-                let file_path = entry.path().to_string_lossy().to_string();
-                let data = OverlayData::from_file(&file_path);
-                overlay_data.merge(data.unwrap());
+                let data = OverlayData::parse(fs::File::open(entry.path()).unwrap(), entry.path());
+                overlay_data.merge(data.strict().unwrap());
             }
         }
 
@@ -141,7 +456,7 @@ mod tests {
             </OverlayData>
             "#;
 
-        let mut overlay_data: OverlayData = OverlayData::from_string(xml_string);
+        let mut overlay_data: OverlayData = OverlayData::from_string(xml_string).strict().unwrap();
         overlay_data.fill_poi_parents();
 
         let parent_opt = overlay_data.pois.poi_list[0].read().unwrap().get_parent();
@@ -164,6 +479,199 @@ mod tests {
         assert_eq!(overlay_data.pois.trail_list.len(), 1);
     }
 
+    #[test]
+    fn attributes_test() {
+        let mut overlay_data = OverlayData::from_string(
+            r#"<OverlayData>
+            <MarkerCategory name="chests" DisplayName="Chests" behavior="4" resetLength="300"
+                resetOffset="60" triggerRange="8" autoTrigger="1" hasCountdown="true"
+                achievementId="1234" achievementBit="5" info="Open me" infoRange="12"
+                isPOI="0" minSize="10" maxSize="100" mapDisplaySize="30" mapVisibility="false"
+                miniMapVisibility="0" inGameVisibility="1" scaleOnMapWithZoom="true"
+                mapFadeoutScaleLevel="2.5" keepOnMapEdge="1" invertBehavior="0"
+                festival="halloween, wintersday" profession="guardian,warrior"
+                specialization="27,62" race="asura" mount="raptor,springer" copy="/wp"
+                copy-message="Copied" bounce="bounce" bounce-height="3" bounce-duration="1.5"
+                bounce-delay="0.5" toggleCategory="chests.opened" cull="CounterClockwise"
+                color="FF0000"/>
+            <POIs>
+            <POI MapID="15" xpos="1" ypos="2" zpos="3" type="chests" GUID="abc=="
+                behavior="6" triggerRange="2" profession="thief"/>
+            </POIs>
+            </OverlayData>"#,
+        )
+        .strict()
+        .unwrap();
+        overlay_data.fill_poi_parents();
+        let poi = overlay_data.pois.poi_list[0].read().unwrap();
+
+        // Set on the POI
+        assert_eq!(poi.get_guid().unwrap(), "abc==");
+        assert_eq!(poi.get_behavior(), Some(PoiBehavior::OncePerInstance));
+        assert_eq!(poi.get_trigger_range(), Some(2.0));
+        assert_eq!(poi.get_profession().unwrap(), vec![Profession::Thief]);
+
+        // Inherited from the category
+        assert_eq!(poi.get_display_name().unwrap(), "Chests");
+        assert_eq!(poi.get_reset_length(), Some(300.0));
+        assert_eq!(poi.get_reset_offset(), Some(60.0));
+        assert_eq!(poi.get_auto_trigger(), Some(true));
+        assert_eq!(poi.get_has_countdown(), Some(true));
+        assert_eq!(poi.get_achievement_id(), Some(1234));
+        assert_eq!(poi.get_achievement_bit(), Some(5));
+        assert_eq!(poi.get_info().unwrap(), "Open me");
+        assert_eq!(poi.get_info_range(), Some(12.0));
+        assert_eq!(poi.get_is_poi(), Some(false));
+        assert_eq!(poi.get_min_size(), Some(10.0));
+        assert_eq!(poi.get_max_size(), Some(100.0));
+        assert_eq!(poi.get_map_display_size(), Some(30.0));
+        assert_eq!(poi.get_map_visibility(), Some(false));
+        assert_eq!(poi.get_mini_map_visibility(), Some(false));
+        assert_eq!(poi.get_in_game_visibility(), Some(true));
+        assert_eq!(poi.get_scale_on_map_with_zoom(), Some(true));
+        assert_eq!(poi.get_map_fade_out_scale_level(), Some(2.5));
+        assert_eq!(poi.get_keep_on_map_edge(), Some(true));
+        assert_eq!(poi.get_invert_behavior(), Some(false));
+        assert_eq!(poi.get_festival().unwrap(), vec!["halloween", "wintersday"]);
+        assert_eq!(poi.get_specialization().unwrap(), vec![27, 62]);
+        assert_eq!(poi.get_race().unwrap(), vec![Race::Asura]);
+        assert_eq!(
+            poi.get_mount().unwrap(),
+            vec![Mount::Raptor, Mount::Springer]
+        );
+        assert_eq!(poi.get_copy().unwrap(), "/wp");
+        assert_eq!(poi.get_copy_message().unwrap(), "Copied");
+        assert_eq!(poi.get_bounce().unwrap(), "bounce");
+        assert_eq!(poi.get_bounce_height(), Some(3.0));
+        assert_eq!(poi.get_bounce_duration(), Some(1.5));
+        assert_eq!(poi.get_bounce_delay(), Some(0.5));
+        assert_eq!(poi.get_toggle_category().unwrap(), "chests.opened");
+        assert_eq!(poi.get_cull(), Some(Cull::CounterClockwise));
+        assert_eq!(poi.get_color().unwrap(), "FF0000");
+
+        // Unset everywhere
+        assert_eq!(poi.get_icon_size(), None);
+    }
+
+    #[test]
+    fn diagnostics_test() {
+        let xml = r#"<OverlayData>
+<MarkerCategory name="chests" iconSize="big">
+<MarkerCategory name="gold"/>
+</MarkerCategory>
+<MarkerCategory DisplayName="No name"/>
+<MarkerCategory name="jumping" behavior="3"/>
+<POIs>
+<POI MapID="15" xpos="1" ypos="2" zpos="3"/>
+<POI MapID="15" xpos="one" ypos="2" zpos="3" autoTrigger="maybe"/>
+<Trail type="jumping" texture="a.png"/>
+<POI MapID="15" xpos="4" ypos="5" zpos="6"/>
+</POIs>
+</OverlayData>"#;
+        let parsed = OverlayData::parse(xml.as_bytes(), Path::new("pack/markers.xml"));
+        let problems: Vec<_> = parsed
+            .diagnostics
+            .iter()
+            .map(|d| (d.line, d.column, d.element.as_str()))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (2, 1, "OverlayData/MarkerCategory[1]/@iconSize"),
+                (5, 1, "OverlayData/MarkerCategory[2]"),
+                (9, 1, "OverlayData/POIs[1]/POI[2]/@xpos"),
+                (9, 1, "OverlayData/POIs[1]/POI[2]/@autoTrigger"),
+                (10, 1, "OverlayData/POIs[1]/Trail[1]"),
+            ]
+        );
+        assert_eq!(
+            parsed.diagnostics[0].to_string(),
+            r#"pack/markers.xml:2:1: OverlayData/MarkerCategory[1]/@iconSize: "big": invalid float literal"#
+        );
+
+        // Only the broken elements are skipped
+        let data = OverlayData::parse(xml.as_bytes(), Path::new("markers.xml")).lenient();
+        assert_eq!(data.marker_category.len(), 1);
+        assert_eq!(data.marker_category[0].read().unwrap().name, "jumping");
+        assert_eq!(data.pois.poi_list.len(), 2);
+        assert_eq!(data.pois.poi_list[1].read().unwrap().pos.xpos, 4.0);
+        assert!(data.pois.trail_list.is_empty());
+
+        let errors = OverlayData::parse(xml.as_bytes(), Path::new("markers.xml"))
+            .strict()
+            .unwrap_err();
+        assert_eq!(errors.len(), 5);
+        assert!(OverlayData::from_string("<OverlayData><POIs>")
+            .strict()
+            .is_err());
+        assert!(OverlayData::from_string("<Markers/>").strict().is_err());
+    }
+
+    #[test]
+    fn unknown_names_test() {
+        let xml = r#"<OverlayData><POIs>
+<POI MapID="15" xpos="1" ypos="2" zpos="3" profession="guardian,theif"/>
+<POI MapID="15" xpos="1" ypos="2" zpos="3" race="Charr" mount="griffin"/>
+</POIs></OverlayData>"#;
+        let errors = OverlayData::parse(xml.as_bytes(), Path::new("markers.xml"))
+            .strict()
+            .unwrap_err();
+        let messages: Vec<_> = errors
+            .iter()
+            .map(|d| (d.element.as_str(), d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    "OverlayData/POIs[1]/POI[1]/@profession",
+                    r#""guardian,theif": unknown profession "theif""#
+                ),
+                (
+                    "OverlayData/POIs[1]/POI[2]/@mount",
+                    r#""griffin": unknown mount "griffin""#
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_test() {
+        let mut overlay_data = OverlayData::from_string(
+            r#"<OverlayData>
+            <MarkerCategory name="chests" iconFile="chest.png">
+            <MarkerCategory name="silver"/>
+            </MarkerCategory>
+            </OverlayData>"#,
+        )
+        .strict()
+        .unwrap();
+        overlay_data.merge(
+            OverlayData::from_string(
+                r#"<OverlayData>
+            <MarkerCategory name="chests">
+            <MarkerCategory name="gold"/>
+            </MarkerCategory>
+            <MarkerCategory name="jumping"/>
+            <POIs><POI MapID="15" xpos="1" ypos="2" zpos="3" type="chests.gold"/></POIs>
+            </OverlayData>"#,
+            )
+            .strict()
+            .unwrap(),
+        );
+        overlay_data.fill_poi_parents();
+
+        assert_eq!(overlay_data.marker_category.len(), 2);
+        let chests = overlay_data.marker_category[0].read().unwrap();
+        assert_eq!(chests.children.len(), 2);
+        assert_eq!(
+            chests.data.get_icon_file().unwrap().to_str().unwrap(),
+            "chest.png"
+        );
+        let parent = overlay_data.pois.poi_list[0].read().unwrap().get_parent();
+        assert_eq!(parent.unwrap().read().unwrap().name, "gold");
+    }
+
     #[test]
     fn fill_test() {
         let mut overlay_data = OverlayData {
@@ -237,4 +745,93 @@ mod tests {
         poi.set_display_name(Some("child_name".into()));
         assert_eq!(poi.get_display_name().unwrap(), "child_name");
     }
+
+    #[test]
+    fn write_test() {
+        let overlay_data = OverlayData::from_string(
+            r#"<OverlayData>
+            <MarkerCategory name="chests" behavior="4" isPOI="1">
+            <MarkerCategory name="silver"/>
+            <MarkerCategory name="gold" profession="guardian, thief"/>
+            </MarkerCategory>
+            <POIs>
+            <Trail trailData="chests.trl" type="chests"/>
+            <POI MapID="15" xpos="1" ypos="2.5" zpos="-3" type="chests.gold"/>
+            </POIs>
+            </OverlayData>"#,
+        )
+        .strict()
+        .unwrap();
+        let written = String::from_utf8(write(&overlay_data)).unwrap();
+        assert_eq!(
+            written,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<OverlayData>
+  <MarkerCategory name="chests" behavior="4" isPOI="1">
+    <MarkerCategory name="gold" profession="guardian,thief" />
+    <MarkerCategory name="silver" />
+  </MarkerCategory>
+  <POIs>
+    <POI type="chests.gold" xpos="1" ypos="2.5" zpos="-3" MapID="15" />
+    <Trail trailData="chests.trl" type="chests" />
+  </POIs>
+</OverlayData>"#
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn write_round_trip(file in marker_file()) {
+            let mut text = vec![];
+            file.write(&mut text).unwrap();
+            let data = OverlayData::parse(text.as_slice(), Path::new("generated.xml"))
+                .strict()
+                .unwrap();
+            let written = write(&data);
+
+            // Every attribute is written with the value it was read with
+            let mut diagnostics = Diagnostics::new(Path::new("written.xml"));
+            let element = XmlElement::read(written.as_slice(), &mut diagnostics).unwrap();
+            prop_assert_eq!(normalize(element), normalize(file));
+
+            let data = OverlayData::parse(written.as_slice(), Path::new("written.xml"))
+                .strict()
+                .unwrap();
+            prop_assert_eq!(String::from_utf8(write(&data)), String::from_utf8(written));
+        }
+    }
+
+    #[test]
+    fn load_trails_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = TrailFile {
+            version: 0,
+            map_id: 15,
+            points: vec![],
+            trailing_bytes: 0,
+        };
+        fs::write(dir.path().join("good.trl"), file.to_bytes()).unwrap();
+        let files = PackFiles::open_directory(dir.path()).unwrap();
+        let overlay_data = OverlayData::from_string(
+            r#"<OverlayData><POIs>
+            <Trail trailData="missing.trl"/>
+            <Trail trailData="good.trl"/>
+            </POIs></OverlayData>"#,
+        )
+        .strict()
+        .unwrap();
+
+        // The missing file doesn't stop the other trails
+        overlay_data.load_trails(&files);
+        let trails = &overlay_data.pois.trail_list;
+        assert_eq!(trails[0].read().unwrap().poi.get_map_id(), None);
+        assert_eq!(trails[1].read().unwrap().poi.get_map_id(), Some(15));
+
+        let error = trails[0]
+            .write()
+            .unwrap()
+            .load_map_trail(&files)
+            .unwrap_err();
+        assert!(error.to_string().starts_with("missing.trl: "));
+    }
 }
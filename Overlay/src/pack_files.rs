@@ -0,0 +1,432 @@
+//! Files of a marker pack, either a directory or a `.taco`/`.zip` archive.
+//!
+//! Packs are made on Windows, so their paths use backslashes and rarely match the case of
+//! the files. Every lookup goes through [`normalize_path`].
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex, RwLock,
+    },
+};
+
+use bevy::{
+    asset::{AssetIo, AssetIoError, ChangeWatcher, FileType, Metadata},
+    prelude::{App, AssetPlugin, AssetServer, Plugin, Resource},
+    utils::BoxedFuture,
+};
+use walkdir::WalkDir;
+use zip::ZipArchive;
+
+/// Asset paths below this prefix are served from a pack, e.g. `pack:3/Data/icon.png`
+pub const PACK_ASSET_PREFIX: &str = "pack:";
+
+/// Lookup key of a path inside of a pack
+pub fn normalize_path(path: &str) -> String {
+    path.replace('\\', "/")
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+        .to_lowercase()
+}
+
+/// A path inside of a pack as a relative path of this system. `None` if it is empty or
+/// leaves the pack
+pub fn to_relative_path(path: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => (),
+            ".." => return None,
+            part => relative.push(part),
+        }
+    }
+    (!relative.as_os_str().is_empty()).then_some(relative)
+}
+
+/// Whether `path` is a zipped pack
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("taco") || ext.eq_ignore_ascii_case("zip"))
+}
+
+fn not_found(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} is not in the pack", path),
+    )
+}
+
+pub enum PackFiles {
+    Directory {
+        root: PathBuf,
+        /// Normalized path to the path relative to `root`
+        index: HashMap<String, PathBuf>,
+    },
+    Archive {
+        path: PathBuf,
+        archive: Mutex<ZipArchive<fs::File>>,
+        /// Normalized path to the index of the entry
+        index: HashMap<String, usize>,
+    },
+}
+
+impl PackFiles {
+    /// Every file below `root`, except for the archives, which are packs of their own
+    pub fn open_directory(root: &Path) -> io::Result<Self> {
+        Self::index_directory(root, usize::MAX)
+    }
+
+    /// Only the files directly in `root`. Its subdirectories are packs of their own
+    pub fn open_loose_files(root: &Path) -> io::Result<Self> {
+        Self::index_directory(root, 1)
+    }
+
+    fn index_directory(root: &Path, max_depth: usize) -> io::Result<Self> {
+        fs::metadata(root)?;
+        let mut index = HashMap::new();
+        let entries = WalkDir::new(root).max_depth(max_depth).into_iter();
+        for entry in entries.filter_map(|e| e.ok()) {
+            if !entry.file_type().is_file() || is_archive(entry.path()) {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(root).unwrap().to_path_buf();
+            index.insert(normalize_path(&relative.to_string_lossy()), relative);
+        }
+        Ok(Self::Directory {
+            root: root.to_path_buf(),
+            index,
+        })
+    }
+
+    pub fn open_archive(path: &Path) -> io::Result<Self> {
+        let mut archive = ZipArchive::new(fs::File::open(path)?)?;
+        let mut index = HashMap::new();
+        for i in 0..archive.len() {
+            let file = archive.by_index_raw(i)?;
+            if file.is_file() {
+                index.insert(normalize_path(file.name()), i);
+            }
+        }
+        Ok(Self::Archive {
+            path: path.to_path_buf(),
+            archive: Mutex::new(archive),
+            index,
+        })
+    }
+
+    /// The directory or the archive file
+    pub fn get_root(&self) -> &Path {
+        match self {
+            Self::Directory { root, .. } => root,
+            Self::Archive { path, .. } => path,
+        }
+    }
+
+    /// Paths of every file, as stored in the pack
+    pub fn list(&self) -> Vec<String> {
+        let mut files: Vec<String> = match self {
+            Self::Directory { index, .. } => index
+                .values()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect(),
+            Self::Archive { archive, .. } => archive
+                .lock()
+                .unwrap()
+                .file_names()
+                .filter(|name| !name.ends_with('/'))
+                .map(String::from)
+                .collect(),
+        };
+        files.sort();
+        files
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        let key = normalize_path(path);
+        match self {
+            Self::Directory { index, .. } => index.contains_key(&key),
+            Self::Archive { index, .. } => index.contains_key(&key),
+        }
+    }
+
+    /// Reads `path`, which may use backslashes and any case
+    pub fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let key = normalize_path(path);
+        match self {
+            Self::Directory { root, index } => {
+                fs::read(root.join(index.get(&key).ok_or_else(|| not_found(path))?))
+            }
+            Self::Archive { archive, index, .. } => {
+                let i = *index.get(&key).ok_or_else(|| not_found(path))?;
+                let mut archive = archive.lock().unwrap();
+                let mut file = archive.by_index(i)?;
+                let mut data = Vec::with_capacity(file.size() as usize);
+                file.read_to_end(&mut data)?;
+                Ok(data)
+            }
+        }
+    }
+}
+
+/// The loaded packs by id. Shared with the asset server to load textures from the packs
+#[derive(Resource, Clone, Default)]
+pub struct PackRegistry {
+    packs: Arc<RwLock<HashMap<u32, Arc<PackFiles>>>>,
+    next_id: Arc<AtomicU32>,
+}
+
+impl PackRegistry {
+    /// Ids are never reused, so the asset server doesn't hand out textures of a removed pack
+    pub fn insert(&self, files: Arc<PackFiles>) -> u32 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.packs.write().unwrap().insert(id, files);
+        id
+    }
+
+    pub fn get(&self, id: u32) -> Option<Arc<PackFiles>> {
+        self.packs.read().unwrap().get(&id).cloned()
+    }
+
+    pub fn clear(&self) {
+        self.packs.write().unwrap().clear();
+    }
+}
+
+/// Asset path of `path` inside of pack `pack`. Without a pack it's relative to the assets
+pub fn get_asset_path(pack: Option<u32>, path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    match pack {
+        Some(pack) => format!("{}{}/{}", PACK_ASSET_PREFIX, pack, path),
+        None => path,
+    }
+}
+
+fn split_asset_path(path: &Path) -> Option<(u32, &str)> {
+    let (pack, path) = path
+        .to_str()?
+        .strip_prefix(PACK_ASSET_PREFIX)?
+        .split_once('/')?;
+    Some((pack.parse().ok()?, path))
+}
+
+/// Serves the files of the packs and passes every other path on to the default asset io
+pub struct PackAssetIo {
+    default_io: Box<dyn AssetIo>,
+    packs: PackRegistry,
+}
+
+impl PackAssetIo {
+    pub fn new(default_io: Box<dyn AssetIo>, packs: PackRegistry) -> Self {
+        Self { default_io, packs }
+    }
+
+    fn get_pack(&self, asset_path: &Path, pack: u32) -> Result<Arc<PackFiles>, AssetIoError> {
+        self.packs
+            .get(pack)
+            .ok_or_else(|| AssetIoError::NotFound(asset_path.to_path_buf()))
+    }
+}
+
+impl AssetIo for PackAssetIo {
+    fn load_path<'a>(&'a self, path: &'a Path) -> BoxedFuture<'a, Result<Vec<u8>, AssetIoError>> {
+        let Some((pack, file)) = split_asset_path(path) else {
+            return self.default_io.load_path(path);
+        };
+        Box::pin(async move {
+            self.get_pack(path, pack)?
+                .read(file)
+                .map_err(|e| match e.kind() {
+                    io::ErrorKind::NotFound => AssetIoError::NotFound(path.to_path_buf()),
+                    _ => e.into(),
+                })
+        })
+    }
+
+    fn read_directory(
+        &self,
+        path: &Path,
+    ) -> Result<Box<dyn Iterator<Item = PathBuf>>, AssetIoError> {
+        match split_asset_path(path) {
+            Some(_) => Err(AssetIoError::NotFound(path.to_path_buf())),
+            None => self.default_io.read_directory(path),
+        }
+    }
+
+    fn get_metadata(&self, path: &Path) -> Result<Metadata, AssetIoError> {
+        match split_asset_path(path) {
+            Some((pack, file)) if self.get_pack(path, pack)?.contains(file) => {
+                Ok(Metadata::new(FileType::File))
+            }
+            Some(_) => Err(AssetIoError::NotFound(path.to_path_buf())),
+            None => self.default_io.get_metadata(path),
+        }
+    }
+
+    fn watch_path_for_changes(
+        &self,
+        to_watch: &Path,
+        to_reload: Option<PathBuf>,
+    ) -> Result<(), AssetIoError> {
+        // Packs are reloaded as a whole by the marker config
+        match split_asset_path(to_watch) {
+            Some(_) => Ok(()),
+            None => self.default_io.watch_path_for_changes(to_watch, to_reload),
+        }
+    }
+
+    fn watch_for_changes(&self, configuration: &ChangeWatcher) -> Result<(), AssetIoError> {
+        self.default_io.watch_for_changes(configuration)
+    }
+}
+
+/// Has to be added before the [`AssetPlugin`], which only creates an asset server if there
+/// is none yet
+pub struct PackAssetPlugin {
+    pub packs: PackRegistry,
+}
+
+impl Plugin for PackAssetPlugin {
+    fn build(&self, app: &mut App) {
+        let default_io = AssetPlugin::default().create_platform_default_asset_io();
+        app.insert_resource(AssetServer::new(PackAssetIo::new(
+            default_io,
+            self.packs.clone(),
+        )))
+        .insert_resource(self.packs.clone());
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        io::Write,
+        path::{Path, PathBuf},
+        sync::Arc,
+        task::{Context, Poll, Waker},
+    };
+
+    use bevy::asset::{AssetIo, AssetIoError};
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::{
+        get_asset_path, normalize_path, to_relative_path, PackAssetIo, PackFiles, PackRegistry,
+        PACK_ASSET_PREFIX,
+    };
+
+    /// Writes a pack like the ones made on Windows
+    pub(crate) fn write_archive(path: &Path, files: &[(&str, &[u8])]) {
+        let mut zip = ZipWriter::new(std::fs::File::create(path).unwrap());
+        for (name, data) in files {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    /// Both asset ios read synchronously, so the future is ready right away
+    fn load(io: &PackAssetIo, path: &str) -> Result<Vec<u8>, AssetIoError> {
+        let mut context = Context::from_waker(Waker::noop());
+        match io.load_path(Path::new(path)).as_mut().poll(&mut context) {
+            Poll::Ready(result) => result,
+            Poll::Pending => panic!("{} is loaded asynchronously", path),
+        }
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path(r"Data\Icons\Chest.PNG"),
+            "data/icons/chest.png"
+        );
+        assert_eq!(
+            normalize_path("./data//icons/chest.png"),
+            "data/icons/chest.png"
+        );
+        assert_eq!(normalize_path("/chest.png"), "chest.png");
+    }
+
+    #[test]
+    fn test_to_relative_path() {
+        assert_eq!(
+            to_relative_path(r"Data\Trails/./Jump.trl"),
+            Some(PathBuf::from("Data/Trails/Jump.trl"))
+        );
+        assert_eq!(
+            to_relative_path("/jump.trl"),
+            Some(PathBuf::from("jump.trl"))
+        );
+        assert_eq!(to_relative_path(r"..\jump.trl"), None);
+        assert_eq!(to_relative_path(""), None);
+    }
+
+    #[test]
+    fn test_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pack.taco");
+        write_archive(
+            &path,
+            &[
+                ("Markers.xml", b"<OverlayData/>"),
+                ("Data/Chest.png", b"png"),
+            ],
+        );
+        let files = PackFiles::open_archive(&path).unwrap();
+        assert_eq!(files.list(), vec!["Data/Chest.png", "Markers.xml"]);
+        assert_eq!(files.read(r"data\chest.PNG").unwrap(), b"png");
+        assert!(files.contains("markers.xml"));
+        assert_eq!(
+            files.read("Data/Missing.png").unwrap_err().kind(),
+            std::io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("Data")).unwrap();
+        std::fs::write(dir.path().join("Data/Trail.trl"), b"trl").unwrap();
+        write_archive(&dir.path().join("other.zip"), &[]);
+        let files = PackFiles::open_directory(dir.path()).unwrap();
+        assert_eq!(files.list(), vec!["Data/Trail.trl"]);
+        assert_eq!(files.read(r"DATA\trail.trl").unwrap(), b"trl");
+        assert!(PackFiles::open_directory(&dir.path().join("missing")).is_err());
+
+        std::fs::write(dir.path().join("Markers.xml"), b"xml").unwrap();
+        let files = PackFiles::open_loose_files(dir.path()).unwrap();
+        assert_eq!(files.list(), vec!["Markers.xml"]);
+    }
+
+    #[test]
+    fn test_asset_io() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pack.zip");
+        write_archive(&path, &[("Data/Chest.png", b"png")]);
+        std::fs::write(dir.path().join("font.ttf"), b"ttf").unwrap();
+
+        let packs = PackRegistry::default();
+        let id = packs.insert(Arc::new(PackFiles::open_archive(&path).unwrap()));
+        let io = PackAssetIo::new(
+            Box::new(bevy::asset::FileAssetIo::new(dir.path(), &None)),
+            packs.clone(),
+        );
+        let icon = get_asset_path(Some(id), Path::new(r"data\chest.png"));
+        assert_eq!(icon, format!("{}{}/data/chest.png", PACK_ASSET_PREFIX, id));
+        assert_eq!(load(&io, &icon).unwrap(), b"png");
+        assert!(io.get_metadata(Path::new(&icon)).unwrap().is_file());
+        assert_eq!(load(&io, "font.ttf").unwrap(), b"ttf");
+
+        let missing = get_asset_path(Some(id), Path::new("missing.png"));
+        assert!(matches!(
+            load(&io, &missing),
+            Err(AssetIoError::NotFound(_))
+        ));
+        packs.clear();
+        assert!(matches!(load(&io, &icon), Err(AssetIoError::NotFound(_))));
+    }
+}
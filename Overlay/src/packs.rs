@@ -0,0 +1,641 @@
+//! Loads and checks the marker packs below a directory. Every subdirectory and every `.taco`
+//! or `.zip` archive is a pack of its own, the loose files next to them form one more pack.
+//! A directory with a manifest is a single pack.
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bevy::prelude::{error, info};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::{
+    overlay_data::OverlayData,
+    pack_files::{is_archive, to_relative_path, PackFiles, PackRegistry, PACK_ASSET_PREFIX},
+    trail::TrailFile,
+};
+
+/// A file of a marker pack that can't be loaded
+#[derive(Debug)]
+pub struct PackError {
+    pub file: PathBuf,
+    pub message: String,
+}
+
+/// Markers of one map
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MapMarkers {
+    pub pois: usize,
+    pub trails: usize,
+}
+
+/// Optional file in the root of a pack that describes it
+pub const MANIFEST_FILE: &str = "manifest.toml";
+/// The file [`MarkerPack::write_to`] writes the markers to
+pub const MARKER_FILE: &str = "markers.xml";
+
+/// ```toml
+/// name = "Tekkit's All-In-One"
+/// version = "2.5.1"
+/// author = "Tekkit"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PackManifest {
+    /// Used to disable the pack in the config
+    pub name: String,
+    pub version: Option<String>,
+    pub author: Option<String>,
+}
+
+/// The markers of one directory or archive
+pub struct MarkerPack {
+    /// Id of the files of the pack in the [`PackRegistry`]
+    pub id: u32,
+    /// The directory or the archive
+    pub root: PathBuf,
+    pub manifest: PackManifest,
+    pub data: OverlayData,
+    enabled: bool,
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+/// The loose files in `path`, every subdirectory and every archive, or the archive `path`
+/// itself. Also returns the packs that can't be opened
+pub fn open_packs(path: &Path) -> std::io::Result<(Vec<PackFiles>, Vec<PackError>)> {
+    if path.is_file() {
+        return Ok((vec![PackFiles::open_archive(path)?], vec![]));
+    }
+    let mut packs = vec![];
+    let mut errors = vec![];
+    if path.join(MANIFEST_FILE).is_file() {
+        packs.push(PackFiles::open_directory(path)?);
+    } else {
+        packs.push(PackFiles::open_loose_files(path)?);
+        // Unreadable entries are reported by the search for archives
+        let subdirectories = WalkDir::new(path)
+            .min_depth(1)
+            .max_depth(1)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_dir());
+        for entry in subdirectories {
+            match PackFiles::open_directory(entry.path()) {
+                Ok(files) => packs.push(files),
+                Err(e) => errors.push(PackError {
+                    file: entry.path().to_path_buf(),
+                    message: e.to_string(),
+                }),
+            }
+        }
+    }
+    for entry in WalkDir::new(path).sort_by_file_name() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                errors.push(PackError {
+                    file: e.path().unwrap_or(path).to_path_buf(),
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() || !is_archive(entry.path()) {
+            continue;
+        }
+        match PackFiles::open_archive(entry.path()) {
+            Ok(files) => packs.push(files),
+            Err(e) => errors.push(PackError {
+                file: entry.path().to_path_buf(),
+                message: e.to_string(),
+            }),
+        }
+    }
+    Ok((packs, errors))
+}
+
+impl PackManifest {
+    /// Reads the manifest of `files`. Without one the pack is named after its file
+    fn read(files: &PackFiles) -> Self {
+        let mut manifest = match files.read(MANIFEST_FILE) {
+            Ok(data) => toml::from_str(&String::from_utf8_lossy(&data)).unwrap_or_else(|e| {
+                error!("Invalid manifest of {:?}: {}", files.get_root(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+        if manifest.name.is_empty() {
+            manifest.name = files
+                .get_root()
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+        }
+        manifest
+    }
+}
+
+impl MarkerPack {
+    /// Loads every XML file of a pack. Broken files are logged and skipped
+    pub fn load(files: &PackFiles, id: u32) -> Self {
+        let manifest = PackManifest::read(files);
+        let mut data = OverlayData::default();
+        for file in files.list() {
+            if !has_extension(Path::new(&file), "xml") {
+                continue;
+            }
+            info!("Found XML file: {:?}", files.get_root().join(&file));
+            let path = files.get_root().join(&file);
+            match files.read(&file) {
+                Ok(file_data) => {
+                    data.merge(OverlayData::parse(file_data.as_slice(), &path).lenient())
+                }
+                Err(e) => error!("Failed to load file {} with error {}", file, e),
+            }
+        }
+        data.set_pack(id);
+        data.load_trails(files);
+        data.fill_poi_parents();
+        Self {
+            id,
+            root: files.get_root().to_path_buf(),
+            manifest,
+            data,
+            enabled: true,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Writes the manifest, the markers to [`MARKER_FILE`] in `dir` and the points of every
+    /// trail to the file its `trailData` names. Trails without points and the icons aren't
+    /// written
+    pub fn write_to(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::create_dir_all(dir)?;
+        // Makes `dir` a single pack, even if the trails are in subdirectories
+        std::fs::write(dir.join(MANIFEST_FILE), toml::to_string(&self.manifest)?)?;
+        let mut xml = vec![];
+        self.data.write(&mut xml)?;
+        std::fs::write(dir.join(MARKER_FILE), xml)?;
+        for trail in &self.data.pois.trail_list {
+            let trail = trail.read().unwrap();
+            if !trail.has_trail_file() {
+                continue;
+            }
+            let trail_file = trail.trail_file.to_string_lossy();
+            let path = to_relative_path(&trail_file)
+                .ok_or_else(|| format!("{} is not a file inside of the pack", trail_file))?;
+            let path = dir.join(path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, trail.get_trail_file().to_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Loads every pack below `path` and adds their files to `registry`
+pub fn load_packs(path: &Path, registry: &PackRegistry) -> Vec<MarkerPack> {
+    let packs = match open_packs(path) {
+        Ok((packs, errors)) => {
+            for e in errors {
+                error!("Failed to open pack {:?}: {}", e.file, e.message);
+            }
+            packs
+        }
+        Err(e) => {
+            error!("Failed to open the packs in {:?}: {}", path, e);
+            vec![]
+        }
+    };
+    packs
+        .into_iter()
+        // Directories that only contain archives
+        .filter(|files| {
+            matches!(files, PackFiles::Archive { .. })
+                || files
+                    .list()
+                    .iter()
+                    .any(|file| has_extension(Path::new(file), "xml"))
+        })
+        .map(|files| {
+            let files = Arc::new(files);
+            let pack = MarkerPack::load(&files, registry.insert(files.clone()));
+            info!(
+                "Loaded pack {} {} by {} from {} as {}{}",
+                pack.manifest.name,
+                pack.manifest.version.as_deref().unwrap_or("(no version)"),
+                pack.manifest.author.as_deref().unwrap_or("unknown"),
+                pack.root.display(),
+                PACK_ASSET_PREFIX,
+                pack.id
+            );
+            pack
+        })
+        .collect()
+}
+
+/// Disables the packs named in `disabled` and enables every other. Returns whether a pack
+/// changed
+pub fn set_disabled_packs(packs: &mut [MarkerPack], disabled: &[String]) -> bool {
+    let mut changed = false;
+    for pack in packs {
+        let enabled = !disabled.contains(&pack.manifest.name);
+        if pack.is_enabled() != enabled {
+            info!(
+                "{} pack {}",
+                if enabled { "Enabling" } else { "Disabling" },
+                pack.manifest.name
+            );
+            pack.set_enabled(enabled);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// The problems of `file`, or `None` if it isn't a marker or trail file
+fn validate_file(files: &PackFiles, file: &str) -> Option<Vec<PackError>> {
+    let is_xml = has_extension(Path::new(file), "xml");
+    if !is_xml && !has_extension(Path::new(file), "trl") {
+        return None;
+    }
+    let path = files.get_root().join(file);
+    let error = |message: String| PackError {
+        file: path.clone(),
+        message,
+    };
+    let data = match files.read(file) {
+        Ok(data) => data,
+        Err(e) => return Some(vec![error(e.to_string())]),
+    };
+    if is_xml {
+        let diagnostics = match OverlayData::parse(data.as_slice(), &path).strict() {
+            Ok(_) => vec![],
+            Err(diagnostics) => diagnostics,
+        };
+        return Some(
+            diagnostics
+                .into_iter()
+                .map(|d| {
+                    error(format!(
+                        "{}:{}: {}: {}",
+                        d.line, d.column, d.element, d.message
+                    ))
+                })
+                .collect(),
+        );
+    }
+    let result = TrailFile::parse(&data).and_then(|trail| match trail.trailing_bytes {
+        0 => Ok(()),
+        bytes => Err(format!("truncated, {} bytes after the last point", bytes).into()),
+    });
+    Some(
+        result
+            .err()
+            .map(|e| error(e.to_string()))
+            .into_iter()
+            .collect(),
+    )
+}
+
+/// Parses every XML and TRL file of the packs below `path`. Returns the number of checked
+/// files and the broken ones
+pub fn validate_packs(path: &Path) -> std::io::Result<(usize, Vec<PackError>)> {
+    let (packs, mut errors) = open_packs(path)?;
+    let mut checked = 0;
+    for files in &packs {
+        for file in files.list() {
+            let Some(file_errors) = validate_file(files, &file) else {
+                continue;
+            };
+            checked += 1;
+            errors.extend(file_errors);
+        }
+    }
+    Ok((checked, errors))
+}
+
+/// Counts the POIs and trails of every map
+pub fn count_markers<'a>(
+    data: impl IntoIterator<Item = &'a OverlayData>,
+) -> BTreeMap<u32, MapMarkers> {
+    let mut maps: BTreeMap<u32, MapMarkers> = BTreeMap::new();
+    for data in data {
+        for poi in &data.pois.poi_list {
+            let map_id = poi.read().unwrap().get_map_id().unwrap_or(0);
+            maps.entry(map_id).or_default().pois += 1;
+        }
+        for trail in &data.pois.trail_list {
+            let map_id = trail.read().unwrap().poi.get_map_id().unwrap_or(0);
+            maps.entry(map_id).or_default().trails += 1;
+        }
+    }
+    maps
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{
+        count_markers, load_packs, set_disabled_packs, validate_packs, MapMarkers, PackManifest,
+        MARKER_FILE,
+    };
+    use crate::{
+        overlay_data::OverlayData, pack_files::tests::write_archive, pack_files::PackRegistry,
+    };
+
+    fn trail_file(points: usize) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend(0u32.to_le_bytes());
+        data.extend(15u32.to_le_bytes());
+        for i in 0..points {
+            for value in [i as f32, 1.0, 2.0] {
+                data.extend(value.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_validate_packs() {
+        let dir = tempfile::tempdir().unwrap();
+        let pack = dir.path().join("pack");
+        std::fs::create_dir(&pack).unwrap();
+        std::fs::write(
+            pack.join("good.xml"),
+            r#"<OverlayData><POIs><POI MapID="15" xpos="1" ypos="2" zpos="3"/></POIs></OverlayData>"#,
+        )
+        .unwrap();
+        std::fs::write(pack.join("broken.XML"), "<OverlayData><POIs>").unwrap();
+        std::fs::write(pack.join("good.trl"), trail_file(2)).unwrap();
+        let mut truncated = trail_file(2);
+        truncated.pop();
+        std::fs::write(pack.join("truncated.trl"), truncated).unwrap();
+        std::fs::write(pack.join("short.trl"), [0u8; 4]).unwrap();
+        std::fs::write(pack.join("icon.png"), [0u8; 4]).unwrap();
+        write_archive(
+            &pack.join("zipped.taco"),
+            &[
+                ("Zipped.xml", b"<OverlayData>"),
+                ("zipped.trl", &trail_file(1)),
+            ],
+        );
+        std::fs::write(pack.join("damaged.zip"), [0u8; 4]).unwrap();
+
+        let (checked, errors) = validate_packs(dir.path()).unwrap();
+        assert_eq!(checked, 7);
+        let mut broken: Vec<_> = errors
+            .iter()
+            .map(|error| error.file.file_name().unwrap().to_str().unwrap())
+            .collect();
+        broken.sort();
+        assert_eq!(
+            broken,
+            vec![
+                "Zipped.xml",
+                "broken.XML",
+                "damaged.zip",
+                "short.trl",
+                "truncated.trl"
+            ]
+        );
+        let (checked, errors) = validate_packs(&pack.join("zipped.taco")).unwrap();
+        assert_eq!((checked, errors.len()), (2, 1));
+
+        assert!(validate_packs(Path::new("/does/not/exist")).is_err());
+    }
+
+    #[test]
+    fn test_load_packs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("loose.xml"),
+            r#"<OverlayData>
+            <MarkerCategory name="chests" iconFile="loose.png"/>
+            <POIs><POI MapID="50" xpos="1" ypos="2" zpos="3" type="chests"/></POIs>
+            </OverlayData>"#,
+        )
+        .unwrap();
+        write_archive(
+            &dir.path().join("chests.taco"),
+            &[
+                (
+                    "manifest.toml",
+                    b"name = \"Chests\"\nversion = \"1.0\"\nauthor = \"Someone\"\n",
+                ),
+                (
+                    "Categories.xml",
+                    br#"<OverlayData>
+                    <MarkerCategory name="chests" iconFile="Data\Chest.png">
+                    <MarkerCategory name="silver"/>
+                    </MarkerCategory>
+                    </OverlayData>"#,
+                ),
+                (
+                    "Markers.xml",
+                    br#"<OverlayData>
+                    <MarkerCategory name="chests"><MarkerCategory name="gold"/></MarkerCategory>
+                    <POIs>
+                    <POI MapID="15" xpos="1" ypos="2" zpos="3" type="chests"/>
+                    <Trail type="chests" trailData="data\TRAIL.trl" texture="Data\Trail.png"/>
+                    </POIs></OverlayData>"#,
+                ),
+                ("Data/Chest.png", b"chest"),
+                ("Data/Trail.trl", &trail_file(3)),
+            ],
+        );
+
+        let registry = PackRegistry::default();
+        let packs = load_packs(dir.path(), &registry);
+        assert_eq!(packs.len(), 2);
+        let loose = &packs[0];
+        assert_eq!(loose.root, dir.path());
+        assert_eq!(
+            loose.manifest.name,
+            dir.path().file_name().unwrap().to_str().unwrap()
+        );
+        let chests = &packs[1];
+        assert_eq!(
+            chests.manifest,
+            PackManifest {
+                name: "Chests".to_string(),
+                version: Some("1.0".to_string()),
+                author: Some("Someone".to_string()),
+            }
+        );
+        assert!(chests.is_enabled());
+
+        let maps = count_markers(packs.iter().map(|pack| &pack.data));
+        assert_eq!(maps[&50], MapMarkers { pois: 1, trails: 0 });
+        // The map of a trail is the one in its file
+        assert_eq!(maps[&15], MapMarkers { pois: 1, trails: 1 });
+
+        // Both packs define chests, but the category is only merged inside of a pack
+        assert_eq!(chests.data.marker_category.len(), 1);
+        let category = chests.data.marker_category[0].read().unwrap();
+        assert_eq!(category.children.len(), 2);
+        let (pack, icon) = chests.data.pois.poi_list[0]
+            .read()
+            .unwrap()
+            .get_icon_source()
+            .unwrap();
+        assert_eq!(pack, Some(chests.id));
+        assert_eq!(icon, PathBuf::from(r"Data\Chest.png"));
+        let files = registry.get(chests.id).unwrap();
+        assert_eq!(files.read(&icon.to_string_lossy()).unwrap(), b"chest");
+        let (pack, icon) = loose.data.pois.poi_list[0]
+            .read()
+            .unwrap()
+            .get_icon_source()
+            .unwrap();
+        assert_eq!((pack, icon), (Some(loose.id), PathBuf::from("loose.png")));
+
+        let trail = chests.data.pois.trail_list[0].read().unwrap();
+        assert_eq!(trail.poi.get_pack(), Some(chests.id));
+        assert_eq!(trail.generate_meshes().len(), 1);
+    }
+
+    #[test]
+    fn test_unpacked_packs() {
+        let dir = tempfile::tempdir().unwrap();
+        let markers = |category: &str| {
+            format!(
+                r#"<OverlayData>
+                <MarkerCategory name="{0}"/>
+                <POIs><POI MapID="15" xpos="1" ypos="2" zpos="3" type="{0}"/></POIs>
+                </OverlayData>"#,
+                category
+            )
+        };
+        let first = dir.path().join("first");
+        std::fs::create_dir_all(first.join("Data")).unwrap();
+        std::fs::write(first.join("manifest.toml"), "name = \"First\"\n").unwrap();
+        std::fs::write(first.join("Data/markers.xml"), markers("chests")).unwrap();
+        let second = dir.path().join("second");
+        std::fs::create_dir(&second).unwrap();
+        std::fs::write(second.join("markers.xml"), markers("nodes")).unwrap();
+
+        let registry = PackRegistry::default();
+        // The loose pack has no XML and is skipped
+        let mut packs = load_packs(dir.path(), &registry);
+        assert_eq!(packs.len(), 2);
+        assert_eq!(packs[0].root, first);
+        assert_eq!(packs[0].manifest.name, "First");
+        assert_eq!(packs[0].data.pois.poi_list.len(), 1);
+        assert_eq!(packs[1].root, second);
+        assert_eq!(packs[1].manifest.name, "second");
+        assert_eq!(packs[1].data.pois.poi_list.len(), 1);
+        assert_ne!(packs[0].id, packs[1].id);
+
+        assert!(set_disabled_packs(&mut packs, &["second".to_string()]));
+        assert!(packs[0].is_enabled());
+        assert!(!packs[1].is_enabled());
+
+        // A directory with a manifest is one pack, even with subdirectories
+        let packs = load_packs(&first, &registry);
+        assert_eq!(packs.len(), 1);
+        assert_eq!(packs[0].manifest.name, "First");
+    }
+
+    #[test]
+    fn test_write_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = trail_file(3);
+        // Neither the version nor the map of the file are the defaults
+        file[..4].copy_from_slice(&2u32.to_le_bytes());
+        write_archive(
+            &dir.path().join("trails.taco"),
+            &[
+                (
+                    "Markers.xml",
+                    br#"<OverlayData>
+                    <MarkerCategory name="jumping"/>
+                    <POIs>
+                    <POI MapID="50" xpos="1" ypos="2" zpos="3" type="jumping"/>
+                    <Trail type="jumping" trailData="Data\Jump.trl" texture="a.png"/>
+                    <Trail type="jumping" trailData="Data\Missing.trl" texture="a.png"/>
+                    </POIs></OverlayData>"#,
+                ),
+                ("Data/Jump.trl", &file),
+            ],
+        );
+        let registry = PackRegistry::default();
+        let packs = load_packs(&dir.path().join("trails.taco"), &registry);
+        let out = dir.path().join("unpacked");
+        packs[0].write_to(&out).unwrap();
+
+        assert_eq!(std::fs::read(out.join("Data/Jump.trl")).unwrap(), file);
+        assert!(!out.join("Data/Missing.trl").exists());
+        let xml = std::fs::read_to_string(out.join(MARKER_FILE)).unwrap();
+        // The map of the trail stays in its file
+        assert_eq!(xml.matches("MapID").count(), 1);
+
+        let written = load_packs(&out, &registry);
+        assert_eq!(written.len(), 1);
+        assert_eq!(written[0].manifest, packs[0].manifest);
+        let maps = count_markers([&packs[0].data]);
+        assert_eq!(count_markers([&written[0].data]), maps);
+        assert_eq!(maps[&15].trails, 1);
+        let mut trail = written[0].data.pois.trail_list[0].write().unwrap();
+        assert_eq!(trail.get_trail_file().to_bytes(), file);
+
+        trail.trail_file = PathBuf::from(r"..\Jump.trl");
+        drop(trail);
+        assert!(written[0].write_to(&dir.path().join("escaped")).is_err());
+    }
+
+    #[test]
+    fn test_set_disabled_packs() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.zip", "b.taco"] {
+            write_archive(
+                &dir.path().join(name),
+                &[("markers.xml", b"<OverlayData/>")],
+            );
+        }
+        let mut packs = load_packs(dir.path(), &PackRegistry::default());
+        assert_eq!(packs.len(), 2);
+        assert!(set_disabled_packs(&mut packs, &["b".to_string()]));
+        assert!(packs[0].is_enabled());
+        assert!(!packs[1].is_enabled());
+        assert!(!set_disabled_packs(&mut packs, &["b".to_string()]));
+        assert!(set_disabled_packs(&mut packs, &[]));
+        assert!(packs[1].is_enabled());
+    }
+
+    #[test]
+    fn test_count_markers() {
+        let data = OverlayData::from_string(
+            r#"<OverlayData><POIs>
+            <POI MapID="15" xpos="1" ypos="2" zpos="3"/>
+            <POI MapID="15" xpos="1" ypos="2" zpos="3"/>
+            <POI MapID="50" xpos="1" ypos="2" zpos="3"/>
+            <Trail MapID="50" trailData="a.trl" texture="a.png"/>
+            </POIs></OverlayData>"#,
+        )
+        .strict()
+        .unwrap();
+        let maps = count_markers([&data]);
+        assert_eq!(maps[&15], MapMarkers { pois: 2, trails: 0 });
+        assert_eq!(maps[&50], MapMarkers { pois: 1, trails: 1 });
+    }
+}
@@ -1,130 +1,373 @@
 use std::collections::HashMap;
 use std::io::Write;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
+use bevy::prelude::{info, warn};
+use nix::sys::signal::{kill, Signal};
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd;
 use tempfile::NamedTempFile;
 
-pub fn find_wine_process(name: &str) -> Option<i32> {
-    println!("### Readin proc in rust");
-    let dirs = std::fs::read_dir("/proc").unwrap();
-    for d in dirs {
-        let d = d.unwrap();
-        if d.path().is_dir()
-            && d.file_name()
-                .to_str()
-                .unwrap()
-                .to_string()
-                .chars()
-                .all(|c| c.is_numeric() && c.is_alphanumeric())
-        {
-            // It is a process dir
-            let cmdline_path = d.path().join("cmdline");
-            if cmdline_path.exists() {
-                let cmdline = std::fs::read_to_string(cmdline_path);
-                match cmdline {
-                    Ok(line) => {
-                        if line.find(name).is_some() {
-                            let pid = d.file_name().to_str().unwrap().parse::<i32>().unwrap_or(-1);
-                            println!("Found {} with pid {}", name, pid);
-                            return Some(pid);
-                        }
-                    }
-                    Err(_) => (),
-                }
-            }
-        }
+use crate::wine::WineEnvironment;
+
+/// Root of the process filesystem
+pub const PROC_ROOT: &str = "/proc";
+
+/// A snapshot of a running process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub pid: i32,
+    /// Target of `exe`. `None` for processes of other users
+    pub exe: Option<PathBuf>,
+    pub argv: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    /// Clock ticks after boot. Tells a process apart from a later one with the same pid
+    pub start_time: u64,
+}
+
+/// The file name of a linux or windows path
+fn get_basename(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// The start time is field 22. The name in field 2 can contain spaces and parentheses
+fn parse_start_time(stat: &str) -> Option<u64> {
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+impl ProcessInfo {
+    /// Reads `<root>/<pid>`. `None` if the process exited in the meantime
+    pub fn read(root: &Path, pid: i32) -> Option<Self> {
+        let dir = root.join(pid.to_string());
+        let start_time = parse_start_time(&std::fs::read_to_string(dir.join("stat")).ok()?)?;
+        let argv = std::fs::read(dir.join("cmdline"))
+            .ok()?
+            .split(|&b| b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).to_string())
+            .collect();
+        Some(Self {
+            pid,
+            exe: std::fs::read_link(dir.join("exe")).ok(),
+            argv,
+            cwd: std::fs::read_link(dir.join("cwd")).ok(),
+            start_time,
+        })
+    }
+
+    /// The program name. Wine processes have the windows path of the program in the
+    /// first argument while `exe` is the wine preloader
+    pub fn get_name(&self) -> Option<&str> {
+        self.argv
+            .first()
+            .map(|arg| get_basename(arg))
+            .or_else(|| self.exe.as_deref()?.file_name()?.to_str())
+    }
+
+    /// Compares the program name with `name` ignoring the case like windows does
+    pub fn is_named(&self, name: &str) -> bool {
+        self.get_name()
+            .is_some_and(|own| own.eq_ignore_ascii_case(name))
     }
-    None
 }
 
-pub fn is_child_running(pid: i32) -> bool {
-    let ret = waitpid(unistd::Pid::from_raw(pid), Some(WaitPidFlag::WNOHANG));
-    return ret.is_ok() && ret.unwrap() == WaitStatus::StillAlive;
+/// Lists the processes of a `/proc` like directory
+#[derive(Debug, Clone)]
+pub struct ProcessScanner {
+    root: PathBuf,
 }
 
-pub fn get_env_val(list_of_envs: Vec<String>, env_to_find: &str) -> String {
-    for env in list_of_envs {
-        let tokens = env.split_once("=");
-        if tokens.is_some() && tokens.unwrap().0 == env_to_find {
-            return tokens.unwrap().1.to_string();
-        }
+impl Default for ProcessScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProcessScanner {
+    pub fn new() -> Self {
+        Self::with_root(PROC_ROOT)
+    }
+
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn get(&self, pid: i32) -> Option<ProcessInfo> {
+        ProcessInfo::read(&self.root, pid)
+    }
+
+    /// Every readable process. Processes that exit while scanning are skipped
+    pub fn iter(&self) -> impl Iterator<Item = ProcessInfo> + '_ {
+        std::fs::read_dir(&self.root)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<i32>().ok())
+            .filter_map(|pid| self.get(pid))
+    }
+
+    pub fn filter<'a>(
+        &'a self,
+        predicate: impl Fn(&ProcessInfo) -> bool + 'a,
+    ) -> impl Iterator<Item = ProcessInfo> + 'a {
+        self.iter().filter(move |process| predicate(process))
+    }
+
+    /// Processes running the program `name`. Launchers and crash reporters that only get
+    /// the path of it as an argument don't match
+    pub fn find_by_name<'a>(&'a self, name: &'a str) -> impl Iterator<Item = ProcessInfo> + 'a {
+        self.filter(move |process| process.is_named(name))
     }
-    return "".to_string();
 }
 
-fn start_process(cmd: &str, args: Vec<&str>, env: HashMap<String, String>) -> u32 {
-    let process = std::process::Command::new(cmd)
-        .args(args)
-        .envs(env)
-        .spawn()
-        .unwrap();
-    process.id()
+pub fn is_child_running(pid: i32) -> bool {
+    matches!(
+        waitpid(unistd::Pid::from_raw(pid), Some(WaitPidFlag::WNOHANG)),
+        Ok(WaitStatus::StillAlive)
+    )
 }
 
-pub fn get_environment(pid: i32) -> HashMap<String, String> {
-    let proc_path = format!("/proc/{}/environ", pid);
-    let env_path = std::path::Path::new(&proc_path);
-    let mut env_data: HashMap<String, String> = HashMap::new();
-    if env_path.exists() {
-        let data = std::fs::read_to_string(env_path);
-        match data {
-            Ok(d) => {
-                let tokens = d.split("\0");
-                tokens.into_iter().for_each(|t| {
-                    let key_val = t.split_once("=");
-                    if key_val.is_some() {
-                        env_data.insert(
-                            key_val.unwrap().0.to_string(),
-                            key_val.unwrap().1.to_string(),
-                        );
-                    }
-                });
-            }
-            Err(_) => (),
+/// Sends SIGTERM to a child and reaps it. A child that is still running after `timeout`
+/// gets killed
+pub fn stop_child(pid: i32, timeout: Duration) {
+    let pid = unistd::Pid::from_raw(pid);
+    if kill(pid, Signal::SIGTERM).is_err() {
+        return;
+    }
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) => std::thread::sleep(Duration::from_millis(10)),
+            _ => return,
         }
     }
-    env_data
+    warn!("Helper {} ignored SIGTERM, killing it", pid);
+    if kill(pid, Signal::SIGKILL).is_ok() {
+        let _ = waitpid(pid, None);
+    }
 }
 
-pub fn remove_from_env(
+/// The process gets killed when the overlay dies, even if it can't clean up
+fn start_process(
+    cmd: &str,
+    args: Vec<String>,
     env: HashMap<String, String>,
-    to_remove: Vec<&str>,
-) -> HashMap<String, String> {
-    let env = env
-        .into_iter()
-        .filter(|(key, _val)| {
-            let str_key: &str = key;
-            !to_remove.contains(&str_key)
-        })
-        .collect();
+) -> std::io::Result<u32> {
+    let mut command = std::process::Command::new(cmd);
+    command.args(args).envs(env);
+    // SAFETY: prctl is async-signal-safe
+    unsafe {
+        command.pre_exec(|| {
+            if nix::libc::prctl(nix::libc::PR_SET_PDEATHSIG, nix::libc::SIGTERM) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let process = command.spawn()?;
+    Ok(process.id())
+}
+
+/// Overrides the location of `mumble_bridge.exe`
+pub const BRIDGE_ENV: &str = "RUSTYGW2_BRIDGE";
+
+/// Loads the bridge from `RUSTYGW2_BRIDGE`, the copy embedded at build time or
+/// `mumble_bridge.exe` next to the overlay, in that order
+pub fn load_bridge() -> std::io::Result<Vec<u8>> {
+    if let Some(path) = std::env::var_os(BRIDGE_ENV) {
+        return std::fs::read(path);
+    }
+    #[cfg(embed_bridge)]
+    return Ok(include_bytes!(env!("RUSTYGW2_BRIDGE_PATH")).to_vec());
+    #[cfg(not(embed_bridge))]
+    std::fs::read(std::env::current_exe()?.with_file_name("mumble_bridge.exe"))
+}
+
+/// Writes the bridge where `wine` can run it. Returns the file and its path for wine
+pub fn write_bridge(
+    wine: &WineEnvironment,
+    bridge: &[u8],
+) -> std::io::Result<(NamedTempFile, String)> {
+    let (dir, wine_dir) = wine.get_bridge_dir();
+    let mut file = tempfile::Builder::new()
+        .prefix("mumble_bridge")
+        .suffix(".exe")
+        .tempfile_in(dir)?;
+    file.write_all(bridge)?;
+    let path = wine_dir.join(file.path().file_name().unwrap_or_default());
+    let path = path.to_str().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} is not valid UTF-8", path.display()),
+        )
+    })?;
+    Ok((file, path.to_string()))
+}
 
-    env
+/// Starts the bridge in the wine environment of `pid`. It forwards the MumbleLink
+/// `mumble_name` to `port`
+pub fn start_gw2_helper(
+    pid: i32,
+    port: u16,
+    mumble_name: &str,
+) -> std::io::Result<(NamedTempFile, i32)> {
+    let wine = WineEnvironment::from_pid(pid)?;
+    info!(
+        "Wine: {:?} {} prefix: {}",
+        wine.flavor,
+        wine.wine.display(),
+        wine.prefix.display()
+    );
+    match &wine.server_socket {
+        Some(socket) if !socket.exists() => {
+            warn!("Wineserver socket {} is missing", socket.display())
+        }
+        _ => (),
+    }
+
+    let (mumble_file, file_path) = write_bridge(&wine, &load_bridge()?)?;
+    let port = port.to_string();
+    let (program, args) = wine.command(&[&file_path, &port, mumble_name]);
+    let pid = start_process(&program, args, wine.env)? as i32;
+    Ok((mumble_file, pid))
 }
 
-pub fn start_gw2_helper(pid: i32) -> (NamedTempFile, i32) {
-    // XXX: Increases memory footprint, but makes it easier to manage
-    let mumble_process_data = include_bytes!("../helper/mumble.exe");
-    let mut mumble_file = NamedTempFile::new().unwrap();
-    mumble_file.write_all(mumble_process_data).unwrap();
-    let env = get_environment(pid);
-
-    let envs_to_remove = vec![
-        "WINESERVERSOCKET",
-        "WINELOADERNOEXEC",
-        "WINEPRELOADRESERVE",
-        "LD_PRELOAD",
-    ];
-    let env = remove_from_env(env, envs_to_remove);
-
-    let wine = env.get("WINE").unwrap_or(&"wine".to_string()).clone();
-    let prefix = env
-        .get("WINEPREFIX")
-        .unwrap_or(&"~/.wine".to_string())
-        .clone();
-    println!("Wine: {} prefix: {}", wine, prefix);
-
-    let file_path = mumble_file.path().to_str().unwrap();
-    let pid = start_process(&wine, vec![file_path], env) as i32;
-    (mumble_file, pid)
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::fs::symlink,
+        path::Path,
+        time::{Duration, Instant},
+    };
+
+    use super::{is_child_running, parse_start_time, stop_child, write_bridge, ProcessScanner};
+    use crate::wine::{WineEnvironment, WineFlavor};
+
+    fn add_process(root: &Path, pid: i32, argv: &[&str], start_time: u64) {
+        let dir = root.join(pid.to_string());
+        std::fs::create_dir(&dir).unwrap();
+        let cmdline: String = argv.iter().map(|arg| format!("{}\0", arg)).collect();
+        std::fs::write(dir.join("cmdline"), cmdline).unwrap();
+        let stat = format!(
+            "{} (wine64 (x)) S 1 2 3 0 -1 4194304 1 0 0 0 0 0 0 0 20 0 1 0 {} 1000 10",
+            pid, start_time
+        );
+        std::fs::write(dir.join("stat"), stat).unwrap();
+        symlink("/usr/bin/wine64-preloader", dir.join("exe")).unwrap();
+        symlink("/home/user/Games", dir.join("cwd")).unwrap();
+    }
+
+    #[test]
+    fn test_stop_child() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        let pid = child.id() as i32;
+        let start = Instant::now();
+        stop_child(pid, Duration::from_secs(5));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(!is_child_running(pid));
+        // Already reaped
+        assert!(child.wait().is_err());
+    }
+
+    #[test]
+    fn test_stop_child_ignoring_sigterm() {
+        // The ignored SIGTERM survives the exec
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "trap '' TERM; exec sleep 10"])
+            .spawn()
+            .unwrap();
+        let pid = child.id() as i32;
+        // Give the shell time to set up the trap
+        std::thread::sleep(Duration::from_millis(200));
+        let start = Instant::now();
+        stop_child(pid, Duration::from_millis(300));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(!is_child_running(pid));
+        // Already reaped
+        assert!(child.wait().is_err());
+    }
+
+    #[test]
+    fn test_write_bridge_flatpak() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("var/data/bottles/GW2")).unwrap();
+        let wine = WineEnvironment {
+            flavor: WineFlavor::Bottles,
+            wine: "/var/data/bottles/runners/wine/bin/wine64".into(),
+            prefix: "/var/data/bottles/GW2".into(),
+            server_socket: None,
+            flatpak_id: Some("com.usebottles.bottles".to_string()),
+            root: root.path().to_path_buf(),
+            env: Default::default(),
+        };
+        let (file, path) = write_bridge(&wine, b"MZ").unwrap();
+        // Wine sees the file at `path` inside of the sandbox
+        assert!(path.starts_with("/var/data/bottles/GW2/"));
+        assert!(path.ends_with(".exe"));
+        assert_eq!(file.path(), root.path().join(&path[1..]));
+        assert_eq!(std::fs::read(root.path().join(&path[1..])).unwrap(), b"MZ");
+    }
+
+    #[test]
+    fn test_start_time() {
+        let stat = "12 (a) b) S 1 2 3 0 -1 4194304 1 0 0 0 0 0 0 0 20 0 1 0 4242 1000";
+        assert_eq!(parse_start_time(stat), Some(4242));
+        assert_eq!(parse_start_time("12 (a) S 1"), None);
+    }
+
+    #[test]
+    fn test_scanner() {
+        let root = tempfile::tempdir().unwrap();
+        add_process(
+            root.path(),
+            100,
+            &[r"C:\Program Files\Guild Wars 2\Gw2-64.exe"],
+            50,
+        );
+        // The launcher only passes the path of the game
+        add_process(
+            root.path(),
+            101,
+            &["Launcher.exe", r"C:\Program Files\Guild Wars 2\Gw2-64.exe"],
+            51,
+        );
+        add_process(root.path(), 102, &["/usr/bin/CrashReporterGw2-64.exe"], 52);
+        // Exited while scanning
+        std::fs::create_dir(root.path().join("103")).unwrap();
+        std::fs::create_dir(root.path().join("self")).unwrap();
+
+        let scanner = ProcessScanner::with_root(root.path());
+        let mut pids: Vec<i32> = scanner.iter().map(|process| process.pid).collect();
+        pids.sort();
+        assert_eq!(pids, vec![100, 101, 102]);
+
+        let games: Vec<_> = scanner.find_by_name("GW2-64.exe").collect();
+        assert_eq!(games.len(), 1);
+        let game = &games[0];
+        assert_eq!(game.pid, 100);
+        assert_eq!(game.start_time, 50);
+        assert_eq!(game.get_name(), Some("Gw2-64.exe"));
+        assert_eq!(
+            game.exe.as_deref(),
+            Some(Path::new("/usr/bin/wine64-preloader"))
+        );
+        assert_eq!(game.cwd.as_deref(), Some(Path::new("/home/user/Games")));
+
+        let launchers: Vec<_> = scanner
+            .filter(|process| process.argv.len() > 1)
+            .map(|process| process.pid)
+            .collect();
+        assert_eq!(launchers, vec![101]);
+
+        assert!(scanner.get(103).is_none());
+        assert!(ProcessScanner::with_root("/does/not/exist")
+            .iter()
+            .next()
+            .is_none());
+    }
 }
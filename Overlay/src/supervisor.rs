@@ -0,0 +1,381 @@
+//! Keeps the helper of a game client running.
+//!
+//! A crashed helper is restarted with an exponential backoff. If the game exits the helper is
+//! stopped and the supervisor waits until a game with the same MumbleLink name shows up again.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use bevy::log::{info, warn};
+use tempfile::NamedTempFile;
+
+use crate::{
+    clients::{find_games, get_mumble_name, GameClient},
+    processutils::{self, ProcessInfo, ProcessScanner},
+};
+
+/// Wait time before the first restart
+pub const MIN_BACKOFF: Duration = Duration::from_secs(1);
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A helper that ran at least this long resets the backoff when it crashes
+pub const STABLE_AFTER: Duration = Duration::from_secs(30);
+/// A stopped helper that is still running after this long gets killed
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Starts and watches the processes of a client
+pub trait HelperLauncher: Send + Sync {
+    /// Starts the helper for `client` and returns its pid
+    fn start(&mut self, client: &GameClient) -> std::io::Result<i32>;
+    fn is_helper_running(&mut self, pid: i32) -> bool;
+    fn is_game_running(&self, client: &GameClient) -> bool;
+    fn stop(&mut self, pid: i32);
+    /// Running games that have the MumbleLink of `client`
+    fn find_games(&self, client: &GameClient) -> Vec<ProcessInfo>;
+}
+
+/// Runs the bridge with the wine of the game
+pub struct WineLauncher {
+    exe_names: Vec<String>,
+    /// The bridge executables have to exist as long as their helper runs
+    files: HashMap<i32, NamedTempFile>,
+}
+
+impl WineLauncher {
+    pub fn new(exe_names: Vec<String>) -> Self {
+        Self {
+            exe_names,
+            files: HashMap::new(),
+        }
+    }
+}
+
+impl HelperLauncher for WineLauncher {
+    fn start(&mut self, client: &GameClient) -> std::io::Result<i32> {
+        let (file, pid) =
+            processutils::start_gw2_helper(client.pid, client.port, &client.mumble_name)?;
+        self.files.insert(pid, file);
+        Ok(pid)
+    }
+
+    fn is_helper_running(&mut self, pid: i32) -> bool {
+        let running = processutils::is_child_running(pid);
+        if !running {
+            self.files.remove(&pid);
+        }
+        running
+    }
+
+    fn is_game_running(&self, client: &GameClient) -> bool {
+        ProcessScanner::new()
+            .get(client.pid)
+            .is_some_and(|process| process.start_time == client.start_time)
+    }
+
+    fn stop(&mut self, pid: i32) {
+        processutils::stop_child(pid, STOP_TIMEOUT);
+        self.files.remove(&pid);
+    }
+
+    fn find_games(&self, client: &GameClient) -> Vec<ProcessInfo> {
+        find_games(&self.exe_names)
+            .into_iter()
+            .filter(|game| get_mumble_name(&game.argv) == client.mumble_name)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HelperState {
+    Running(i32),
+    /// The helper crashed or failed to start and is started again at the given time
+    Restarting(Instant),
+    /// The game exited. The helper starts again with the next game
+    WaitingForGame,
+    /// Stopped for good, e.g. because the overlay exits
+    Stopped,
+}
+
+pub struct HelperSupervisor<L: HelperLauncher = WineLauncher> {
+    launcher: L,
+    client: GameClient,
+    state: HelperState,
+    backoff: Duration,
+    started: Instant,
+    restarts: u32,
+}
+
+impl<L: HelperLauncher> HelperSupervisor<L> {
+    /// Starts the helper right away
+    pub fn new(launcher: L, client: GameClient) -> Self {
+        Self::new_at(launcher, client, Instant::now())
+    }
+
+    pub fn new_at(launcher: L, client: GameClient, now: Instant) -> Self {
+        let mut supervisor = Self {
+            launcher,
+            client,
+            state: HelperState::Restarting(now),
+            backoff: MIN_BACKOFF,
+            started: now,
+            restarts: 0,
+        };
+        supervisor.start(now);
+        supervisor
+    }
+
+    pub fn get_state(&self) -> HelperState {
+        self.state
+    }
+
+    /// The game that is currently supervised. The pid changes when the game restarts
+    pub fn get_client(&self) -> &GameClient {
+        &self.client
+    }
+
+    /// How often the helper was restarted after a crash
+    pub fn get_restarts(&self) -> u32 {
+        self.restarts
+    }
+
+    pub fn update(&mut self, taken: &[i32]) {
+        self.update_at(Instant::now(), taken)
+    }
+
+    /// Checks the processes. `taken` are the games of other clients that must not be attached
+    pub fn update_at(&mut self, now: Instant, taken: &[i32]) {
+        match self.state {
+            HelperState::Stopped => return,
+            HelperState::WaitingForGame => {
+                let game = self
+                    .launcher
+                    .find_games(&self.client)
+                    .into_iter()
+                    .find(|game| !taken.contains(&game.pid));
+                if let Some(game) = game {
+                    info!(
+                        "Game of port {} is back with pid {}",
+                        self.client.port, game.pid
+                    );
+                    self.client.pid = game.pid;
+                    self.client.start_time = game.start_time;
+                    self.backoff = MIN_BACKOFF;
+                    self.start(now);
+                }
+                return;
+            }
+            _ => (),
+        }
+        if !self.launcher.is_game_running(&self.client) {
+            info!("Game {} exited. Waiting for it to restart", self.client.pid);
+            self.stop_helper();
+            self.state = HelperState::WaitingForGame;
+            return;
+        }
+        match self.state {
+            HelperState::Running(pid) if !self.launcher.is_helper_running(pid) => {
+                if now.duration_since(self.started) >= STABLE_AFTER {
+                    self.backoff = MIN_BACKOFF;
+                }
+                warn!(
+                    "Helper {} of game {} died. Restarting in {:?}",
+                    pid, self.client.pid, self.backoff
+                );
+                self.schedule_restart(now);
+            }
+            HelperState::Restarting(at) if now >= at => {
+                self.restarts += 1;
+                self.start(now);
+            }
+            _ => (),
+        }
+    }
+
+    /// Stops the helper and doesn't start it again
+    pub fn stop(&mut self) {
+        self.stop_helper();
+        self.state = HelperState::Stopped;
+    }
+
+    fn start(&mut self, now: Instant) {
+        match self.launcher.start(&self.client) {
+            Ok(pid) => {
+                info!("Started helper {} for game {}", pid, self.client.pid);
+                self.started = now;
+                self.state = HelperState::Running(pid);
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to start the helper for game {}: {}. Retrying in {:?}",
+                    self.client.pid, e, self.backoff
+                );
+                self.schedule_restart(now);
+            }
+        }
+    }
+
+    fn schedule_restart(&mut self, now: Instant) {
+        self.state = HelperState::Restarting(now + self.backoff);
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+
+    fn stop_helper(&mut self) {
+        if let HelperState::Running(pid) = self.state {
+            info!("Stopping helper {}", pid);
+            self.launcher.stop(pid);
+        }
+    }
+}
+
+impl<L: HelperLauncher> Drop for HelperSupervisor<L> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        io::{Error, ErrorKind},
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use super::{HelperLauncher, HelperState, HelperSupervisor, MAX_BACKOFF, MIN_BACKOFF};
+    use crate::{clients::GameClient, processutils::ProcessInfo};
+
+    #[derive(Default)]
+    struct Processes {
+        games: HashSet<i32>,
+        helpers: HashSet<i32>,
+        next_pid: i32,
+        fail_start: bool,
+        stopped: Vec<i32>,
+    }
+
+    /// Shares the fake processes with the test
+    #[derive(Clone, Default)]
+    struct FakeLauncher(Arc<Mutex<Processes>>);
+
+    impl HelperLauncher for FakeLauncher {
+        fn start(&mut self, _client: &GameClient) -> std::io::Result<i32> {
+            let mut processes = self.0.lock().unwrap();
+            if processes.fail_start {
+                return Err(Error::new(ErrorKind::NotFound, "no wine"));
+            }
+            processes.next_pid += 1;
+            let pid = 1000 + processes.next_pid;
+            processes.helpers.insert(pid);
+            Ok(pid)
+        }
+
+        fn is_helper_running(&mut self, pid: i32) -> bool {
+            self.0.lock().unwrap().helpers.contains(&pid)
+        }
+
+        fn is_game_running(&self, client: &GameClient) -> bool {
+            self.0.lock().unwrap().games.contains(&client.pid)
+        }
+
+        fn stop(&mut self, pid: i32) {
+            let mut processes = self.0.lock().unwrap();
+            processes.helpers.remove(&pid);
+            processes.stopped.push(pid);
+        }
+
+        fn find_games(&self, _client: &GameClient) -> Vec<ProcessInfo> {
+            let mut games: Vec<i32> = self.0.lock().unwrap().games.iter().copied().collect();
+            games.sort();
+            games
+                .into_iter()
+                .map(|pid| ProcessInfo {
+                    pid,
+                    exe: None,
+                    argv: vec!["Gw2-64.exe".to_string()],
+                    cwd: None,
+                    start_time: pid as u64,
+                })
+                .collect()
+        }
+    }
+
+    fn client(pid: i32) -> GameClient {
+        GameClient {
+            pid,
+            start_time: pid as u64,
+            mumble_name: "MumbleLink".to_string(),
+            port: 7070,
+        }
+    }
+
+    #[test]
+    fn test_restart_with_backoff() {
+        let launcher = FakeLauncher::default();
+        launcher.0.lock().unwrap().games.insert(1);
+        let start = Instant::now();
+        let mut supervisor = HelperSupervisor::new_at(launcher.clone(), client(1), start);
+        assert_eq!(supervisor.get_state(), HelperState::Running(1001));
+
+        // Crash
+        launcher.0.lock().unwrap().helpers.clear();
+        supervisor.update_at(start, &[]);
+        assert_eq!(
+            supervisor.get_state(),
+            HelperState::Restarting(start + MIN_BACKOFF)
+        );
+        supervisor.update_at(start + MIN_BACKOFF, &[]);
+        assert_eq!(supervisor.get_state(), HelperState::Running(1002));
+        assert_eq!(supervisor.get_restarts(), 1);
+
+        // Crashing right away doubles the wait
+        launcher.0.lock().unwrap().helpers.clear();
+        let now = start + MIN_BACKOFF;
+        supervisor.update_at(now, &[]);
+        assert_eq!(
+            supervisor.get_state(),
+            HelperState::Restarting(now + MIN_BACKOFF * 2)
+        );
+
+        // Failing starts keep backing off up to the maximum
+        launcher.0.lock().unwrap().fail_start = true;
+        let mut now = now;
+        for _ in 0..10 {
+            now += MAX_BACKOFF;
+            supervisor.update_at(now, &[]);
+        }
+        assert_eq!(
+            supervisor.get_state(),
+            HelperState::Restarting(now + MAX_BACKOFF)
+        );
+    }
+
+    #[test]
+    fn test_wait_for_game() {
+        let launcher = FakeLauncher::default();
+        launcher.0.lock().unwrap().games.insert(1);
+        let now = Instant::now();
+        let mut supervisor = HelperSupervisor::new_at(launcher.clone(), client(1), now);
+
+        // The game exits, the helper is stopped
+        launcher.0.lock().unwrap().games.clear();
+        supervisor.update_at(now, &[]);
+        assert_eq!(supervisor.get_state(), HelperState::WaitingForGame);
+        assert_eq!(launcher.0.lock().unwrap().stopped, vec![1001]);
+
+        // A game of another client is not attached
+        launcher.0.lock().unwrap().games.insert(2);
+        supervisor.update_at(now, &[2]);
+        assert_eq!(supervisor.get_state(), HelperState::WaitingForGame);
+
+        launcher.0.lock().unwrap().games.insert(3);
+        supervisor.update_at(now + Duration::from_secs(1), &[2]);
+        assert_eq!(supervisor.get_client().pid, 3);
+        assert_eq!(supervisor.get_state(), HelperState::Running(1002));
+
+        // Dropping kills the helper
+        drop(supervisor);
+        assert_eq!(launcher.0.lock().unwrap().stopped, vec![1001, 1002]);
+    }
+}
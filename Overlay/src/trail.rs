@@ -1,9 +1,6 @@
 use std::{
     error::Error,
-    fs,
-    io::{Read, Seek, SeekFrom},
-    path::PathBuf,
-    str::FromStr,
+    path::{Path, PathBuf},
     sync::{Arc, RwLock},
 };
 
@@ -11,28 +8,18 @@ use bevy::{
     prelude::{Mesh, Vec2, Vec3, Vec4},
     render::{mesh::Indices, render_resource::PrimitiveTopology},
 };
-use log::error;
 
-use byteorder::{LittleEndian, ReadBytesExt};
-use serde::{Deserialize, Deserializer};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{Deserialize, Serialize};
 
 use crate::gw2poi::POI;
+use crate::pack_files::PackFiles;
 use crate::utils::ToGw2Coordinate;
 
 pub type TrailContainer = Arc<RwLock<Trail>>;
 
-pub fn deserialize_trail_vec<'de, D>(deserializer: D) -> Result<Vec<TrailContainer>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let trail = Vec::<Trail>::deserialize(deserializer)?;
-    let new_vec: Vec<TrailContainer> = trail
-        .iter()
-        .map(|p| Arc::new(RwLock::new(p.clone())))
-        .collect();
-
-    Ok(new_vec)
-}
+/// Version in the header of new `.trl` files
+pub const TRAIL_FILE_VERSION: u32 = 0;
 
 #[derive(Debug, Default, Clone)]
 struct TrailData {
@@ -51,10 +38,25 @@ impl From<Vec3> for TrailData {
     }
 }
 
-#[derive(Debug, Default, Deserialize, Clone)]
+/// Converts between the points of a `.trl` file and the ones the trail is drawn with. The
+/// conversion is its own inverse
+fn convert_file_point(pos: Vec3) -> Vec3 {
+    if cfg!(feature = "custom_projection") {
+        pos
+    } else {
+        pos.as_gw2_coordinate()
+    }
+}
+
+fn is_empty_path(path: &Path) -> bool {
+    path.as_os_str().is_empty()
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct Trail {
-    #[serde(rename = "trailData")]
+    #[serde(default, rename = "trailData")]
     pub trail_file: PathBuf,
+    #[serde(default, skip_serializing_if = "is_empty_path")]
     pub texture: PathBuf,
     pub color: Option<String>,
     #[serde(rename = "animSpeed")]
@@ -64,53 +66,107 @@ pub struct Trail {
 
     #[serde(skip)]
     trail_data: Vec<TrailData>,
+    /// Version of the loaded `.trl` file, written back unchanged
+    #[serde(skip)]
+    trail_version: Option<u32>,
+}
+
+/// Contents of a `.trl` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrailFile {
+    pub version: u32,
+    pub map_id: u32,
+    pub points: Vec<Vec3>,
+    /// Bytes after the last complete point. Only a broken file has them
+    pub trailing_bytes: usize,
+}
+
+impl TrailFile {
+    /// The header is the version and the map id, followed by the x, y and z of every point
+    pub fn parse(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if data.len() < 8 {
+            return Err(format!("{} bytes are too short for the header", data.len()).into());
+        }
+        let mut cursor = std::io::Cursor::new(data);
+        let version = cursor.read_u32::<LittleEndian>()?;
+        let map_id = cursor.read_u32::<LittleEndian>()?;
+
+        let coord_size = std::mem::size_of::<TrailData>();
+        let num_coords = (data.len() - 8) / coord_size;
+        let mut points = Vec::with_capacity(num_coords);
+        for _ in 0..num_coords {
+            let x = cursor.read_f32::<LittleEndian>()?;
+            let y = cursor.read_f32::<LittleEndian>()?;
+            let z = cursor.read_f32::<LittleEndian>()?;
+            points.push(Vec3::new(x, y, z));
+        }
+        Ok(Self {
+            version,
+            map_id,
+            points,
+            trailing_bytes: (data.len() - 8) % coord_size,
+        })
+    }
+
+    /// The header and the points in the layout [`Self::parse`] reads
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(8 + self.points.len() * std::mem::size_of::<TrailData>());
+        // Writing to a Vec can't fail
+        data.write_u32::<LittleEndian>(self.version).unwrap();
+        data.write_u32::<LittleEndian>(self.map_id).unwrap();
+        for point in &self.points {
+            data.write_f32::<LittleEndian>(point.x).unwrap();
+            data.write_f32::<LittleEndian>(point.y).unwrap();
+            data.write_f32::<LittleEndian>(point.z).unwrap();
+        }
+        data
+    }
 }
 
 impl Trail {
-    pub fn load_map_trail(&mut self) -> Result<(), Box<dyn Error>> {
-        // TODO: get from asset server
-        let mut file_path = PathBuf::from_str("Overlay/assets").unwrap();
-        file_path.push(self.trail_file.clone());
-        let f = fs::File::open(file_path);
-        match f {
-            Ok(mut file) => {
-                let total_len = file.metadata()?.len();
-                if total_len >= 8 {
-                    file.seek(SeekFrom::Start(4))?;
-                    let mut buffer = [0u8; 4];
-                    file.read_exact(&mut buffer)?;
-                    let map_id = u32::from_le_bytes(buffer);
-                    self.poi.set_map_id(Some(map_id));
-
-                    // Calculate the number of coordinates in the file
-                    let coord_size = std::mem::size_of::<TrailData>();
-                    let mut buffer = Vec::new();
-                    file.read_to_end(&mut buffer)?;
-                    let num_coords = buffer.len() / coord_size;
-
-                    // Read data from the buffer into the vector of structs
-                    for i in 0..num_coords {
-                        let offset = i * coord_size;
-                        let mut cursor = std::io::Cursor::new(&buffer[offset..offset + coord_size]);
-
-                        let x = cursor.read_f32::<LittleEndian>()?;
-                        let y = cursor.read_f32::<LittleEndian>()?;
-                        let z = cursor.read_f32::<LittleEndian>()?;
-                        let pos = Vec3::new(x, y, z);
-
-                        #[cfg(not(feature = "custom_projection"))]
-                        let trail = TrailData::from(pos.as_gw2_coordinate());
-                        #[cfg(feature = "custom_projection")]
-                        let trail = TrailData::from(pos);
-                        self.trail_data.push(trail);
-                    }
-                }
-            }
-            Err(e) => error!("Failed to load trail data: {}", e),
+    /// Reads `trailData` from the pack the trail is defined in
+    pub fn load_map_trail(&mut self, files: &PackFiles) -> Result<(), Box<dyn Error>> {
+        let data = files
+            .read(&self.trail_file.to_string_lossy())
+            .map_err(|e| format!("{}: {}", self.trail_file.display(), e))?;
+        if data.len() >= 8 {
+            let file = TrailFile::parse(&data)
+                .map_err(|e| format!("{}: {}", self.trail_file.display(), e))?;
+            self.set_trail_file(file);
         }
         Ok(())
     }
 
+    /// Takes the version, the map and the points of a `.trl` file
+    pub fn set_trail_file(&mut self, file: TrailFile) {
+        self.trail_version = Some(file.version);
+        self.poi.set_map_id(Some(file.map_id));
+        self.trail_data = file
+            .points
+            .into_iter()
+            .map(|pos| TrailData::from(convert_file_point(pos)))
+            .collect();
+    }
+
+    /// Whether the points were loaded from a `.trl` file
+    pub fn has_trail_file(&self) -> bool {
+        self.trail_version.is_some()
+    }
+
+    /// The `.trl` file of the trail, with the points as they are stored in the file
+    pub fn get_trail_file(&self) -> TrailFile {
+        TrailFile {
+            version: self.trail_version.unwrap_or(TRAIL_FILE_VERSION),
+            map_id: self.poi.get_map_id().unwrap_or_default(),
+            points: self
+                .trail_data
+                .iter()
+                .map(|trail| convert_file_point(Vec3::new(trail.x, trail.y, trail.z)))
+                .collect(),
+            trailing_bytes: 0,
+        }
+    }
+
     fn get_perpendicular_point(p1: Vec3, p2: Vec3, distance: f32) -> (Vec3, Vec3) {
         let mut a = p1.z - p2.z;
         let mut b = p1.x - p2.x;
@@ -232,3 +288,62 @@ impl Vertex {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Vec3;
+    use proptest::prelude::*;
+
+    use super::{Trail, TrailFile, TRAIL_FILE_VERSION};
+
+    fn trail_file() -> impl Strategy<Value = TrailFile> {
+        (
+            any::<u32>(),
+            any::<u32>(),
+            proptest::collection::vec(
+                proptest::array::uniform3(-1e5f32..1e5f32).prop_map(Vec3::from_array),
+                0..64,
+            ),
+        )
+            .prop_map(|(version, map_id, points)| TrailFile {
+                version,
+                map_id,
+                points,
+                trailing_bytes: 0,
+            })
+    }
+
+    #[test]
+    fn test_trail_file_layout() {
+        let file = TrailFile {
+            version: 0,
+            map_id: 50,
+            points: vec![Vec3::new(1.0, 2.0, 3.0)],
+            trailing_bytes: 0,
+        };
+        let data = file.to_bytes();
+        assert_eq!(data.len(), 20);
+        assert_eq!(&data[..8], &[0, 0, 0, 0, 50, 0, 0, 0]);
+        assert_eq!(&data[16..], &3.0f32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_new_trail_file() {
+        let file = Trail::default().get_trail_file();
+        assert_eq!(file.version, TRAIL_FILE_VERSION);
+        assert!(file.points.is_empty());
+    }
+
+    proptest! {
+        #[test]
+        fn trail_file_round_trip(file in trail_file()) {
+            let data = file.to_bytes();
+            prop_assert_eq!(TrailFile::parse(&data).unwrap(), file.clone());
+
+            // The points are converted for drawing and back for writing
+            let mut trail = Trail::default();
+            trail.set_trail_file(file.clone());
+            prop_assert_eq!(trail.get_trail_file().to_bytes(), data);
+        }
+    }
+}
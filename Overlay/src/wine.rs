@@ -0,0 +1,337 @@
+//! Finds out how the game was started, so the helper runs in the same wine environment.
+
+use std::{
+    collections::HashMap,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+/// Variables that only make sense for the process they were set for
+const ENVS_TO_REMOVE: &[&str] = &[
+    "WINESERVERSOCKET",
+    "WINELOADERNOEXEC",
+    "WINEPRELOADRESERVE",
+    "LD_PRELOAD",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WineFlavor {
+    Proton,
+    Lutris,
+    Bottles,
+    Wine,
+}
+
+/// Everything needed to start a process next to the game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WineEnvironment {
+    pub flavor: WineFlavor,
+    pub wine: PathBuf,
+    pub prefix: PathBuf,
+    /// Socket of the wineserver the game uses. `None` if the prefix isn't visible to us
+    pub server_socket: Option<PathBuf>,
+    /// Id of the flatpak sandbox the game runs in
+    pub flatpak_id: Option<String>,
+    /// The root directory of the game as seen by the overlay. Differs from `/` inside of
+    /// a flatpak sandbox
+    pub root: PathBuf,
+    pub env: HashMap<String, String>,
+}
+
+/// Replaces a leading `~` with `home`
+pub fn expand_home(path: &str, home: &Path) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some("") => home.to_path_buf(),
+        Some(rest) if rest.starts_with('/') => home.join(&rest[1..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// The socket wine creates for `prefix`. It is named after the device and inode of the prefix
+pub fn get_server_socket(prefix: &Path, uid: u32) -> Option<PathBuf> {
+    let metadata = std::fs::metadata(prefix).ok()?;
+    Some(PathBuf::from(format!(
+        "/tmp/.wine-{}/server-{:x}-{:x}/socket",
+        uid,
+        metadata.dev(),
+        metadata.ino()
+    )))
+}
+
+/// Parses the NUL separated `KEY=value` pairs of `/proc/<pid>/environ`
+pub fn parse_environ(data: &str) -> HashMap<String, String> {
+    data.split('\0')
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+fn parse_uid(status: &str) -> Option<u32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// The wine next to the preloader the game was started with, e.g. `.../bin/wine64-preloader`
+fn wine_next_to(exe: &Path) -> Option<PathBuf> {
+    let name = exe.file_name()?.to_str()?;
+    let wine = name.strip_suffix("-preloader")?;
+    Some(exe.with_file_name(wine))
+}
+
+fn find_proton_wine(env: &HashMap<String, String>) -> Option<PathBuf> {
+    let tool = env.get("STEAM_COMPAT_TOOL_PATHS")?.split(':').next()?;
+    ["files/bin/wine64", "dist/bin/wine64"]
+        .iter()
+        .map(|bin| Path::new(tool).join(bin))
+        .find(|wine| wine.exists())
+        .or_else(|| Some(Path::new(tool).join("files/bin/wine64")))
+}
+
+impl WineEnvironment {
+    /// Reads the environment of a running process
+    pub fn from_pid(pid: i32) -> std::io::Result<Self> {
+        Self::from_proc_dir(Path::new(&format!("/proc/{}", pid)))
+    }
+
+    /// Reads `environ`, `cmdline`, `exe` and `status` of a `/proc/<pid>` like directory
+    pub fn from_proc_dir(dir: &Path) -> std::io::Result<Self> {
+        let env = parse_environ(&std::fs::read_to_string(dir.join("environ"))?);
+        let cmdline: Vec<String> = std::fs::read_to_string(dir.join("cmdline"))
+            .unwrap_or_default()
+            .split('\0')
+            .filter(|arg| !arg.is_empty())
+            .map(str::to_string)
+            .collect();
+        let exe = std::fs::read_link(dir.join("exe")).ok();
+        let uid = std::fs::read_to_string(dir.join("status"))
+            .ok()
+            .and_then(|status| parse_uid(&status))
+            .unwrap_or_else(|| nix::unistd::getuid().as_raw());
+        let home = env
+            .get("HOME")
+            .cloned()
+            .or_else(|| std::env::var("HOME").ok())
+            .unwrap_or_default();
+        let mut wine = Self::resolve(env, &cmdline, exe.as_deref(), Path::new(&home), uid);
+        if wine.flatpak_id.is_some() {
+            wine.root = dir.join("root");
+        }
+        Ok(wine)
+    }
+
+    /// Detects the flavor from the environment, the arguments and the executable of the game
+    pub fn resolve(
+        mut env: HashMap<String, String>,
+        cmdline: &[String],
+        exe: Option<&Path>,
+        home: &Path,
+        uid: u32,
+    ) -> Self {
+        env.retain(|key, _| !ENVS_TO_REMOVE.contains(&key.as_str()));
+        let get = |key: &str| env.get(key).filter(|value| !value.is_empty());
+        let prefix_env = get("WINEPREFIX").map(|prefix| expand_home(prefix, home));
+        let wine_env = get("WINE").map(|wine| expand_home(wine, home));
+        // Wine starts the game through its preloader. The first argument can be it as well
+        let preloader = exe
+            .map(Path::to_path_buf)
+            .into_iter()
+            .chain(cmdline.first().map(PathBuf::from))
+            .find_map(|exe| wine_next_to(&exe));
+        let path_contains = |path: &Option<PathBuf>, part: &str| {
+            path.as_ref()
+                .is_some_and(|path| path.to_string_lossy().contains(part))
+        };
+
+        let (flavor, wine, prefix) = if let Some(compat_data) = get("STEAM_COMPAT_DATA_PATH") {
+            let prefix = prefix_env.unwrap_or_else(|| expand_home(compat_data, home).join("pfx"));
+            let wine = preloader
+                .or_else(|| find_proton_wine(&env))
+                .unwrap_or_else(|| PathBuf::from("wine64"));
+            (WineFlavor::Proton, wine, prefix)
+        } else if get("LUTRIS_GAME_UUID").is_some() || path_contains(&wine_env, "/lutris/runners/")
+        {
+            let wine = wine_env
+                .or(preloader)
+                .unwrap_or_else(|| PathBuf::from("wine"));
+            let prefix = prefix_env.unwrap_or_else(|| home.join(".wine"));
+            (WineFlavor::Lutris, wine, prefix)
+        } else if get("BOTTLE_NAME").is_some()
+            || path_contains(&prefix_env, "/bottles/bottles/")
+            || path_contains(&preloader, "/bottles/runners/")
+        {
+            let wine = preloader
+                .or(wine_env)
+                .unwrap_or_else(|| PathBuf::from("wine"));
+            let prefix = prefix_env.unwrap_or_else(|| home.join(".wine"));
+            (WineFlavor::Bottles, wine, prefix)
+        } else {
+            let wine = wine_env
+                .or(preloader)
+                .unwrap_or_else(|| PathBuf::from("wine"));
+            let prefix = prefix_env.unwrap_or_else(|| home.join(".wine"));
+            (WineFlavor::Wine, wine, prefix)
+        };
+
+        env.insert(
+            "WINEPREFIX".to_string(),
+            prefix.to_string_lossy().to_string(),
+        );
+        Self {
+            flavor,
+            server_socket: get_server_socket(&prefix, uid),
+            flatpak_id: env.get("FLATPAK_ID").cloned(),
+            root: PathBuf::from("/"),
+            wine,
+            prefix,
+            env,
+        }
+    }
+
+    /// The directory the bridge is written to, first as seen by the overlay and then by
+    /// wine. Flatpak games don't see the `/tmp` of the host, so it goes into their prefix
+    pub fn get_bridge_dir(&self) -> (PathBuf, PathBuf) {
+        match &self.flatpak_id {
+            Some(_) => {
+                let relative = self.prefix.strip_prefix("/").unwrap_or(&self.prefix);
+                (self.root.join(relative), self.prefix.clone())
+            }
+            None => (std::env::temp_dir(), std::env::temp_dir()),
+        }
+    }
+
+    /// The program and its arguments to run `args` with this wine. Flatpak games are
+    /// entered through their sandbox, their paths are only valid inside of it
+    pub fn command(&self, args: &[&str]) -> (String, Vec<String>) {
+        let wine = self.wine.to_string_lossy().to_string();
+        let args = args.iter().map(|arg| arg.to_string());
+        match &self.flatpak_id {
+            Some(id) => (
+                "flatpak".to_string(),
+                ["run".to_string(), format!("--command={}", wine), id.clone()]
+                    .into_iter()
+                    .chain(args)
+                    .collect(),
+            ),
+            None => (wine, args.collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{expand_home, get_server_socket, WineEnvironment, WineFlavor};
+
+    fn fixture(name: &str) -> WineEnvironment {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/wine")
+            .join(name);
+        WineEnvironment::from_proc_dir(&dir).unwrap()
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let home = Path::new("/home/user");
+        assert_eq!(expand_home("~", home), PathBuf::from("/home/user"));
+        assert_eq!(
+            expand_home("~/.wine", home),
+            PathBuf::from("/home/user/.wine")
+        );
+        assert_eq!(expand_home("~other/x", home), PathBuf::from("~other/x"));
+        assert_eq!(expand_home("/opt/wine", home), PathBuf::from("/opt/wine"));
+    }
+
+    #[test]
+    fn test_plain_wine() {
+        let env = fixture("wine");
+        assert_eq!(env.flavor, WineFlavor::Wine);
+        assert_eq!(env.wine, PathBuf::from("/usr/bin/wine64"));
+        // `~` of the game, not of the overlay
+        assert_eq!(env.prefix, PathBuf::from("/home/user/Games/gw2"));
+        assert_eq!(env.env["WINEPREFIX"], "/home/user/Games/gw2");
+        assert!(!env.env.contains_key("LD_PRELOAD"));
+        assert_eq!(env.server_socket, None);
+        assert_eq!(env.flatpak_id, None);
+        assert_eq!(env.root, PathBuf::from("/"));
+        assert_eq!(
+            env.get_bridge_dir(),
+            (std::env::temp_dir(), std::env::temp_dir())
+        );
+    }
+
+    #[test]
+    fn test_proton() {
+        let env = fixture("proton");
+        assert_eq!(env.flavor, WineFlavor::Proton);
+        assert_eq!(
+            env.wine,
+            PathBuf::from("/home/user/.steam/steam/steamapps/common/Proton 8.0/dist/bin/wine64")
+        );
+        assert_eq!(
+            env.prefix,
+            PathBuf::from("/home/user/.steam/steam/steamapps/compatdata/1284210/pfx")
+        );
+    }
+
+    #[test]
+    fn test_lutris() {
+        let env = fixture("lutris");
+        assert_eq!(env.flavor, WineFlavor::Lutris);
+        assert_eq!(
+            env.wine,
+            PathBuf::from(
+                "/home/user/.local/share/lutris/runners/wine/lutris-GE-Proton8-14-x86_64/bin/wine"
+            )
+        );
+        assert_eq!(env.prefix, PathBuf::from("/home/user/Games/guild-wars-2"));
+    }
+
+    #[test]
+    fn test_bottles_flatpak() {
+        let env = fixture("bottles");
+        assert_eq!(env.flavor, WineFlavor::Bottles);
+        assert_eq!(
+            env.wine,
+            PathBuf::from("/var/data/bottles/runners/soda-7.0-9/bin/wine64")
+        );
+        assert_eq!(env.prefix, PathBuf::from("/var/data/bottles/bottles/GW2"));
+        assert_eq!(env.flatpak_id.as_deref(), Some("com.usebottles.bottles"));
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/wine/bottles/root");
+        assert_eq!(env.root, root);
+        assert_eq!(
+            env.get_bridge_dir(),
+            (
+                root.join("var/data/bottles/bottles/GW2"),
+                PathBuf::from("/var/data/bottles/bottles/GW2")
+            )
+        );
+        let (program, args) = env.command(&["bridge.exe", "7070"]);
+        assert_eq!(program, "flatpak");
+        assert_eq!(
+            args,
+            vec![
+                "run",
+                "--command=/var/data/bottles/runners/soda-7.0-9/bin/wine64",
+                "com.usebottles.bottles",
+                "bridge.exe",
+                "7070"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_server_socket() {
+        let prefix = tempfile::tempdir().unwrap();
+        let socket = get_server_socket(prefix.path(), 1000).unwrap();
+        let socket = socket.to_string_lossy();
+        assert!(socket.starts_with("/tmp/.wine-1000/server-"));
+        assert!(socket.ends_with("/socket"));
+        assert_eq!(get_server_socket(Path::new("/does/not/exist"), 1000), None);
+    }
+}
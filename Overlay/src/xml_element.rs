@@ -0,0 +1,577 @@
+//! Element tree of a marker file that remembers where every element starts, so broken
+//! elements can be reported and skipped on their own.
+
+use std::{
+    collections::HashMap,
+    io::{BufReader, Read, Write},
+};
+
+use serde::{
+    de::{value, DeserializeOwned, Error, IntoDeserializer, Visitor},
+    forward_to_deserialize_any,
+    ser::{self, Impossible, SerializeMap, SerializeStruct},
+    Deserializer, Serialize, Serializer,
+};
+use xml::{
+    common::Position,
+    reader::XmlEvent,
+    writer::{self, EmitterConfig},
+    EventReader, EventWriter,
+};
+
+use crate::diagnostics::Diagnostics;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct XmlElement {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<XmlElement>,
+    /// Position of the start tag, starting at 0
+    pub line: u64,
+    pub column: u64,
+}
+
+fn close_element(stack: &mut Vec<XmlElement>, root: &mut Option<XmlElement>) {
+    let Some(element) = stack.pop() else {
+        return;
+    };
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(element),
+        None => *root = Some(element),
+    }
+}
+
+impl XmlElement {
+    /// An element with the fields of `value` as attributes, see [`serialize_attributes`]
+    pub fn from_value<T: Serialize>(name: &str, value: &T) -> Result<Self, value::Error> {
+        Ok(Self {
+            name: name.to_string(),
+            attributes: serialize_attributes(value)?,
+            ..Default::default()
+        })
+    }
+
+    /// Reads the root element. A syntax error ends the document where it occurs, the
+    /// elements before it are kept
+    pub fn read(reader: impl Read, diagnostics: &mut Diagnostics) -> Option<Self> {
+        let mut events = EventReader::new(BufReader::new(reader));
+        let mut stack: Vec<XmlElement> = vec![];
+        let mut root = None;
+        loop {
+            match events.next() {
+                Ok(XmlEvent::StartElement {
+                    name, attributes, ..
+                }) => {
+                    let position = events.position();
+                    stack.push(XmlElement {
+                        name: name.local_name,
+                        attributes: attributes
+                            .into_iter()
+                            .map(|attribute| (attribute.name.local_name, attribute.value))
+                            .collect(),
+                        children: vec![],
+                        line: position.row,
+                        column: position.column,
+                    });
+                }
+                Ok(XmlEvent::EndElement { .. }) => close_element(&mut stack, &mut root),
+                Ok(XmlEvent::EndDocument) => break,
+                Ok(_) => (),
+                Err(e) => {
+                    let path: Vec<&str> =
+                        stack.iter().map(|element| element.name.as_str()).collect();
+                    let position = e.position();
+                    diagnostics.record(position.row, position.column, &path.join("/"), e.msg());
+                    while !stack.is_empty() {
+                        close_element(&mut stack, &mut root);
+                    }
+                    break;
+                }
+            }
+        }
+        root
+    }
+
+    /// The children with their element path, e.g. `OverlayData/POIs/POI[2]`
+    pub fn get_children<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> impl Iterator<Item = (String, &'a XmlElement)> + 'a {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        self.children.iter().map(move |child| {
+            let index = counts.entry(child.name.as_str()).or_default();
+            *index += 1;
+            (format!("{}/{}[{}]", path, child.name, index), child)
+        })
+    }
+
+    /// Builds `T` from the attributes. On failure every invalid attribute is recorded. Every
+    /// field of `T` needs a default, so each attribute can be tried on its own
+    pub fn deserialize<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        diagnostics: &mut Diagnostics,
+    ) -> Option<T> {
+        let error = match deserialize_attributes::<T>(&self.attributes) {
+            Ok(value) => return Some(value),
+            Err(e) => e,
+        };
+        // Flattened fields hide which attribute failed, so each one is tried on its own
+        let mut found = false;
+        for attribute in &self.attributes {
+            if let Err(e) = deserialize_attributes::<T>(std::slice::from_ref(attribute)) {
+                let element = format!("{}/@{}", path, attribute.0);
+                diagnostics.record(self.line, self.column, &element, e.into_inner());
+                found = true;
+            }
+        }
+        if !found {
+            diagnostics.record(self.line, self.column, path, error);
+        }
+        None
+    }
+
+    /// Writes the element as the root of an XML document
+    pub fn write(&self, writer: impl Write) -> writer::Result<()> {
+        let mut events = EmitterConfig::new()
+            .perform_indent(true)
+            .create_writer(writer);
+        self.write_events(&mut events)
+    }
+
+    fn write_events<W: Write>(&self, events: &mut EventWriter<W>) -> writer::Result<()> {
+        let start = self.attributes.iter().fold(
+            writer::XmlEvent::start_element(self.name.as_str()),
+            |start, (name, value)| start.attr(name.as_str(), value),
+        );
+        events.write(start)?;
+        for child in &self.children {
+            child.write_events(events)?;
+        }
+        events.write(writer::XmlEvent::end_element())
+    }
+}
+
+/// Deserializes a struct from the attributes of an element. The values are parsed from their
+/// text, like `xpos="1.5"`
+pub fn deserialize_attributes<T: DeserializeOwned>(
+    attributes: &[(String, String)],
+) -> Result<T, serde_path_to_error::Error<value::Error>> {
+    let attributes = attributes
+        .iter()
+        .map(|(name, value)| (name.as_str(), AttributeValue(value)));
+    serde_path_to_error::deserialize(value::MapDeserializer::new(attributes))
+}
+
+struct AttributeValue<'a>(&'a str);
+
+impl<'de, 'a> IntoDeserializer<'de, value::Error> for AttributeValue<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($method: ident, $visit: ident) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            let value = self.0.trim().parse().map_err(value::Error::custom)?;
+            visitor.$visit(value)
+        }
+    };
+}
+
+impl<'de, 'a> Deserializer<'de> for AttributeValue<'a> {
+    type Error = value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str(self.0)
+    }
+
+    /// A present attribute always has a value
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0.trim().to_lowercase().as_str() {
+            "1" | "true" => visitor.visit_bool(true),
+            "0" | "false" => visitor.visit_bool(false),
+            _ => Err(value::Error::custom(format!("{} is not a boolean", self.0))),
+        }
+    }
+
+    deserialize_parsed!(deserialize_i8, visit_i8);
+    deserialize_parsed!(deserialize_i16, visit_i16);
+    deserialize_parsed!(deserialize_i32, visit_i32);
+    deserialize_parsed!(deserialize_i64, visit_i64);
+    deserialize_parsed!(deserialize_u8, visit_u8);
+    deserialize_parsed!(deserialize_u16, visit_u16);
+    deserialize_parsed!(deserialize_u32, visit_u32);
+    deserialize_parsed!(deserialize_u64, visit_u64);
+    deserialize_parsed!(deserialize_f32, visit_f32);
+    deserialize_parsed!(deserialize_f64, visit_f64);
+
+    forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Serializes a struct to the attributes of an element, the reverse of
+/// [`deserialize_attributes`]. `None` fields are left out
+pub fn serialize_attributes<T: Serialize>(
+    value: &T,
+) -> Result<Vec<(String, String)>, value::Error> {
+    let mut serializer = AttributeSerializer::default();
+    value.serialize(&mut serializer)?;
+    Ok(serializer.attributes)
+}
+
+macro_rules! unsupported {
+    ($($method: ident($($arg: ty),*) -> $ok: ty;)*) => {
+        $(fn $method(self, $(_: $arg),*) -> Result<$ok, Self::Error> {
+            Err(ser::Error::custom(concat!(
+                "can't be written as an attribute: ",
+                stringify!($method)
+            )))
+        })*
+    };
+}
+
+/// Collects the fields of a struct, flattened fields are serialized as map entries
+#[derive(Default)]
+struct AttributeSerializer {
+    attributes: Vec<(String, String)>,
+    key: Option<String>,
+}
+
+impl AttributeSerializer {
+    fn push<T: ?Sized + Serialize>(&mut self, name: String, value: &T) -> Result<(), value::Error> {
+        if let Some(text) = value.serialize(ValueSerializer)? {
+            self.attributes.push((name, text));
+        }
+        Ok(())
+    }
+}
+
+impl Serializer for &mut AttributeSerializer {
+    type Ok = ();
+    type Error = value::Error;
+    type SerializeSeq = Impossible<(), value::Error>;
+    type SerializeTuple = Impossible<(), value::Error>;
+    type SerializeTupleStruct = Impossible<(), value::Error>;
+    type SerializeTupleVariant = Impossible<(), value::Error>;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), value::Error>;
+
+    unsupported! {
+        serialize_bool(bool) -> ();
+        serialize_i8(i8) -> ();
+        serialize_i16(i16) -> ();
+        serialize_i32(i32) -> ();
+        serialize_i64(i64) -> ();
+        serialize_u8(u8) -> ();
+        serialize_u16(u16) -> ();
+        serialize_u32(u32) -> ();
+        serialize_u64(u64) -> ();
+        serialize_f32(f32) -> ();
+        serialize_f64(f64) -> ();
+        serialize_char(char) -> ();
+        serialize_str(&str) -> ();
+        serialize_bytes(&[u8]) -> ();
+        serialize_none() -> ();
+        serialize_unit() -> ();
+        serialize_unit_struct(&'static str) -> ();
+        serialize_unit_variant(&'static str, u32, &'static str) -> ();
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: &T,
+    ) -> Result<(), Self::Error> {
+        Err(ser::Error::custom(format!(
+            "can't be written as attributes: {}",
+            variant
+        )))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self, Self::Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self, Self::Error> {
+        Ok(self)
+    }
+}
+
+impl SerializeStruct for &mut AttributeSerializer {
+    type Ok = ();
+    type Error = value::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.push(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl SerializeMap for &mut AttributeSerializer {
+    type Ok = ();
+    type Error = value::Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
+        self.key = key.serialize(ValueSerializer)?;
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| <value::Error as ser::Error>::custom("attribute without a name"))?;
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+macro_rules! serialize_display {
+    ($($method: ident($type: ty);)*) => {
+        $(fn $method(self, value: $type) -> Result<Option<String>, Self::Error> {
+            Ok(Some(value.to_string()))
+        })*
+    };
+}
+
+/// The text of one attribute, `None` leaves the attribute out. Booleans are written as
+/// `1`/`0` like packs do
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = Option<String>;
+    type Error = value::Error;
+    type SerializeSeq = Impossible<Option<String>, value::Error>;
+    type SerializeTuple = Impossible<Option<String>, value::Error>;
+    type SerializeTupleStruct = Impossible<Option<String>, value::Error>;
+    type SerializeTupleVariant = Impossible<Option<String>, value::Error>;
+    type SerializeMap = Impossible<Option<String>, value::Error>;
+    type SerializeStruct = Impossible<Option<String>, value::Error>;
+    type SerializeStructVariant = Impossible<Option<String>, value::Error>;
+
+    serialize_display! {
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_char(char);
+        serialize_str(&str);
+    }
+
+    unsupported! {
+        serialize_bytes(&[u8]) -> Self::Ok;
+        serialize_unit() -> Self::Ok;
+        serialize_unit_struct(&'static str) -> Self::Ok;
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+
+    fn serialize_bool(self, value: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(if value { "1" } else { "0" }.to_string()))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(ser::Error::custom(format!(
+            "can't be written as an attribute: {}",
+            variant
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{serialize_attributes, XmlElement};
+    use crate::diagnostics::Diagnostics;
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Marker {
+        #[serde(default)]
+        xpos: f32,
+        visible: Option<bool>,
+        name: Option<String>,
+    }
+
+    #[test]
+    fn test_read() {
+        let mut diagnostics = Diagnostics::new(Path::new("markers.xml"));
+        let root = XmlElement::read(
+            "<OverlayData>\n  <POIs>\n    <POI xpos=\"1.5\" visible=\"0\"/>\n    <POI xpos=\"a\"/>\n    <Trail/><POI/>\n  </POIs>\n</OverlayData>".as_bytes(),
+            &mut diagnostics,
+        )
+        .unwrap();
+        let pois = &root.children[0];
+        let children: Vec<_> = pois.get_children("OverlayData/POIs").collect();
+        assert_eq!(children[1].0, "OverlayData/POIs/POI[2]");
+        assert_eq!(children[3].0, "OverlayData/POIs/POI[3]");
+        assert_eq!((children[1].1.line, children[1].1.column), (3, 4));
+
+        let marker: Marker = children[0]
+            .1
+            .deserialize(&children[0].0, &mut diagnostics)
+            .unwrap();
+        assert_eq!(
+            marker,
+            Marker {
+                xpos: 1.5,
+                visible: Some(false),
+                name: None
+            }
+        );
+        assert!(children[1]
+            .1
+            .deserialize::<Marker>(&children[1].0, &mut diagnostics)
+            .is_none());
+        let diagnostics = diagnostics.finish(()).diagnostics;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].element, "OverlayData/POIs/POI[2]/@xpos");
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (4, 5));
+    }
+
+    #[test]
+    fn test_read_broken() {
+        let mut diagnostics = Diagnostics::new(Path::new("broken.xml"));
+        let root = XmlElement::read(
+            "<OverlayData><POIs><POI xpos=\"1\"/><POI".as_bytes(),
+            &mut diagnostics,
+        )
+        .unwrap();
+        // The elements before the error are kept
+        assert_eq!(root.children[0].children.len(), 1);
+        let diagnostics = diagnostics.finish(()).diagnostics;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].element, "OverlayData/POIs");
+
+        let mut diagnostics = Diagnostics::new(Path::new("empty.xml"));
+        assert!(XmlElement::read("".as_bytes(), &mut diagnostics).is_none());
+        assert_eq!(diagnostics.finish(()).diagnostics.len(), 1);
+    }
+
+    #[derive(Serialize)]
+    struct Trail {
+        #[serde(rename = "trailData")]
+        trail_data: String,
+        #[serde(flatten)]
+        marker: Marker,
+    }
+
+    #[test]
+    fn test_write() {
+        let trail = Trail {
+            trail_data: "a.trl".to_string(),
+            marker: Marker {
+                xpos: 1.5,
+                visible: Some(true),
+                name: None,
+            },
+        };
+        assert_eq!(
+            serialize_attributes(&trail).unwrap(),
+            vec![
+                ("trailData".to_string(), "a.trl".to_string()),
+                ("xpos".to_string(), "1.5".to_string()),
+                ("visible".to_string(), "1".to_string()),
+            ]
+        );
+        assert!(serialize_attributes(&1.5).is_err());
+
+        let mut root = XmlElement::from_value("Trail", &trail).unwrap();
+        root.attributes
+            .push(("info".to_string(), "<\"&'>".to_string()));
+        root.children
+            .push(XmlElement::from_value("Marker", &trail.marker).unwrap());
+        let mut written = vec![];
+        root.write(&mut written).unwrap();
+
+        let mut diagnostics = Diagnostics::new(Path::new("written.xml"));
+        let read = XmlElement::read(written.as_slice(), &mut diagnostics).unwrap();
+        assert_eq!(read.attributes, root.attributes);
+        assert_eq!(read.children[0].attributes, root.children[0].attributes);
+        assert_eq!(read.children[0].name, "Marker");
+    }
+}
@@ -20,13 +20,76 @@ use xcb::{
     Xid,
 };
 
+use bevy_window::{Window, WindowPosition};
 use std::{sync::Arc, thread};
 
-pub fn create_window() -> (RawDisplayHandle, RawWindowHandle) {
-    let x = 1680;
-    let y = 0;
-    let w = 1920;
-    let h = 1080;
+/// Position and size of an overlay window in pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowGeometry {
+    /// Left edge on the X screen
+    pub x: i32,
+    /// Top edge on the X screen
+    pub y: i32,
+    /// Width in physical pixels
+    pub width: u32,
+    /// Height in physical pixels
+    pub height: u32,
+}
+
+impl From<&Window> for WindowGeometry {
+    fn from(window: &Window) -> Self {
+        let (x, y) = match window.position {
+            WindowPosition::At(position) => (position.x, position.y),
+            _ => (0, 0),
+        };
+        Self {
+            x,
+            y,
+            width: window.resolution.physical_width(),
+            height: window.resolution.physical_height(),
+        }
+    }
+}
+
+/// The X window the winit window is embedded in
+pub struct OverlayWindow {
+    conn: Arc<xcb::Connection>,
+    window: x::Window,
+}
+
+impl std::fmt::Debug for OverlayWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OverlayWindow")
+            .field("window", &self.window)
+            .finish()
+    }
+}
+
+impl OverlayWindow {
+    /// Moves and resizes the X window
+    pub fn set_geometry(&self, geometry: WindowGeometry) {
+        self.conn.send_request(&x::ConfigureWindow {
+            window: self.window,
+            value_list: &[
+                x::ConfigWindow::X(geometry.x),
+                x::ConfigWindow::Y(geometry.y),
+                x::ConfigWindow::Width(geometry.width),
+                x::ConfigWindow::Height(geometry.height),
+            ],
+        });
+        if let Err(e) = self.conn.flush() {
+            println!("Failed to move the overlay window: {:?}", e);
+        }
+    }
+}
+
+/// Creates the transparent, click through X window for the overlay
+pub fn create_window(
+    geometry: WindowGeometry,
+) -> (RawDisplayHandle, RawWindowHandle, OverlayWindow) {
+    let WindowGeometry { x, y, .. } = geometry;
+    let w = geometry.width as u16;
+    let h = geometry.height as u16;
 
     let mut base_event_mask = EventMask::empty();
     //base_event_mask.set(EventMask::EXPOSURE, true);
@@ -83,8 +146,8 @@ pub fn create_window() -> (RawDisplayHandle, RawWindowHandle) {
         depth: depth.depth(),
         wid: window,
         parent: screen.root(),
-        x,
-        y,
+        x: x as i16,
+        y: y as i16,
         width: w,
         height: h,
         border_width: 0,
@@ -153,5 +216,6 @@ pub fn create_window() -> (RawDisplayHandle, RawWindowHandle) {
     (
         RawDisplayHandle::Xcb(display_handle),
         RawWindowHandle::Xcb(window_handle),
+        OverlayWindow { conn, window },
     )
 }
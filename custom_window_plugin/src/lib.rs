@@ -15,8 +15,9 @@ mod winit_windows;
 
 use bevy_ecs::system::SystemState;
 use bevy_tasks::tick_global_task_pools_on_main_thread;
-use system::{create_window, CachedWindow};
+use system::{changed_window, create_window, CachedWindow};
 
+pub use custom_window::{OverlayWindow, WindowGeometry};
 pub use winit_config::*;
 pub use winit_windows::*;
 
@@ -49,7 +50,8 @@ impl Plugin for WinitPlugin {
 
         app.init_non_send_resource::<WinitWindows>()
             .init_resource::<WinitSettings>()
-            .set_runner(winit_runner);
+            .set_runner(winit_runner)
+            .add_systems(Last, changed_window);
 
         let mut create_window_system_state: SystemState<(
             Commands,
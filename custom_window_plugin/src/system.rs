@@ -1,8 +1,8 @@
 use bevy_ecs::{
     entity::Entity,
     event::EventWriter,
-    prelude::{Component, Resource},
-    system::{Commands, NonSendMut},
+    prelude::{Changed, Component, Resource},
+    system::{Commands, NonSend, NonSendMut, Query},
     world::Mut,
 };
 use bevy_utils::{tracing::info, HashMap};
@@ -11,7 +11,7 @@ use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 
 use winit::event_loop::EventLoopWindowTarget;
 
-use crate::{converters::convert_winit_theme, WinitWindows};
+use crate::{converters::convert_winit_theme, custom_window::WindowGeometry, WinitWindows};
 
 /// System responsible for creating new windows whenever a [`Window`] component is added
 /// to an entity.
@@ -72,3 +72,18 @@ pub struct WindowTitleCache(HashMap<Entity, String>);
 pub struct CachedWindow {
     pub window: Window,
 }
+
+/// Moves and resizes the overlay when the position or resolution of its [`Window`] changes
+pub(crate) fn changed_window(
+    mut changed_windows: Query<(Entity, &Window, &mut CachedWindow), Changed<Window>>,
+    winit_windows: NonSend<WinitWindows>,
+) {
+    for (entity, window, mut cache) in &mut changed_windows {
+        let geometry = WindowGeometry::from(window);
+        if geometry != WindowGeometry::from(&cache.window) {
+            info!("Moving window {:?} to {:?}", entity, geometry);
+            winit_windows.set_geometry(entity, geometry);
+        }
+        cache.window = window.clone();
+    }
+}
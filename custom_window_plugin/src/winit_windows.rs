@@ -6,13 +6,13 @@ use bevy_utils::{tracing::warn, HashMap};
 use bevy_window::{CursorGrabMode, Window, WindowPosition, WindowResolution};
 
 use winit::{
-    dpi::{LogicalSize, PhysicalPosition},
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
     monitor::MonitorHandle,
 };
 
 use crate::{
     converters::{convert_window_level, convert_window_theme},
-    custom_window,
+    custom_window::{self, OverlayWindow, WindowGeometry},
 };
 
 /// A resource which maps window entities to [`winit`] library windows.
@@ -24,6 +24,8 @@ pub struct WinitWindows {
     pub entity_to_winit: HashMap<Entity, winit::window::WindowId>,
     /// Maps `winit` window identifiers to entities.
     pub winit_to_entity: HashMap<winit::window::WindowId, Entity>,
+    /// The X windows the `winit` windows are embedded in
+    pub overlay_windows: HashMap<Entity, OverlayWindow>,
 
     // Some winit functions, such as `set_window_icon` can only be used from the main thread. If
     // they are used in another thread, the app will hang. This marker ensures `WinitWindows` is
@@ -43,13 +45,15 @@ impl WinitWindows {
 
         // Due to a UIA limitation, winit windows need to be invisible for the
         // AccessKit adapter is initialized.
-        let (_my_display, my_window) = custom_window::create_window();
+        let geometry = WindowGeometry::from(window);
+        let (_my_display, my_window, overlay_window) = custom_window::create_window(geometry);
+        self.overlay_windows.insert(entity, overlay_window);
         winit_window_builder = unsafe {
             winit_window_builder
                 .with_transparent(true)
                 .with_decorations(false)
                 .with_maximized(true)
-                .with_inner_size(LogicalSize::new(1920, 1080))
+                .with_inner_size(PhysicalSize::new(geometry.width, geometry.height))
                 .with_parent_window(Some(my_window))
         };
 
@@ -100,6 +104,16 @@ impl WinitWindows {
             .and_then(|winit_id| self.windows.get(winit_id))
     }
 
+    /// Moves and resizes the overlay of `entity`
+    pub fn set_geometry(&self, entity: Entity, geometry: WindowGeometry) {
+        if let Some(overlay_window) = self.overlay_windows.get(&entity) {
+            overlay_window.set_geometry(geometry);
+        }
+        if let Some(winit_window) = self.get_window(entity) {
+            winit_window.set_inner_size(PhysicalSize::new(geometry.width, geometry.height));
+        }
+    }
+
     /// Get the entity associated with the winit window id.
     ///
     /// This is mostly just an intermediary step between us and winit.
@@ -111,6 +125,7 @@ impl WinitWindows {
     ///
     /// This should mostly just be called when the window is closing.
     pub fn remove_window(&mut self, entity: Entity) -> Option<winit::window::Window> {
+        self.overlay_windows.remove(&entity);
         let winit_id = self.entity_to_winit.remove(&entity)?;
         // Don't remove from winit_to_window_id, to track that we used to know about this winit window
         self.windows.remove(&winit_id)
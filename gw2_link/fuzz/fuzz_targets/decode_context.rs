@@ -0,0 +1,10 @@
+#![no_main]
+
+use gw2_link::{MumbleContext, MUMBLE_CONTEXT_SIZE};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(ctx) = MumbleContext::decode(data) {
+        assert_eq!(&ctx.encode()[..], &data[..MUMBLE_CONTEXT_SIZE]);
+    }
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use gw2_link::{protocol::Packet, LinkedMem, LinkedMemNet};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mem) = LinkedMemNet::decode(data) {
+        assert_eq!(&mem.encode()[..], data);
+        let mem = LinkedMem::from(mem);
+        let _ = mem.get_context();
+        let _ = mem.get_identity();
+    }
+    // Framed packets and the legacy format share one entry point
+    if let Ok(packet) = Packet::decode(data) {
+        let _ = packet.mem.get_context();
+    }
+});
@@ -0,0 +1,202 @@
+//! Records, inspects and replays MumbleLink sessions.
+//!
+//! ```text
+//! gw2link record <file> [--port <port>] [--shm <name>]
+//! gw2link inspect <file>
+//! gw2link replay <file> [--port <port>] [--loop]
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant},
+};
+
+use gw2_link::{
+    protocol::{Packet, PacketFlags},
+    record::{Recorder, RecordingReader, ReplaySource},
+    Error, GW2LinkBuilder, LinkSource,
+};
+
+const USAGE: &str = "Usage:
+    gw2link record <file> [--port <port>] [--shm <name>]
+    gw2link inspect <file>
+    gw2link replay <file> [--port <port>] [--loop]";
+
+struct Args {
+    command: String,
+    file: String,
+    port: u16,
+    shm: Option<String>,
+    looping: bool,
+}
+
+fn parse_args() -> Option<Args> {
+    let mut args = std::env::args().skip(1);
+    let command = args.next()?;
+    let file = args.next()?;
+    let mut parsed = Args {
+        command,
+        file,
+        port: gw2_link::DEFAULT_PORT,
+        shm: None,
+        looping: false,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => parsed.port = args.next()?.parse().ok()?,
+            "--shm" => parsed.shm = Some(args.next()?),
+            "--loop" => parsed.looping = true,
+            _ => return None,
+        }
+    }
+    Some(parsed)
+}
+
+fn print_progress(frames: usize) {
+    print!("\rRecorded {} frames", frames);
+    let _ = std::io::stdout().flush();
+}
+
+/// Records until the process is killed. Every frame is flushed so nothing is lost
+fn record(args: &Args) -> Result<(), Error> {
+    let mut recorder = Recorder::create(&args.file)?;
+    let builder = GW2LinkBuilder::new().port(args.port);
+    let mut frames = 0usize;
+    match &args.shm {
+        #[cfg(unix)]
+        Some(name) => {
+            let mut source = builder.shm_name(name).build_shm_source()?;
+            println!("Recording {} to {}", name, args.file);
+            loop {
+                match source.poll() {
+                    Some(mem) => {
+                        recorder.record(&mem)?;
+                        recorder.flush()?;
+                        frames += 1;
+                        print_progress(frames);
+                    }
+                    None => thread::sleep(Duration::from_millis(1)),
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        Some(_) => Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "--shm is only supported on unix",
+        ))),
+        None => {
+            let mut source = builder.build_udp_source()?;
+            println!("Recording port {} to {}", args.port, args.file);
+            loop {
+                match source.receive(true) {
+                    Ok(Some(mem)) => {
+                        recorder.record(&mem)?;
+                        recorder.flush()?;
+                        frames += 1;
+                        print_progress(frames);
+                    }
+                    Ok(None) => (),
+                    Err(e @ (Error::PacketSize { .. } | Error::OutOfOrder { .. })) => {
+                        println!("\n{}", e)
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+}
+
+fn inspect(args: &Args) -> Result<(), Error> {
+    let frames = RecordingReader::open(&args.file)?.read_all()?;
+    let (first, last) = match (frames.first(), frames.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            println!("{}: empty recording", args.file);
+            return Ok(());
+        }
+    };
+
+    let mut maps: BTreeMap<u32, usize> = BTreeMap::new();
+    for frame in &frames {
+        *maps
+            .entry(frame.mem.get_context().get_map_id())
+            .or_default() += 1;
+    }
+    let length = last.timestamp.saturating_sub(first.timestamp);
+    println!("{}", args.file);
+    println!("  frames:   {}", frames.len());
+    println!("  length:   {:.2}s", length.as_secs_f32());
+    if !length.is_zero() {
+        println!(
+            "  rate:     {:.1} frames/s",
+            frames.len() as f32 / length.as_secs_f32()
+        );
+    }
+    println!(
+        "  ui_tick:  {} - {}",
+        first.mem.get_ui_tick(),
+        last.mem.get_ui_tick()
+    );
+    for (map_id, count) in maps {
+        println!("  map {:>5}: {} frames", map_id, count);
+    }
+    Ok(())
+}
+
+/// Sends the recording like the helper would, so a running overlay picks it up unchanged
+fn replay(args: &Args) -> Result<(), Error> {
+    let mut source = ReplaySource::open(&args.file)?.looping(args.looping);
+    let socket = UdpSocket::bind("127.0.0.1:0").map_err(Error::SocketBind)?;
+    let target = SocketAddr::from((Ipv4Addr::LOCALHOST, args.port));
+    println!("Replaying {} to {}", args.file, target);
+
+    let start = Instant::now();
+    let mut sequence = 0u32;
+    while !source.is_finished() {
+        match source.poll() {
+            Some(mem) => {
+                let packet = Packet {
+                    sequence: Some(sequence),
+                    mem,
+                };
+                socket
+                    .send_to(&packet.encode(PacketFlags::empty()), target)
+                    .map_err(Error::Socket)?;
+                sequence = sequence.wrapping_add(1);
+            }
+            None => thread::sleep(Duration::from_millis(1)),
+        }
+    }
+    println!("Done after {:.2}s", start.elapsed().as_secs_f32());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Some(args) => args,
+        None => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    let result = match args.command.as_str() {
+        "record" => record(&args),
+        "inspect" => inspect(&args),
+        "replay" => replay(&args),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
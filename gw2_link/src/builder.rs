@@ -0,0 +1,130 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+#[cfg(unix)]
+use nix::unistd::getuid;
+
+use crate::{Error, UdpSource};
+#[cfg(unix)]
+use crate::{GW2Link, ShmSource};
+
+pub const DEFAULT_PORT: u16 = 7070;
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Configures where a [`GW2Link`] receives its data from and where it mirrors it to.
+///
+/// Running one link per game client only requires a different port and shm name per client:
+/// ```no_run
+/// let link = gw2_link::GW2Link::builder()
+///     .port(7071)
+///     .shm_name("/MumbleLink.second")
+///     .build()?;
+/// # Ok::<(), gw2_link::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct GW2LinkBuilder {
+    address: IpAddr,
+    port: u16,
+    shm_name: Option<String>,
+    timeout: Duration,
+}
+
+impl Default for GW2LinkBuilder {
+    fn default() -> Self {
+        Self {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: DEFAULT_PORT,
+            shm_name: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl GW2LinkBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Address the udp socket binds to. Defaults to `127.0.0.1`
+    pub fn address(mut self, address: IpAddr) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Port the helper sends its packets to. Defaults to `7070`
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Name of the POSIX shared memory the data is mirrored to. Defaults to `/MumbleLink.{uid}`
+    pub fn shm_name(mut self, shm_name: impl Into<String>) -> Self {
+        self.shm_name = Some(shm_name.into());
+        self
+    }
+
+    /// How long a blocking `update_gw2` waits for a packet. Defaults to 200ms
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn get_socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    pub fn get_shm_name(&self) -> String {
+        match &self.shm_name {
+            Some(name) => name.clone(),
+            #[cfg(unix)]
+            None => format!("/MumbleLink.{}", getuid()),
+            #[cfg(not(unix))]
+            None => "MumbleLink".to_string(),
+        }
+    }
+
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    #[cfg(unix)]
+    pub fn build(&self) -> Result<GW2Link, Error> {
+        GW2Link::from_builder(self)
+    }
+
+    /// Only receives the udp packets without mirroring them to the shared memory
+    pub fn build_udp_source(&self) -> Result<UdpSource, Error> {
+        UdpSource::from_builder(self)
+    }
+
+    /// Reads the shared memory `shm_name` directly instead of receiving udp packets
+    #[cfg(unix)]
+    pub fn build_shm_source(&self) -> Result<ShmSource, Error> {
+        ShmSource::open(&self.get_shm_name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::GW2LinkBuilder;
+
+    #[test]
+    fn test_builder_defaults() {
+        let builder = GW2LinkBuilder::new();
+        assert_eq!(builder.get_socket_addr().to_string(), "127.0.0.1:7070");
+        assert!(builder.get_shm_name().starts_with("/MumbleLink."));
+        assert_eq!(builder.get_timeout(), Duration::from_millis(200));
+
+        let builder = builder
+            .port(7071)
+            .shm_name("/MumbleLink.second")
+            .timeout(Duration::from_millis(50));
+        assert_eq!(builder.get_socket_addr().port(), 7071);
+        assert_eq!(builder.get_shm_name(), "/MumbleLink.second");
+        assert_eq!(builder.get_timeout(), Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,316 @@
+//! Explicit little endian encoding of the structs that cross process boundaries.
+//!
+//! Incoming bytes are never transmuted. Every field is read on its own, so truncated or
+//! garbage input results in an [`Error`] instead of undefined behavior.
+
+use crate::{Error, LinkedMem, LinkedMemNet, MumbleContext};
+
+pub const LINKED_MEM_NET_SIZE: usize = 1364;
+pub const MUMBLE_CONTEXT_SIZE: usize = 85;
+pub const CONTEXT_CAPACITY: usize = 256;
+
+pub(crate) struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub(crate) fn bytes<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let end = self.pos + N;
+        let slice = self.data.get(self.pos..end).ok_or(Error::Truncated {
+            got: self.data.len(),
+            expected: end,
+        })?;
+        self.pos = end;
+        let mut out = [0u8; N];
+        out.copy_from_slice(slice);
+        Ok(out)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.bytes()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    pub(crate) fn f32(&mut self) -> Result<f32, Error> {
+        Ok(f32::from_le_bytes(self.bytes()?))
+    }
+
+    pub(crate) fn f32_array<const N: usize>(&mut self) -> Result<[f32; N], Error> {
+        let mut out = [0.0; N];
+        for value in out.iter_mut() {
+            *value = self.f32()?;
+        }
+        Ok(out)
+    }
+
+    pub(crate) fn u16_array<const N: usize>(&mut self) -> Result<[u16; N], Error> {
+        let mut out = [0; N];
+        for value in out.iter_mut() {
+            *value = self.u16()?;
+        }
+        Ok(out)
+    }
+}
+
+/// Writes into a buffer that is known to be large enough
+pub(crate) struct ByteWriter<'a> {
+    data: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> ByteWriter<'a> {
+    pub(crate) fn new(data: &'a mut [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.data[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn f32_array(&mut self, values: &[f32]) {
+        values.iter().for_each(|v| self.f32(*v));
+    }
+
+    pub(crate) fn u16_array(&mut self, values: &[u16]) {
+        values.iter().for_each(|v| self.u16(*v));
+    }
+}
+
+impl LinkedMemNet {
+    /// Decodes a packet. The packet has to be exactly `LINKED_MEM_NET_SIZE` bytes long
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        if data.len() != LINKED_MEM_NET_SIZE {
+            return Err(Error::PacketSize {
+                got: data.len(),
+                expected: LINKED_MEM_NET_SIZE,
+            });
+        }
+        let mut reader = ByteReader::new(data);
+        let mem = Self {
+            ui_version: reader.u32()?,
+            ui_tick: reader.u32()?,
+            avatar_position: reader.f32_array()?,
+            avatar_front: reader.f32_array()?,
+            avatar_top: reader.f32_array()?,
+            name: reader.u16_array()?,
+            camera_position: reader.f32_array()?,
+            camera_front: reader.f32_array()?,
+            camera_top: reader.f32_array()?,
+            identity: reader.u16_array()?,
+            context_len: reader.u32()?,
+            context: reader.bytes()?,
+        };
+        if mem.context_len as usize > CONTEXT_CAPACITY {
+            return Err(Error::Malformed("context_len is larger than the context"));
+        }
+        Ok(mem)
+    }
+
+    pub fn encode(&self) -> [u8; LINKED_MEM_NET_SIZE] {
+        let mut data = [0u8; LINKED_MEM_NET_SIZE];
+        let mut writer = ByteWriter::new(&mut data);
+        writer.u32(self.ui_version);
+        writer.u32(self.ui_tick);
+        writer.f32_array(&{ self.avatar_position });
+        writer.f32_array(&{ self.avatar_front });
+        writer.f32_array(&{ self.avatar_top });
+        writer.u16_array(&{ self.name });
+        writer.f32_array(&{ self.camera_position });
+        writer.f32_array(&{ self.camera_front });
+        writer.f32_array(&{ self.camera_top });
+        writer.u16_array(&{ self.identity });
+        writer.u32(self.context_len);
+        writer.bytes(&{ self.context });
+        data
+    }
+}
+
+impl MumbleContext {
+    /// Decodes the context from the start of `data`. Additional bytes are ignored
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        Ok(Self {
+            server_address: reader.bytes()?,
+            map_id: reader.u32()?,
+            map_type: reader.u32()?,
+            shard_id: reader.u32()?,
+            instance: reader.u32()?,
+            build_id: reader.u32()?,
+            ui_state: reader.u32()?,
+            compass_width: reader.u16()?,
+            compass_height: reader.u16()?,
+            compass_rotation: reader.f32()?,
+            player_x: reader.f32()?,
+            player_y: reader.f32()?,
+            map_center_x: reader.f32()?,
+            map_center_y: reader.f32()?,
+            map_scale: reader.f32()?,
+            process_id: reader.u32()?,
+            mount_index: reader.u8()?,
+        })
+    }
+
+    pub fn encode(&self) -> [u8; MUMBLE_CONTEXT_SIZE] {
+        let mut data = [0u8; MUMBLE_CONTEXT_SIZE];
+        let mut writer = ByteWriter::new(&mut data);
+        writer.bytes(&{ self.server_address });
+        writer.u32(self.map_id);
+        writer.u32(self.map_type);
+        writer.u32(self.shard_id);
+        writer.u32(self.instance);
+        writer.u32(self.build_id);
+        writer.u32(self.ui_state);
+        writer.u16(self.compass_width);
+        writer.u16(self.compass_height);
+        writer.f32(self.compass_rotation);
+        writer.f32(self.player_x);
+        writer.f32(self.player_y);
+        writer.f32(self.map_center_x);
+        writer.f32(self.map_center_y);
+        writer.f32(self.map_scale);
+        writer.u32(self.process_id);
+        writer.u8(self.mount_index);
+        data
+    }
+}
+
+impl LinkedMem {
+    /// Replaces the context with `context`
+    pub fn set_context(&mut self, context: &MumbleContext) {
+        let mut data = [0u8; CONTEXT_CAPACITY];
+        data[..MUMBLE_CONTEXT_SIZE].copy_from_slice(&context.encode());
+        self.context = data;
+        self.context_len = MUMBLE_CONTEXT_SIZE as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::{CONTEXT_CAPACITY, LINKED_MEM_NET_SIZE, MUMBLE_CONTEXT_SIZE};
+    use crate::{Error, LinkedMem, LinkedMemNet, MumbleContext};
+
+    /// Offset of `context_len` inside `LinkedMemNet`
+    const CONTEXT_LEN_OFFSET: usize = 1104;
+
+    fn valid_packet() -> impl Strategy<Value = Vec<u8>> {
+        (
+            proptest::collection::vec(any::<u8>(), LINKED_MEM_NET_SIZE),
+            0..=CONTEXT_CAPACITY as u32,
+        )
+            .prop_map(|(mut data, context_len)| {
+                data[CONTEXT_LEN_OFFSET..CONTEXT_LEN_OFFSET + 4]
+                    .copy_from_slice(&context_len.to_le_bytes());
+                data
+            })
+    }
+
+    #[test]
+    fn test_layout() {
+        let mem = LinkedMem {
+            ui_version: 2,
+            ui_tick: 0x01020304,
+            camera_position: [1.0, 2.0, 3.0],
+            context_len: 86,
+            ..Default::default()
+        };
+        let data = LinkedMemNet::from(&mem).encode();
+        assert_eq!(&data[0..8], &[2, 0, 0, 0, 4, 3, 2, 1]);
+        // 8 bytes header, 3 * 12 bytes vectors and 512 bytes name
+        assert_eq!(&data[556..560], &1.0f32.to_le_bytes());
+        assert_eq!(
+            &data[CONTEXT_LEN_OFFSET..CONTEXT_LEN_OFFSET + 4],
+            &86u32.to_le_bytes()
+        );
+
+        let ctx = MumbleContext {
+            map_id: 50,
+            mount_index: 3,
+            ..Default::default()
+        };
+        let data = ctx.encode();
+        assert_eq!(&data[28..32], &50u32.to_le_bytes());
+        assert_eq!(data[84], 3);
+    }
+
+    #[test]
+    fn test_invalid_packets() {
+        assert!(matches!(
+            LinkedMemNet::decode(&[0; 10]),
+            Err(Error::PacketSize { got: 10, .. })
+        ));
+        let mut data = [0u8; LINKED_MEM_NET_SIZE];
+        data[CONTEXT_LEN_OFFSET..CONTEXT_LEN_OFFSET + 4].copy_from_slice(&257u32.to_le_bytes());
+        assert!(matches!(
+            LinkedMemNet::decode(&data),
+            Err(Error::Malformed(_))
+        ));
+        assert!(matches!(
+            MumbleContext::decode(&[0; MUMBLE_CONTEXT_SIZE - 1]),
+            Err(Error::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn test_set_context() {
+        let mut mem = LinkedMem::default();
+        mem.set_context(&MumbleContext {
+            map_id: 15,
+            ..Default::default()
+        });
+        assert_eq!(mem.get_context().get_map_id(), 15);
+    }
+
+    proptest! {
+        #[test]
+        fn decode_never_panics(data in proptest::collection::vec(any::<u8>(), 0..2 * LINKED_MEM_NET_SIZE)) {
+            let _ = LinkedMemNet::decode(&data);
+            let _ = MumbleContext::decode(&data);
+        }
+
+        #[test]
+        fn packet_round_trip(data in valid_packet()) {
+            let mem = LinkedMemNet::decode(&data).unwrap();
+            prop_assert_eq!(mem.encode().to_vec(), data.clone());
+
+            let mem = LinkedMem::from(mem);
+            prop_assert_eq!(LinkedMemNet::from(&mem).encode().to_vec(), data);
+        }
+
+        #[test]
+        fn context_round_trip(data in proptest::collection::vec(any::<u8>(), MUMBLE_CONTEXT_SIZE)) {
+            let ctx = MumbleContext::decode(&data).unwrap();
+            prop_assert_eq!(ctx.encode().to_vec(), data);
+        }
+    }
+}
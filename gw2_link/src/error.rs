@@ -0,0 +1,110 @@
+use std::fmt::Display;
+
+/// Everything that can go wrong while setting up or reading the link
+#[derive(Debug)]
+pub enum Error {
+    /// Opening or creating the MumbleLink shared memory failed
+    ShmOpen(std::io::Error),
+    /// The existing shared memory is too small to hold a `LinkedMem`
+    ShmSize { got: usize, expected: usize },
+    /// Resizing a freshly created shared memory object failed
+    Ftruncate(std::io::Error),
+    /// Mapping the shared memory into our address space failed
+    Mmap(std::io::Error),
+    /// Binding the udp socket failed. Most likely the port is already in use
+    SocketBind(std::io::Error),
+    /// Configuring or reading the udp socket failed
+    Socket(std::io::Error),
+    /// Received a packet that doesn't have the size of a `LinkedMemNet`
+    PacketSize { got: usize, expected: usize },
+    /// Ran out of bytes while decoding
+    Truncated { got: usize, expected: usize },
+    /// The packet has the right size but contains invalid values
+    Malformed(&'static str),
+    /// The packet uses a protocol version this library doesn't understand
+    UnsupportedVersion(u16),
+    /// The packet is older than one that was already received
+    OutOfOrder { sequence: u32 },
+    /// Reading or writing a recording failed
+    Io(std::io::Error),
+    /// The file is not a recording or uses an unsupported version
+    BadRecording(String),
+    /// The identity is not valid json
+    Identity(serde_json::Error),
+    /// A profession, race or mount name that doesn't exist
+    UnknownName { kind: &'static str, name: String },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::ShmOpen(e) => write!(f, "failed to open shared memory: {}", e),
+            Error::ShmSize { got, expected } => {
+                write!(f, "shared memory has size {} expected {}", got, expected)
+            }
+            Error::Ftruncate(e) => write!(f, "failed to resize shared memory: {}", e),
+            Error::Mmap(e) => write!(f, "failed to map shared memory: {}", e),
+            Error::SocketBind(e) => write!(f, "failed to bind udp socket: {}", e),
+            Error::Socket(e) => write!(f, "udp socket error: {}", e),
+            Error::PacketSize { got, expected } => {
+                write!(f, "got packet with size {} expected {}", got, expected)
+            }
+            Error::Truncated { got, expected } => {
+                write!(
+                    f,
+                    "data is truncated. Got {} bytes expected {}",
+                    got, expected
+                )
+            }
+            Error::Malformed(reason) => write!(f, "malformed packet: {}", reason),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported protocol version {}", version)
+            }
+            Error::OutOfOrder { sequence } => {
+                write!(f, "packet {} arrived out of order", sequence)
+            }
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::BadRecording(reason) => write!(f, "invalid recording: {}", reason),
+            Error::Identity(e) => write!(f, "invalid identity: {}", e),
+            Error::UnknownName { kind, name } => write!(f, "unknown {} {:?}", kind, name),
+        }
+    }
+}
+
+impl Error {
+    /// A single broken or late packet. The next packet may be fine
+    pub fn is_packet_error(&self) -> bool {
+        matches!(
+            self,
+            Error::PacketSize { .. }
+                | Error::Truncated { .. }
+                | Error::Malformed(_)
+                | Error::UnsupportedVersion(_)
+                | Error::OutOfOrder { .. }
+        )
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ShmOpen(e) | Error::Ftruncate(e) | Error::Mmap(e) => Some(e),
+            Error::SocketBind(e) | Error::Socket(e) | Error::Io(e) => Some(e),
+            Error::Identity(e) => Some(e),
+            Error::ShmSize { .. }
+            | Error::PacketSize { .. }
+            | Error::Truncated { .. }
+            | Error::Malformed(_)
+            | Error::UnsupportedVersion(_)
+            | Error::OutOfOrder { .. }
+            | Error::BadRecording(_)
+            | Error::UnknownName { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Io(value)
+    }
+}
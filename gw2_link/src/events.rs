@@ -0,0 +1,309 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    GameState, IdentityCache, InstanceChange, InstanceTracker, LinkSource, LinkedMem, Mount,
+};
+
+/// Time without a new `ui_tick` after which the game is considered stopped
+pub const DEFAULT_TICK_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameEventKind {
+    /// The first map after starting comes from map 0
+    MapChanged {
+        from: u32,
+        to: u32,
+    },
+    InstanceChanged(InstanceChange),
+    CombatEntered,
+    CombatLeft,
+    MapOpened,
+    MapClosed,
+    FocusGained,
+    FocusLost,
+    MountChanged {
+        from: Mount,
+        to: Mount,
+    },
+    /// `from` is `None` for the first character after starting
+    CharacterChanged {
+        from: Option<String>,
+        to: String,
+    },
+    /// No new `ui_tick` for the tick timeout. Usually the game was closed or is loading
+    TicksStopped,
+    TicksResumed,
+}
+
+/// A transition of the game state
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameEvent {
+    /// When the data causing the event was processed
+    pub timestamp: Instant,
+    pub kind: GameEventKind,
+}
+
+/// Turns consecutive `LinkedMem`s into `GameEvent`s. Doesn't depend on bevy and can be used
+/// by any consumer of the link
+pub struct EventTracker {
+    last: GameState,
+    instance_tracker: InstanceTracker,
+    identity_cache: IdentityCache,
+    character: Option<String>,
+    last_tick: Option<Instant>,
+    stopped: bool,
+    tick_timeout: Duration,
+}
+
+impl Default for EventTracker {
+    fn default() -> Self {
+        Self {
+            last: GameState::default(),
+            instance_tracker: InstanceTracker::new(),
+            identity_cache: IdentityCache::new(),
+            character: None,
+            last_tick: None,
+            stopped: false,
+            tick_timeout: DEFAULT_TICK_TIMEOUT,
+        }
+    }
+}
+
+impl EventTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tick_timeout(mut self, timeout: Duration) -> Self {
+        self.tick_timeout = timeout;
+        self
+    }
+
+    /// The state of the last update
+    pub fn get_game_state(&self) -> &GameState {
+        &self.last
+    }
+
+    pub fn get_identity_cache(&self) -> &IdentityCache {
+        &self.identity_cache
+    }
+
+    /// Drains `source` and returns the events of all received data
+    pub fn poll(&mut self, source: &mut impl LinkSource) -> Vec<GameEvent> {
+        let mut events = vec![];
+        while let Some(mem) = source.poll() {
+            events.extend(self.update(&mem));
+        }
+        events.extend(self.check_ticks());
+        events
+    }
+
+    pub fn update(&mut self, mem: &LinkedMem) -> Vec<GameEvent> {
+        self.update_at(mem, Instant::now())
+    }
+
+    /// Returns the events caused by `mem` compared to the previous update
+    pub fn update_at(&mut self, mem: &LinkedMem, now: Instant) -> Vec<GameEvent> {
+        let state = mem.get_game_state();
+        let last = std::mem::replace(&mut self.last, state);
+        let mut kinds = vec![];
+
+        if state.ui_tick != last.ui_tick || self.last_tick.is_none() {
+            self.last_tick = Some(now);
+            if self.stopped {
+                self.stopped = false;
+                kinds.push(GameEventKind::TicksResumed);
+            }
+        }
+        if state.map_id != last.map_id {
+            kinds.push(GameEventKind::MapChanged {
+                from: last.map_id,
+                to: state.map_id,
+            });
+        }
+        if let Some(change) = self.instance_tracker.update(&mem.get_context()) {
+            kinds.push(GameEventKind::InstanceChanged(change));
+        }
+        match (last.ui_state.in_combat(), state.ui_state.in_combat()) {
+            (false, true) => kinds.push(GameEventKind::CombatEntered),
+            (true, false) => kinds.push(GameEventKind::CombatLeft),
+            _ => (),
+        }
+        match (last.ui_state.is_map_open(), state.ui_state.is_map_open()) {
+            (false, true) => kinds.push(GameEventKind::MapOpened),
+            (true, false) => kinds.push(GameEventKind::MapClosed),
+            _ => (),
+        }
+        match (
+            last.ui_state.has_game_focus(),
+            state.ui_state.has_game_focus(),
+        ) {
+            (false, true) => kinds.push(GameEventKind::FocusGained),
+            (true, false) => kinds.push(GameEventKind::FocusLost),
+            _ => (),
+        }
+        if state.mount != last.mount {
+            kinds.push(GameEventKind::MountChanged {
+                from: last.mount,
+                to: state.mount,
+            });
+        }
+        if self.identity_cache.update(mem) {
+            if let Some(identity) = self.identity_cache.get_identity() {
+                if self.character.as_ref() != Some(&identity.name) {
+                    kinds.push(GameEventKind::CharacterChanged {
+                        from: self.character.replace(identity.name.clone()),
+                        to: identity.name.clone(),
+                    });
+                }
+            }
+        }
+
+        kinds
+            .into_iter()
+            .map(|kind| GameEvent {
+                timestamp: now,
+                kind,
+            })
+            .collect()
+    }
+
+    pub fn check_ticks(&mut self) -> Option<GameEvent> {
+        self.check_ticks_at(Instant::now())
+    }
+
+    /// Returns `TicksStopped` once if there was no new tick for the tick timeout.
+    /// Nothing is reported before the first data arrived
+    pub fn check_ticks_at(&mut self, now: Instant) -> Option<GameEvent> {
+        let last_tick = self.last_tick?;
+        if self.stopped || now.saturating_duration_since(last_tick) < self.tick_timeout {
+            return None;
+        }
+        self.stopped = true;
+        Some(GameEvent {
+            timestamp: now,
+            kind: GameEventKind::TicksStopped,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{EventTracker, GameEventKind};
+    use crate::{LinkedMem, MockSource, Mount, MumbleContext, UiState};
+
+    fn mem(tick: u32, map_id: u32, ui_state: UiState, mount: u8) -> LinkedMem {
+        let mut mem = LinkedMem {
+            ui_tick: tick,
+            ..Default::default()
+        };
+        mem.set_context(&MumbleContext {
+            map_id,
+            ui_state: ui_state.bits(),
+            mount_index: mount,
+            ..Default::default()
+        });
+        mem
+    }
+
+    fn kinds(events: Vec<super::GameEvent>) -> Vec<GameEventKind> {
+        events.into_iter().map(|e| e.kind).collect()
+    }
+
+    #[test]
+    fn test_transitions() {
+        let mut tracker = EventTracker::new();
+        let start = Instant::now();
+
+        let mut first = mem(1, 15, UiState::GAME_FOCUS, 0);
+        first.set_identity(r#"{"name":"A"}"#);
+        let events = kinds(tracker.update_at(&first, start));
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], GameEventKind::MapChanged { from: 0, to: 15 });
+        assert!(matches!(events[1], GameEventKind::InstanceChanged(_)));
+        assert_eq!(events[2], GameEventKind::FocusGained);
+        assert_eq!(
+            events[3],
+            GameEventKind::CharacterChanged {
+                from: None,
+                to: "A".to_string()
+            }
+        );
+
+        let mut second = mem(2, 15, UiState::COMBAT | UiState::MAP_OPEN, 2);
+        second.set_identity(r#"{"name":"B"}"#);
+        assert_eq!(
+            kinds(tracker.update_at(&second, start)),
+            vec![
+                GameEventKind::CombatEntered,
+                GameEventKind::MapOpened,
+                GameEventKind::FocusLost,
+                GameEventKind::MountChanged {
+                    from: Mount::None,
+                    to: Mount::Griffon
+                },
+                GameEventKind::CharacterChanged {
+                    from: Some("A".to_string()),
+                    to: "B".to_string()
+                },
+            ]
+        );
+
+        // Nothing changed
+        assert!(tracker.update_at(&second, start).is_empty());
+    }
+
+    #[test]
+    fn test_ticks_stopped() {
+        let mut tracker = EventTracker::new().tick_timeout(Duration::from_millis(100));
+        let start = Instant::now();
+        assert!(tracker.check_ticks_at(start).is_none());
+
+        tracker.update_at(&mem(1, 0, UiState::empty(), 0), start);
+        assert!(tracker
+            .check_ticks_at(start + Duration::from_millis(50))
+            .is_none());
+        // The same tick again doesn't count as alive
+        tracker.update_at(&mem(1, 0, UiState::empty(), 0), start);
+        let stopped = tracker
+            .check_ticks_at(start + Duration::from_millis(150))
+            .unwrap();
+        assert_eq!(stopped.kind, GameEventKind::TicksStopped);
+        assert_eq!(stopped.timestamp, start + Duration::from_millis(150));
+        // Only reported once
+        assert!(tracker
+            .check_ticks_at(start + Duration::from_millis(200))
+            .is_none());
+
+        let resumed = tracker.update_at(
+            &mem(2, 0, UiState::empty(), 0),
+            start + Duration::from_millis(250),
+        );
+        assert_eq!(kinds(resumed), vec![GameEventKind::TicksResumed]);
+    }
+
+    #[test]
+    fn test_poll() {
+        let mut source: MockSource = [
+            mem(1, 15, UiState::empty(), 0),
+            mem(2, 50, UiState::empty(), 0),
+        ]
+        .into_iter()
+        .collect();
+        let mut tracker = EventTracker::new();
+        let map_changes: Vec<GameEventKind> = kinds(tracker.poll(&mut source))
+            .into_iter()
+            .filter(|kind| matches!(kind, GameEventKind::MapChanged { .. }))
+            .collect();
+        assert_eq!(
+            map_changes,
+            vec![
+                GameEventKind::MapChanged { from: 0, to: 15 },
+                GameEventKind::MapChanged { from: 15, to: 50 }
+            ]
+        );
+        assert_eq!(tracker.get_game_state().map_id, 50);
+    }
+}
@@ -0,0 +1,198 @@
+use std::time::{Duration, Instant};
+
+use crate::LinkedMem;
+
+/// Time without a new `ui_tick` after which the link is reported as stale
+pub const DEFAULT_STALE_AFTER: Duration = Duration::from_millis(500);
+
+/// Packets are counted over this window to calculate the packet rate
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkHealth {
+    /// The game didn't send a single tick yet
+    NeverConnected,
+    Connected,
+    /// The last new tick is older than the stale timeout. Contains the time since the last tick
+    Stale(Duration),
+    /// The helper forwarding the link exited
+    HelperDead,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkStats {
+    /// All packets received so far
+    pub packets: u64,
+    /// Packets per second over the last full second
+    pub packet_rate: f32,
+    /// Difference between the last two distinct ticks. 1 means no tick was missed
+    pub last_tick_gap: u32,
+    pub max_tick_gap: u32,
+}
+
+/// Keeps track of received packets to tell whether the game is still sending data.
+/// The helper keeps sending the last data after the game closed, so only a changing `ui_tick`
+/// counts as alive
+pub struct HealthMonitor {
+    stale_after: Duration,
+    last_tick: Option<(u32, Instant)>,
+    helper_alive: bool,
+    stats: LinkStats,
+    window_start: Option<Instant>,
+    window_packets: u32,
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        Self {
+            stale_after: DEFAULT_STALE_AFTER,
+            last_tick: None,
+            helper_alive: true,
+            stats: LinkStats::default(),
+            window_start: None,
+            window_packets: 0,
+        }
+    }
+}
+
+impl HealthMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stale_after(mut self, stale_after: Duration) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+
+    /// Set by whoever started the helper. A dead helper overrides every other state
+    pub fn set_helper_alive(&mut self, alive: bool) {
+        self.helper_alive = alive;
+    }
+
+    pub fn get_stats(&self) -> LinkStats {
+        self.stats
+    }
+
+    /// When the last new tick arrived
+    pub fn get_last_update(&self) -> Option<Instant> {
+        self.last_tick.map(|(_, time)| time)
+    }
+
+    pub fn record(&mut self, mem: &LinkedMem) {
+        self.record_at(mem, Instant::now())
+    }
+
+    pub fn record_at(&mut self, mem: &LinkedMem, now: Instant) {
+        self.stats.packets += 1;
+        let window_start = *self.window_start.get_or_insert(now);
+        self.window_packets += 1;
+        let window = now.saturating_duration_since(window_start);
+        if window >= RATE_WINDOW {
+            self.stats.packet_rate = self.window_packets as f32 / window.as_secs_f32();
+            self.window_start = Some(now);
+            self.window_packets = 0;
+        }
+
+        let tick = mem.get_ui_tick();
+        // A tick of 0 means the game didn't write the shared memory yet
+        if tick == 0 {
+            return;
+        }
+        match self.last_tick {
+            Some((last, _)) if last == tick => (),
+            Some((last, _)) => {
+                let gap = tick.wrapping_sub(last);
+                self.stats.last_tick_gap = gap;
+                self.stats.max_tick_gap = self.stats.max_tick_gap.max(gap);
+                self.last_tick = Some((tick, now));
+            }
+            None => self.last_tick = Some((tick, now)),
+        }
+    }
+
+    pub fn get_health(&self) -> LinkHealth {
+        self.get_health_at(Instant::now())
+    }
+
+    pub fn get_health_at(&self, now: Instant) -> LinkHealth {
+        if !self.helper_alive {
+            return LinkHealth::HelperDead;
+        }
+        match self.last_tick {
+            None => LinkHealth::NeverConnected,
+            Some((_, time)) => {
+                let age = now.saturating_duration_since(time);
+                if age > self.stale_after {
+                    LinkHealth::Stale(age)
+                } else {
+                    LinkHealth::Connected
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{HealthMonitor, LinkHealth};
+    use crate::LinkedMem;
+
+    fn mem(tick: u32) -> LinkedMem {
+        LinkedMem {
+            ui_tick: tick,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_health() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut monitor = HealthMonitor::new().stale_after(ms(100));
+        assert_eq!(monitor.get_health_at(start), LinkHealth::NeverConnected);
+
+        // The helper sends zeroed data until the game started
+        monitor.record_at(&mem(0), start);
+        assert_eq!(monitor.get_health_at(start), LinkHealth::NeverConnected);
+
+        monitor.record_at(&mem(1), start);
+        assert_eq!(monitor.get_health_at(start + ms(50)), LinkHealth::Connected);
+
+        // Repeated ticks don't keep the link alive
+        monitor.record_at(&mem(1), start + ms(100));
+        assert_eq!(
+            monitor.get_health_at(start + ms(150)),
+            LinkHealth::Stale(ms(150))
+        );
+
+        monitor.record_at(&mem(2), start + ms(200));
+        assert_eq!(
+            monitor.get_health_at(start + ms(200)),
+            LinkHealth::Connected
+        );
+        assert_eq!(monitor.get_last_update(), Some(start + ms(200)));
+
+        monitor.set_helper_alive(false);
+        assert_eq!(
+            monitor.get_health_at(start + ms(200)),
+            LinkHealth::HelperDead
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let start = Instant::now();
+        let mut monitor = HealthMonitor::new();
+        for (i, tick) in [1, 2, 3, 7, 8].into_iter().enumerate() {
+            monitor.record_at(&mem(tick), start + Duration::from_millis(i as u64 * 250));
+        }
+        let stats = monitor.get_stats();
+        assert_eq!(stats.packets, 5);
+        assert_eq!(stats.packet_rate, 5.0);
+        assert_eq!(stats.last_tick_gap, 1);
+        assert_eq!(stats.max_tick_gap, 4);
+    }
+}
@@ -0,0 +1,319 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::{Error, LinkedMem};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(from = "u8")]
+pub enum Profession {
+    #[default]
+    None,
+    Guardian,
+    Warrior,
+    Engineer,
+    Ranger,
+    Thief,
+    Elementalist,
+    Mesmer,
+    Necromancer,
+    Revenant,
+    Unknown(u8),
+}
+
+impl From<u8> for Profession {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Profession::None,
+            1 => Profession::Guardian,
+            2 => Profession::Warrior,
+            3 => Profession::Engineer,
+            4 => Profession::Ranger,
+            5 => Profession::Thief,
+            6 => Profession::Elementalist,
+            7 => Profession::Mesmer,
+            8 => Profession::Necromancer,
+            9 => Profession::Revenant,
+            other => Profession::Unknown(other),
+        }
+    }
+}
+
+impl Profession {
+    /// The names marker packs use
+    pub const NAMES: &'static [(Self, &'static str)] = &[
+        (Profession::Guardian, "guardian"),
+        (Profession::Warrior, "warrior"),
+        (Profession::Engineer, "engineer"),
+        (Profession::Ranger, "ranger"),
+        (Profession::Thief, "thief"),
+        (Profession::Elementalist, "elementalist"),
+        (Profession::Mesmer, "mesmer"),
+        (Profession::Necromancer, "necromancer"),
+        (Profession::Revenant, "revenant"),
+    ];
+}
+
+/// Looks up `name` in `names` ignoring the case
+pub(crate) fn parse_name<T: Copy>(
+    names: &[(T, &str)],
+    kind: &'static str,
+    name: &str,
+) -> Result<T, Error> {
+    names
+        .iter()
+        .find(|(_, known)| known.eq_ignore_ascii_case(name.trim()))
+        .map(|(value, _)| *value)
+        .ok_or_else(|| Error::UnknownName {
+            kind,
+            name: name.to_string(),
+        })
+}
+
+/// Writes the name of `value`, `none` or the number of an unknown value
+pub(crate) fn write_name<T: PartialEq>(
+    f: &mut std::fmt::Formatter<'_>,
+    names: &[(T, &str)],
+    value: &T,
+    unknown: Option<u8>,
+) -> std::fmt::Result {
+    match (names.iter().find(|(known, _)| known == value), unknown) {
+        (Some((_, name)), _) => write!(f, "{}", name),
+        (None, Some(number)) => write!(f, "{}", number),
+        (None, None) => write!(f, "none"),
+    }
+}
+
+impl FromStr for Profession {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_name(Self::NAMES, "profession", s)
+    }
+}
+
+impl Display for Profession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unknown = match self {
+            Profession::Unknown(number) => Some(*number),
+            _ => None,
+        };
+        write_name(f, Self::NAMES, self, unknown)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(from = "u8")]
+pub enum Race {
+    #[default]
+    Asura,
+    Charr,
+    Human,
+    Norn,
+    Sylvari,
+    Unknown(u8),
+}
+
+impl From<u8> for Race {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Race::Asura,
+            1 => Race::Charr,
+            2 => Race::Human,
+            3 => Race::Norn,
+            4 => Race::Sylvari,
+            other => Race::Unknown(other),
+        }
+    }
+}
+
+impl Race {
+    /// The names marker packs use
+    pub const NAMES: &'static [(Self, &'static str)] = &[
+        (Race::Asura, "asura"),
+        (Race::Charr, "charr"),
+        (Race::Human, "human"),
+        (Race::Norn, "norn"),
+        (Race::Sylvari, "sylvari"),
+    ];
+}
+
+impl FromStr for Race {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_name(Self::NAMES, "race", s)
+    }
+}
+
+impl Display for Race {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unknown = match self {
+            Race::Unknown(number) => Some(*number),
+            _ => None,
+        };
+        write_name(f, Self::NAMES, self, unknown)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(from = "u8")]
+pub enum UiSize {
+    Small,
+    #[default]
+    Normal,
+    Large,
+    Larger,
+    Unknown(u8),
+}
+
+impl From<u8> for UiSize {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => UiSize::Small,
+            1 => UiSize::Normal,
+            2 => UiSize::Large,
+            3 => UiSize::Larger,
+            other => UiSize::Unknown(other),
+        }
+    }
+}
+
+/// The json the game writes to `LinkedMem::identity`
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Identity {
+    /// Character name
+    pub name: String,
+    pub profession: Profession,
+    /// Id of the elite specialization or 0
+    pub spec: u32,
+    pub race: Race,
+    pub map_id: u32,
+    pub world_id: u32,
+    pub team_color_id: u32,
+    pub commander: bool,
+    /// Vertical field of view in radians
+    pub fov: f32,
+    #[serde(rename = "uisz")]
+    pub ui_size: UiSize,
+}
+
+impl Identity {
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(Error::Identity)
+    }
+}
+
+/// Keeps the last parsed `Identity` and only parses again if the text changed
+#[derive(Default)]
+pub struct IdentityCache {
+    raw: Vec<u16>,
+    identity: Option<Identity>,
+}
+
+impl IdentityCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `None` until the game wrote a valid identity
+    pub fn get_identity(&self) -> Option<&Identity> {
+        self.identity.as_ref()
+    }
+
+    /// Returns true if the identity changed
+    pub fn update(&mut self, mem: &LinkedMem) -> bool {
+        let identity = mem.identity;
+        let len = identity
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(identity.len());
+        if self.raw == identity[..len] {
+            return false;
+        }
+        self.raw = identity[..len].to_vec();
+        let parsed = Identity::from_json(&String::from_utf16_lossy(&self.raw)).ok();
+        let changed = parsed != self.identity;
+        self.identity = parsed;
+        changed
+    }
+}
+
+impl LinkedMem {
+    /// Writes `json` as identity. Longer text is cut off
+    pub fn set_identity(&mut self, json: &str) {
+        let mut identity = [0u16; 256];
+        // Keep the last character as terminating NUL
+        for (dst, src) in identity[..255].iter_mut().zip(json.encode_utf16()) {
+            *dst = src;
+        }
+        self.identity = identity;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Identity, IdentityCache, Profession, Race, UiSize};
+    use crate::LinkedMem;
+
+    const JSON: &str = r#"{"name":"Some Name","profession":7,"spec":59,"race":4,"map_id":50,"world_id":268435458,"team_color_id":0,"commander":false,"map":50,"fov":0.873,"uisz":1}"#;
+
+    #[test]
+    fn test_parse_identity() {
+        let identity = Identity::from_json(JSON).unwrap();
+        assert_eq!(identity.name, "Some Name");
+        assert_eq!(identity.profession, Profession::Mesmer);
+        assert_eq!(identity.spec, 59);
+        assert_eq!(identity.race, Race::Sylvari);
+        assert_eq!(identity.map_id, 50);
+        assert_eq!(identity.world_id, 268435458);
+        assert!(!identity.commander);
+        assert_eq!(identity.fov, 0.873);
+        assert_eq!(identity.ui_size, UiSize::Normal);
+
+        assert!(Identity::from_json("{\"name\":").is_err());
+        assert_eq!(
+            Identity::from_json(r#"{"profession":42}"#)
+                .unwrap()
+                .profession,
+            Profession::Unknown(42)
+        );
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!("Thief".parse::<Profession>().unwrap(), Profession::Thief);
+        assert_eq!(
+            " revenant".parse::<Profession>().unwrap(),
+            Profession::Revenant
+        );
+        assert_eq!("norn".parse::<Race>().unwrap(), Race::Norn);
+        assert!("theif".parse::<Profession>().is_err());
+        assert!("".parse::<Race>().is_err());
+        for (profession, name) in Profession::NAMES {
+            assert_eq!(profession.to_string(), *name);
+            assert_eq!(name.parse::<Profession>().unwrap(), *profession);
+        }
+        assert_eq!(Profession::None.to_string(), "none");
+        assert_eq!(Race::Unknown(9).to_string(), "9");
+    }
+
+    #[test]
+    fn test_identity_cache() {
+        let mut mem = LinkedMem::default();
+        let mut cache = IdentityCache::new();
+        assert!(!cache.update(&mem));
+        assert!(cache.get_identity().is_none());
+
+        mem.set_identity(JSON);
+        assert_eq!(mem.get_identity(), JSON);
+        assert!(cache.update(&mem));
+        assert_eq!(cache.get_identity().unwrap().name, "Some Name");
+        assert!(!cache.update(&mem));
+
+        mem.set_identity(&JSON.replace("Some Name", "Other Name"));
+        assert!(cache.update(&mem));
+        assert_eq!(cache.get_identity().unwrap().name, "Other Name");
+    }
+}
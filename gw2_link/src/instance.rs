@@ -0,0 +1,98 @@
+use std::net::SocketAddr;
+
+use crate::MumbleContext;
+
+/// Everything that identifies a map instance. Two instances of the same map differ in
+/// at least the shard, the instance or the server the client is connected to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InstanceKey {
+    pub map_id: u32,
+    pub shard_id: u32,
+    pub instance: u32,
+    pub server_address: Option<SocketAddr>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstanceChange {
+    /// `None` for the first instance after starting
+    pub from: Option<InstanceKey>,
+    pub to: InstanceKey,
+}
+
+impl InstanceChange {
+    /// True if only the instance changed but the map stayed the same
+    pub fn is_same_map(&self) -> bool {
+        self.from.map(|from| from.map_id) == Some(self.to.map_id)
+    }
+}
+
+/// Detects when the player switches to another map or another instance of the same map
+#[derive(Debug, Default)]
+pub struct InstanceTracker {
+    current: Option<InstanceKey>,
+}
+
+impl InstanceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_current(&self) -> Option<InstanceKey> {
+        self.current
+    }
+
+    /// Returns the change if `ctx` belongs to another instance than the previous context.
+    /// Contexts without a map (e.g. during character select) are ignored
+    pub fn update(&mut self, ctx: &MumbleContext) -> Option<InstanceChange> {
+        let key = ctx.get_instance_key();
+        if key.map_id == 0 || self.current == Some(key) {
+            return None;
+        }
+        let change = InstanceChange {
+            from: self.current,
+            to: key,
+        };
+        self.current = Some(key);
+        Some(change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InstanceTracker;
+    use crate::MumbleContext;
+
+    fn context(map_id: u32, shard_id: u32, ip: [u8; 4]) -> MumbleContext {
+        let mut ctx = MumbleContext {
+            map_id,
+            shard_id,
+            ..Default::default()
+        };
+        ctx.server_address[0..2].copy_from_slice(&2u16.to_le_bytes());
+        ctx.server_address[4..8].copy_from_slice(&ip);
+        ctx
+    }
+
+    #[test]
+    fn test_instance_tracker() {
+        let mut tracker = InstanceTracker::new();
+        assert!(tracker.update(&MumbleContext::default()).is_none());
+
+        let change = tracker.update(&context(15, 1, [1, 2, 3, 4])).unwrap();
+        assert!(change.from.is_none());
+        assert_eq!(change.to.map_id, 15);
+        assert!(tracker.update(&context(15, 1, [1, 2, 3, 4])).is_none());
+
+        // Same map on another server
+        let change = tracker.update(&context(15, 1, [1, 2, 3, 5])).unwrap();
+        assert!(change.is_same_map());
+
+        // Same map on another shard
+        let change = tracker.update(&context(15, 2, [1, 2, 3, 5])).unwrap();
+        assert!(change.is_same_map());
+
+        let change = tracker.update(&context(50, 2, [1, 2, 3, 5])).unwrap();
+        assert!(!change.is_same_map());
+        assert_eq!(tracker.get_current().unwrap().map_id, 50);
+    }
+}
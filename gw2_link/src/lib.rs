@@ -1,29 +1,35 @@
 use std::{
     mem::size_of,
-    net::UdpSocket,
-    num::NonZeroUsize,
-    time::{Duration, Instant},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
 };
 
-use nix::{
-    fcntl::OFlag,
-    libc::memset,
-    sys::{
-        mman::{mmap, shm_open, MapFlags, ProtFlags},
-        stat::Mode,
-    },
-    unistd::{close, ftruncate, getuid},
-};
-
-pub enum UiState {
-    MapOpen = (1 << 0),
-    CompassTopRight = (1 << 1),
-    CompassRotation = (1 << 2),
-    GameFocus = (1 << 3),
-    CompetetiveMode = (1 << 4),
-    TextbookFocus = (1 << 5),
-    Combat = (1 << 6),
-}
+mod builder;
+mod codec;
+mod error;
+mod events;
+mod health;
+mod identity;
+mod instance;
+#[cfg(unix)]
+mod link;
+pub mod protocol;
+pub mod record;
+mod source;
+mod state;
+
+pub use builder::{GW2LinkBuilder, DEFAULT_PORT};
+pub use codec::{CONTEXT_CAPACITY, LINKED_MEM_NET_SIZE, MUMBLE_CONTEXT_SIZE};
+pub use error::Error;
+pub use events::{EventTracker, GameEvent, GameEventKind, DEFAULT_TICK_TIMEOUT};
+pub use health::{HealthMonitor, LinkHealth, LinkStats, DEFAULT_STALE_AFTER};
+pub use identity::{Identity, IdentityCache, Profession, Race, UiSize};
+pub use instance::{InstanceChange, InstanceKey, InstanceTracker};
+#[cfg(unix)]
+pub use link::{new_gw2link, GW2Link, MutLinkedMem};
+#[cfg(unix)]
+pub use source::ShmSource;
+pub use source::{LinkSource, MockSource, UdpSource};
+pub use state::{GameState, MapType, Mount, UiState};
 
 #[repr(C, packed)]
 #[derive(Copy, Clone, Debug)]
@@ -63,15 +69,60 @@ impl From<LinkedMemNet> for LinkedMem {
     }
 }
 
+impl From<&LinkedMem> for LinkedMemNet {
+    fn from(value: &LinkedMem) -> Self {
+        Self {
+            ui_version: value.ui_version,
+            ui_tick: value.ui_tick,
+            avatar_position: value.avatar_position,
+            avatar_front: value.avatar_front,
+            avatar_top: value.avatar_top,
+            name: value.name,
+            camera_position: value.camera_position,
+            camera_front: value.camera_front,
+            camera_top: value.camera_top,
+            identity: value.identity,
+            context_len: value.context_len,
+            context: value.context,
+        }
+    }
+}
+
+impl Default for LinkedMem {
+    fn default() -> Self {
+        Self {
+            ui_version: 0,
+            ui_tick: 0,
+            avatar_position: [0.0; 3],
+            avatar_front: [0.0; 3],
+            avatar_top: [0.0; 3],
+            name: [0; 256],
+            camera_position: [0.0; 3],
+            camera_front: [0.0; 3],
+            camera_top: [0.0; 3],
+            identity: [0; 256],
+            context_len: 0,
+            context: [0; 256],
+            description: [0; 2048],
+        }
+    }
+}
+
 impl LinkedMem {
     pub fn get_context(&self) -> Box<MumbleContext> {
-        let context: MumbleContext = unsafe { std::mem::transmute_copy(&self.context) };
+        // The context array is always large enough to decode a `MumbleContext`
+        let context = MumbleContext::decode(&self.context).unwrap_or_default();
         Box::new(context)
     }
 
+    /// The identity json without the trailing NULs
     pub fn get_identity(&self) -> String {
         let identity = self.identity;
-        String::from_utf16_lossy(&identity)
+        let len = identity
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(identity.len());
+        String::from_utf16_lossy(&identity[..len])
     }
 
     pub fn get_avatar_pos(&self) -> [f32; 3] {
@@ -89,9 +140,14 @@ impl LinkedMem {
     pub fn get_camera_front(&self) -> [f32; 3] {
         self.camera_front
     }
+
+    pub fn get_game_state(&self) -> GameState {
+        GameState::from(self)
+    }
 }
 
 #[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
 pub struct LinkedMemNet {
     ui_version: u32,
     ui_tick: u32,
@@ -109,7 +165,7 @@ pub struct LinkedMemNet {
 }
 
 #[repr(C, packed)]
-#[derive(Default)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct MumbleContext {
     pub server_address: [u8; 28], // contains sockaddr_in or sockaddr_in6
     pub map_id: u32,
@@ -134,6 +190,11 @@ pub struct MumbleContext {
     pub mount_index: u8,
 }
 
+const AF_INET: u16 = 2;
+// The game writes the Windows value, but accept the Linux one as well
+const AF_INET6_WINDOWS: u16 = 23;
+const AF_INET6_LINUX: u16 = 10;
+
 impl MumbleContext {
     pub fn get_ui_state(&self, option: u32) -> bool {
         let state = self.ui_state;
@@ -141,176 +202,122 @@ impl MumbleContext {
         res != 0
     }
 
+    pub fn get_ui_flags(&self) -> UiState {
+        UiState::from_bits_retain(self.ui_state)
+    }
+
     pub fn get_map_id(&self) -> u32 {
         self.map_id
     }
 
-    pub fn get_map_center_y(&self) -> f32 {
-        self.map_center_y
-    }
-    pub fn get_map_center_x(&self) -> f32 {
-        self.map_center_x
+    pub fn get_map_type(&self) -> MapType {
+        MapType::from(self.map_type)
     }
-    pub fn get_map_scale(&self) -> f32 {
-        self.map_scale
+
+    pub fn get_build_id(&self) -> u32 {
+        self.build_id
     }
-}
 
-//struct __attribute__((packed)) LinkedMem {
-//    std::string get_identity() const;
-//    const MumbleContext* get_context() const;
-//    LinkedMem operator=(LinkedMemNet);
-//};
+    /// Width and height in pixels
+    pub fn get_compass_size(&self) -> [u16; 2] {
+        [self.compass_width, self.compass_height]
+    }
 
-static_assertions::const_assert_eq!(size_of::<LinkedMemNet>(), 1364);
-static_assertions::const_assert_eq!(size_of::<MumbleContext>(), 85);
+    /// Radians
+    pub fn get_compass_rotation(&self) -> f32 {
+        self.compass_rotation
+    }
 
-pub struct MutLinkedMem {
-    mem: *mut LinkedMem,
-}
+    /// Continent coordinates
+    pub fn get_player_position(&self) -> [f32; 2] {
+        [self.player_x, self.player_y]
+    }
 
-impl MutLinkedMem {
-    pub fn new(mem: *mut LinkedMem) -> Self {
-        Self { mem }
+    /// Continent coordinates
+    pub fn get_map_center(&self) -> [f32; 2] {
+        [self.map_center_x, self.map_center_y]
     }
-}
 
-unsafe impl Send for MutLinkedMem {}
-unsafe impl Send for LinkedMem {}
-unsafe impl Send for GW2Link {}
+    pub fn get_process_id(&self) -> u32 {
+        self.process_id
+    }
 
-unsafe impl Sync for MutLinkedMem {}
-unsafe impl Sync for LinkedMem {}
-unsafe impl Sync for GW2Link {}
+    pub fn get_mount(&self) -> Mount {
+        Mount::from(self.mount_index)
+    }
 
-pub struct GW2Link {
-    socket: UdpSocket,
-    gw2_data: MutLinkedMem,
-    last_update: Instant,
-}
+    pub fn get_shard_id(&self) -> u32 {
+        self.shard_id
+    }
 
-pub fn new_gw2link() -> Box<GW2Link> {
-    Box::new(GW2Link::new().unwrap())
-}
+    pub fn get_instance(&self) -> u32 {
+        self.instance
+    }
 
-impl GW2Link {
-    pub fn new() -> Option<Self> {
-        // TODO: create if not exist https://github.com/mumble-voip/mumble/blob/master/plugins/link/link-posix.cpp#L177
-        let memname: &str = &format!("/MumbleLink.{}", getuid());
-        let mut shmfd = shm_open(memname, OFlag::O_RDWR, Mode::S_IRUSR | Mode::S_IWUSR);
-
-        // Any error -> Doesn't exist
-        if shmfd.is_err() || (shmfd.is_ok() && shmfd.unwrap() < 0) {
-            shmfd = shm_open(
-                memname,
-                OFlag::O_RDWR | OFlag::O_CREAT,
-                Mode::S_IRUSR | Mode::S_IWUSR,
-            );
-            if let Ok(fd) = shmfd {
-                if fd > 0 {
-                    if ftruncate(fd, size_of::<LinkedMem>() as i64).is_err() {
-                        println!("Failed to resize shared memory");
-                        let _ = close(fd);
-                        return None;
-                    }
-                } else {
-                    println!("Failed to resize shared memory");
-                    return None;
-                }
+    /// Decodes the `sockaddr_in` or `sockaddr_in6` of the map server.
+    /// Returns `None` while not connected to a server
+    pub fn get_server_address(&self) -> Option<SocketAddr> {
+        let addr = self.server_address;
+        let family = u16::from_le_bytes([addr[0], addr[1]]);
+        let port = u16::from_be_bytes([addr[2], addr[3]]);
+        match family {
+            AF_INET => {
+                let ip = Ipv4Addr::new(addr[4], addr[5], addr[6], addr[7]);
+                Some(SocketAddr::V4(SocketAddrV4::new(ip, port)))
             }
-
-            // Error after create
-            if shmfd.is_err() || (shmfd.is_ok() && shmfd.unwrap() < 0) {
-                println!("Failed to shm_open");
+            AF_INET6_WINDOWS | AF_INET6_LINUX => {
+                let flowinfo = u32::from_be_bytes([addr[4], addr[5], addr[6], addr[7]]);
+                let mut ip = [0u8; 16];
+                ip.copy_from_slice(&addr[8..24]);
+                let scope_id = u32::from_le_bytes([addr[24], addr[25], addr[26], addr[27]]);
+                Some(SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::from(ip),
+                    port,
+                    flowinfo,
+                    scope_id,
+                )))
             }
+            _ => None,
         }
-        let shmfd = shmfd.unwrap();
-
-        let gw2_data;
-        unsafe {
-            let map = mmap(
-                None,
-                NonZeroUsize::new_unchecked(size_of::<LinkedMem>()),
-                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
-                MapFlags::MAP_SHARED,
-                shmfd,
-                0,
-            );
-
-            match map {
-                Ok(map) => {
-                    memset(map, 0, size_of::<LinkedMem>());
-                    gw2_data = MutLinkedMem::new(map as *mut LinkedMem);
-                }
-                Err(_) => return None,
-            };
-        }
-        let sock = Self::create_socket().unwrap();
-        Some(Self {
-            gw2_data,
-            socket: sock,
-            last_update: Instant::now(),
-        })
     }
 
-    fn create_socket() -> Result<UdpSocket, std::io::Error> {
-        UdpSocket::bind("127.0.0.1:7070")
-    }
-
-    pub fn update_gw2(&mut self, block: bool) -> bool {
-        let loop_begin = Instant::now();
-        let timeout;
-        if block {
-            timeout = Duration::from_millis(200);
-        } else {
-            timeout = Duration::from_nanos(1);
-            self.socket.set_nonblocking(true).unwrap();
-        }
-        self.socket.set_read_timeout(Some(timeout)).unwrap();
-        const STRUCT_SIZE: usize = size_of::<LinkedMemNet>();
-
-        let mut data: [u8; STRUCT_SIZE] = [0; STRUCT_SIZE];
-        // clear cache
-
-        let recv = self.socket.recv(&mut data);
-        match recv {
-            Ok(size) => {
-                //println!("Got data");
-                if size == STRUCT_SIZE {
-                    let mem_net: LinkedMemNet = unsafe { std::mem::transmute(data) };
-                    unsafe {
-                        *self.gw2_data.mem = LinkedMem::from(mem_net);
-                    }
-
-                    let last_update = Instant::now();
-                    unsafe {
-                        if (*self.gw2_data.mem).ui_tick == 0 {
-                            println!("UiTick is 0. If this message doesn't stop, make sure the mumble script is running!");
-                        }
-                    }
-                    let _time = last_update - loop_begin;
-                    self.last_update = last_update;
-                    //ffic::rust_set_time("link".to_string(), time.as_micros() as u64);
-                    //TODO: PerformanceStats::getInstance().set_time("link", time.count());
-                    return true;
-                } else {
-                    println!("Got wrong size. Got {} expected {}", size, STRUCT_SIZE);
-                }
-            }
-
-            Err(_e) => (), //println!("Error in update gw2: {e}"),
+    /// Identifies the map instance the player is in
+    pub fn get_instance_key(&self) -> InstanceKey {
+        InstanceKey {
+            map_id: self.map_id,
+            shard_id: self.shard_id,
+            instance: self.instance,
+            server_address: self.get_server_address(),
         }
-        false
     }
 
-    pub fn get_gw2_data(&self) -> Box<LinkedMem> {
-        let copy: LinkedMem = unsafe { (*self.gw2_data.mem).clone() };
-        Box::new(copy)
+    pub fn get_map_center_y(&self) -> f32 {
+        self.map_center_y
+    }
+    pub fn get_map_center_x(&self) -> f32 {
+        self.map_center_x
+    }
+    pub fn get_map_scale(&self) -> f32 {
+        self.map_scale
     }
 }
 
+//struct __attribute__((packed)) LinkedMem {
+//    std::string get_identity() const;
+//    const MumbleContext* get_context() const;
+//    LinkedMem operator=(LinkedMemNet);
+//};
+
+static_assertions::const_assert_eq!(size_of::<LinkedMemNet>(), LINKED_MEM_NET_SIZE);
+static_assertions::const_assert_eq!(size_of::<MumbleContext>(), MUMBLE_CONTEXT_SIZE);
+
+unsafe impl Send for LinkedMem {}
+unsafe impl Sync for LinkedMem {}
+
 #[cfg(test)]
 mod tests {
+    use std::net::SocketAddr;
+
     use super::MumbleContext;
 
     #[test]
@@ -328,4 +335,32 @@ mod tests {
             assert!(ctx.get_ui_state(1 << i));
         }
     }
+
+    #[test]
+    fn test_server_address() {
+        let mut ctx = MumbleContext::default();
+        assert_eq!(ctx.get_server_address(), None);
+
+        // sockaddr_in with 1.2.3.4:6112
+        ctx.server_address[0..2].copy_from_slice(&2u16.to_le_bytes());
+        ctx.server_address[2..4].copy_from_slice(&6112u16.to_be_bytes());
+        ctx.server_address[4..8].copy_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(
+            ctx.get_server_address(),
+            Some("1.2.3.4:6112".parse::<SocketAddr>().unwrap())
+        );
+
+        // sockaddr_in6 with [2001:db8::1]:6112
+        let mut addr = [0u8; 28];
+        addr[0..2].copy_from_slice(&23u16.to_le_bytes());
+        addr[2..4].copy_from_slice(&6112u16.to_be_bytes());
+        addr[8..10].copy_from_slice(&[0x20, 0x01]);
+        addr[10..12].copy_from_slice(&[0x0d, 0xb8]);
+        addr[23] = 1;
+        ctx.server_address = addr;
+        assert_eq!(
+            ctx.get_server_address(),
+            Some("[2001:db8::1]:6112".parse::<SocketAddr>().unwrap())
+        );
+    }
 }
@@ -0,0 +1,195 @@
+use std::{mem::size_of, num::NonZeroUsize, os::fd::RawFd, time::Instant};
+
+use nix::{
+    fcntl::OFlag,
+    libc::{c_void, memset},
+    sys::{
+        mman::{mmap, shm_open, MapFlags, ProtFlags},
+        stat::{fstat, Mode},
+    },
+    unistd::{close, ftruncate},
+};
+
+use crate::{
+    protocol::SequenceStats, Error, GW2LinkBuilder, HealthMonitor, LinkHealth, LinkSource,
+    LinkStats, LinkedMem, UdpSource,
+};
+
+pub struct MutLinkedMem {
+    mem: *mut LinkedMem,
+}
+
+impl MutLinkedMem {
+    pub fn new(mem: *mut LinkedMem) -> Self {
+        Self { mem }
+    }
+}
+
+unsafe impl Send for MutLinkedMem {}
+unsafe impl Send for GW2Link {}
+
+unsafe impl Sync for MutLinkedMem {}
+unsafe impl Sync for GW2Link {}
+
+pub struct GW2Link {
+    udp: UdpSource,
+    gw2_data: MutLinkedMem,
+    last_update: Instant,
+    health: HealthMonitor,
+    /// Last error `poll` stopped at
+    error: Option<Error>,
+}
+
+pub fn new_gw2link() -> Result<Box<GW2Link>, Error> {
+    Ok(Box::new(GW2Link::new()?))
+}
+
+/// Maps the shared memory `memname` with the size of `LinkedMem`.
+/// `writable` creates it if it doesn't exist yet
+pub(crate) fn map_shm(memname: &str, writable: bool) -> Result<*mut LinkedMem, Error> {
+    let shmfd = if writable {
+        open_or_create_shm(memname)?
+    } else {
+        let fd = shm_open(memname, OFlag::O_RDONLY, Mode::S_IRUSR)
+            .map_err(|e| Error::ShmOpen(e.into()))?;
+        let size = fstat(fd).map_err(|e| Error::ShmOpen(e.into()))?.st_size as usize;
+        if size < size_of::<LinkedMem>() {
+            let _ = close(fd);
+            return Err(Error::ShmSize {
+                got: size,
+                expected: size_of::<LinkedMem>(),
+            });
+        }
+        fd
+    };
+    let prot = if writable {
+        ProtFlags::PROT_READ | ProtFlags::PROT_WRITE
+    } else {
+        ProtFlags::PROT_READ
+    };
+
+    let map = unsafe {
+        mmap(
+            None,
+            NonZeroUsize::new_unchecked(size_of::<LinkedMem>()),
+            prot,
+            MapFlags::MAP_SHARED,
+            shmfd,
+            0,
+        )
+    };
+    // The mapping stays valid after closing the fd
+    let _ = close(shmfd);
+    Ok(map.map_err(|e| Error::Mmap(e.into()))? as *mut LinkedMem)
+}
+
+/// Opens the shared memory or creates it with the size of `LinkedMem` if it doesn't exist
+fn open_or_create_shm(memname: &str) -> Result<RawFd, Error> {
+    let shmfd = shm_open(memname, OFlag::O_RDWR, Mode::S_IRUSR | Mode::S_IWUSR);
+    if let Ok(fd) = shmfd {
+        return Ok(fd);
+    }
+
+    // Any error -> Doesn't exist
+    let fd = shm_open(
+        memname,
+        OFlag::O_RDWR | OFlag::O_CREAT,
+        Mode::S_IRUSR | Mode::S_IWUSR,
+    )
+    .map_err(|e| Error::ShmOpen(e.into()))?;
+    if let Err(e) = ftruncate(fd, size_of::<LinkedMem>() as i64) {
+        let _ = close(fd);
+        return Err(Error::Ftruncate(e.into()));
+    }
+    Ok(fd)
+}
+
+impl GW2Link {
+    /// Creates a link with the default settings. See [`GW2LinkBuilder`] to change them
+    pub fn new() -> Result<Self, Error> {
+        GW2LinkBuilder::new().build()
+    }
+
+    pub fn builder() -> GW2LinkBuilder {
+        GW2LinkBuilder::new()
+    }
+
+    pub(crate) fn from_builder(builder: &GW2LinkBuilder) -> Result<Self, Error> {
+        let map = map_shm(&builder.get_shm_name(), true)?;
+        unsafe {
+            memset(map as *mut c_void, 0, size_of::<LinkedMem>());
+        }
+        let gw2_data = MutLinkedMem::new(map);
+        let udp = UdpSource::from_builder(builder)?;
+        Ok(Self {
+            gw2_data,
+            udp,
+            last_update: Instant::now(),
+            health: HealthMonitor::new(),
+            error: None,
+        })
+    }
+
+    /// Receives a single packet and writes it to the shared memory.
+    /// Returns `Ok(false)` if no packet arrived in time
+    pub fn update_gw2(&mut self, block: bool) -> Result<bool, Error> {
+        let loop_begin = Instant::now();
+        let mem = match self.udp.receive(block)? {
+            Some(mem) => mem,
+            None => return Ok(false),
+        };
+        unsafe {
+            *self.gw2_data.mem = mem;
+        }
+
+        let last_update = Instant::now();
+        self.health.record_at(&mem, last_update);
+        let _time = last_update - loop_begin;
+        self.last_update = last_update;
+        //ffic::rust_set_time("link".to_string(), time.as_micros() as u64);
+        //TODO: PerformanceStats::getInstance().set_time("link", time.count());
+        Ok(true)
+    }
+
+    /// When the last packet arrived. Also set while the game doesn't update the link
+    pub fn get_last_update(&self) -> Instant {
+        self.last_update
+    }
+
+    pub fn get_health(&self) -> LinkHealth {
+        self.health.get_health()
+    }
+
+    pub fn get_stats(&self) -> LinkStats {
+        self.health.get_stats()
+    }
+
+    pub fn get_sequence_stats(&self) -> SequenceStats {
+        self.udp.get_sequence_stats()
+    }
+
+    pub fn get_gw2_data(&self) -> Box<LinkedMem> {
+        let copy: LinkedMem = unsafe { *self.gw2_data.mem };
+        Box::new(copy)
+    }
+}
+
+impl LinkSource for GW2Link {
+    fn poll(&mut self) -> Option<LinkedMem> {
+        loop {
+            match self.update_gw2(false) {
+                Ok(true) => return Some(*self.get_gw2_data()),
+                Ok(false) => return None,
+                Err(e) if e.is_packet_error() => continue,
+                Err(e) => {
+                    self.error = Some(e);
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+}
@@ -0,0 +1,331 @@
+//! Framed packets sent by the bridge.
+//!
+//! | bytes | content                                              |
+//! |-------|------------------------------------------------------|
+//! | 4     | magic `GW2L`                                         |
+//! | 2     | protocol version (little endian)                     |
+//! | 2     | [`PacketFlags`] (little endian)                      |
+//! | 4     | sequence number (little endian)                      |
+//! | 4     | payload length (little endian)                       |
+//!
+//! The payload starts with the `LinkedMemNet` bytes, followed by the description as UTF-16 if
+//! [`PacketFlags::DESCRIPTION`] is set. Receivers ignore unknown flags and payload bytes after
+//! the known parts, so later versions can append data without breaking older receivers.
+//! Packets without the magic are decoded as the legacy raw `LinkedMemNet`.
+
+use crate::{
+    codec::{ByteReader, ByteWriter},
+    Error, LinkedMem, LinkedMemNet, LINKED_MEM_NET_SIZE,
+};
+
+pub const MAGIC: &[u8; 4] = b"GW2L";
+pub const PROTOCOL_VERSION: u16 = 1;
+pub const HEADER_SIZE: usize = 16;
+pub const DESCRIPTION_SIZE: usize = 2048 * 2;
+/// Largest packet of the current version
+pub const MAX_PACKET_SIZE: usize = HEADER_SIZE + LINKED_MEM_NET_SIZE + DESCRIPTION_SIZE;
+
+/// A sequence number this far behind the expected one means the sender restarted
+const RESTART_DISTANCE: u32 = 1024;
+/// This many late packets in a row, each following the previous one, mean the sender
+/// restarted and its first packets were lost
+const RESTART_LATE_PACKETS: u32 = 3;
+
+bitflags::bitflags! {
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct PacketFlags: u16 {
+        /// The payload contains `LinkedMem::description`
+        const DESCRIPTION = 1 << 0;
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Packet {
+    /// `None` for legacy packets
+    pub sequence: Option<u32>,
+    pub mem: LinkedMem,
+}
+
+impl Packet {
+    /// Decodes a framed or a legacy packet
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        if !data.starts_with(MAGIC) {
+            return Ok(Self {
+                sequence: None,
+                mem: LinkedMem::from(LinkedMemNet::decode(data)?),
+            });
+        }
+
+        let mut reader = ByteReader::new(data);
+        reader.bytes::<4>()?;
+        let version = reader.u16()?;
+        if version != PROTOCOL_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let flags = PacketFlags::from_bits_truncate(reader.u16()?);
+        let sequence = reader.u32()?;
+        let payload_len = reader.u32()? as usize;
+        let payload = data
+            .get(HEADER_SIZE..HEADER_SIZE.saturating_add(payload_len))
+            .ok_or(Error::Truncated {
+                got: data.len(),
+                expected: HEADER_SIZE.saturating_add(payload_len),
+            })?;
+
+        let net = payload.get(..LINKED_MEM_NET_SIZE).ok_or(Error::Truncated {
+            got: payload.len(),
+            expected: LINKED_MEM_NET_SIZE,
+        })?;
+        let mut mem = LinkedMem::from(LinkedMemNet::decode(net)?);
+        if flags.contains(PacketFlags::DESCRIPTION) {
+            let mut reader = ByteReader::new(&payload[LINKED_MEM_NET_SIZE..]);
+            mem.description = reader.u16_array()?;
+        }
+        Ok(Self {
+            sequence: Some(sequence),
+            mem,
+        })
+    }
+
+    /// Encodes the packet in the current version. `flags` selects the optional parts.
+    /// Legacy packets are written with sequence 0
+    pub fn encode(&self, flags: PacketFlags) -> Vec<u8> {
+        let mut payload_len = LINKED_MEM_NET_SIZE;
+        if flags.contains(PacketFlags::DESCRIPTION) {
+            payload_len += DESCRIPTION_SIZE;
+        }
+        let mut data = vec![0u8; HEADER_SIZE + payload_len];
+        let mut writer = ByteWriter::new(&mut data);
+        writer.bytes(MAGIC);
+        writer.u16(PROTOCOL_VERSION);
+        writer.u16(flags.bits());
+        writer.u32(self.sequence.unwrap_or_default());
+        writer.u32(payload_len as u32);
+        writer.bytes(&LinkedMemNet::from(&self.mem).encode());
+        if flags.contains(PacketFlags::DESCRIPTION) {
+            let description = self.mem.description;
+            writer.u16_array(&description);
+        }
+        data
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SequenceStatus {
+    /// The expected packet or the first one
+    InOrder,
+    /// Packets between the previous and this one are missing
+    Dropped(u32),
+    /// Older than a packet that was already received
+    Late,
+    /// The sender started counting from the beginning
+    Restarted,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SequenceStats {
+    pub received: u64,
+    pub dropped: u64,
+    pub late: u64,
+    pub restarts: u64,
+}
+
+/// Checks the sequence numbers of framed packets for gaps and reordering
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    expected: Option<u32>,
+    /// The last late packet and how many late packets in a row led up to it
+    late_run: Option<(u32, u32)>,
+    stats: SequenceStats,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_stats(&self) -> SequenceStats {
+        self.stats
+    }
+
+    pub fn update(&mut self, sequence: u32) -> SequenceStatus {
+        self.stats.received += 1;
+        let expected = match self.expected {
+            Some(expected) => expected,
+            None => {
+                self.expected = Some(sequence.wrapping_add(1));
+                return SequenceStatus::InOrder;
+            }
+        };
+        let ahead = sequence.wrapping_sub(expected);
+        let behind = expected.wrapping_sub(sequence);
+        let status = if ahead == 0 {
+            SequenceStatus::InOrder
+        } else if sequence == 0 || (behind > RESTART_DISTANCE && ahead > u32::MAX / 2) {
+            self.stats.restarts += 1;
+            SequenceStatus::Restarted
+        } else if ahead < u32::MAX / 2 {
+            self.stats.dropped += ahead as u64;
+            SequenceStatus::Dropped(ahead)
+        } else {
+            let run = match self.late_run {
+                Some((last, run))
+                    if sequence.wrapping_sub(last).wrapping_sub(1) < RESTART_DISTANCE =>
+                {
+                    run + 1
+                }
+                _ => 1,
+            };
+            if run < RESTART_LATE_PACKETS {
+                self.late_run = Some((sequence, run));
+                self.stats.late += 1;
+                return SequenceStatus::Late;
+            }
+            self.stats.restarts += 1;
+            SequenceStatus::Restarted
+        };
+        self.late_run = None;
+        self.expected = Some(sequence.wrapping_add(1));
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::{
+        Packet, PacketFlags, SequenceStatus, SequenceTracker, HEADER_SIZE, MAX_PACKET_SIZE,
+    };
+    use crate::{Error, LinkedMem, LinkedMemNet};
+
+    fn mem(tick: u32) -> LinkedMem {
+        LinkedMem {
+            ui_tick: tick,
+            camera_position: [1.0, 2.0, 3.0],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_legacy_packet() {
+        let data = LinkedMemNet::from(&mem(3)).encode();
+        let packet = Packet::decode(&data).unwrap();
+        assert_eq!(packet.sequence, None);
+        assert_eq!(packet.mem.get_ui_tick(), 3);
+        assert!(matches!(
+            Packet::decode(&data[..100]),
+            Err(Error::PacketSize { .. })
+        ));
+    }
+
+    #[test]
+    fn test_framed_packet() {
+        let mut mem = mem(3);
+        mem.description[0] = b'x' as u16;
+        let packet = Packet {
+            sequence: Some(7),
+            mem,
+        };
+
+        let data = packet.encode(PacketFlags::empty());
+        assert_eq!(&data[..4], b"GW2L");
+        let decoded = Packet::decode(&data).unwrap();
+        assert_eq!(decoded.sequence, Some(7));
+        assert_eq!(decoded.mem.get_camera_pos(), [1.0, 2.0, 3.0]);
+        let description = decoded.mem.description;
+        assert_eq!(description[0], 0);
+
+        let data = packet.encode(PacketFlags::DESCRIPTION);
+        assert_eq!(data.len(), MAX_PACKET_SIZE);
+        let description = Packet::decode(&data).unwrap().mem.description;
+        assert_eq!(description[0], b'x' as u16);
+
+        assert!(matches!(
+            Packet::decode(&data[..HEADER_SIZE + 10]),
+            Err(Error::Truncated { .. })
+        ));
+        let mut future = data.clone();
+        future[4] = 2;
+        assert!(matches!(
+            Packet::decode(&future),
+            Err(Error::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn test_forward_compatible() {
+        // A newer sender appending data and setting unknown flags
+        let mut data = Packet {
+            sequence: Some(1),
+            mem: mem(3),
+        }
+        .encode(PacketFlags::empty());
+        data[6] |= 0x80;
+        data.extend_from_slice(&[1, 2, 3, 4]);
+        let payload_len = (data.len() - HEADER_SIZE) as u32;
+        data[12..16].copy_from_slice(&payload_len.to_le_bytes());
+        assert_eq!(Packet::decode(&data).unwrap().mem.get_ui_tick(), 3);
+    }
+
+    #[test]
+    fn test_sequence_tracker() {
+        let mut tracker = SequenceTracker::new();
+        assert_eq!(tracker.update(10), SequenceStatus::InOrder);
+        assert_eq!(tracker.update(11), SequenceStatus::InOrder);
+        assert_eq!(tracker.update(14), SequenceStatus::Dropped(2));
+        assert_eq!(tracker.update(12), SequenceStatus::Late);
+        assert_eq!(tracker.update(15), SequenceStatus::InOrder);
+        assert_eq!(tracker.update(0), SequenceStatus::Restarted);
+        assert_eq!(tracker.update(1), SequenceStatus::InOrder);
+        assert_eq!(tracker.update(u32::MAX), SequenceStatus::Late);
+
+        let stats = tracker.get_stats();
+        assert_eq!(stats.received, 8);
+        assert_eq!(stats.dropped, 2);
+        assert_eq!(stats.late, 2);
+        assert_eq!(stats.restarts, 1);
+
+        let mut tracker = SequenceTracker::new();
+        tracker.update(u32::MAX);
+        assert_eq!(tracker.update(0), SequenceStatus::InOrder);
+    }
+
+    #[test]
+    fn test_sequence_restart_without_first_packet() {
+        let mut tracker = SequenceTracker::new();
+        for sequence in 0..500 {
+            tracker.update(sequence);
+        }
+        // The restarted sender's packet 0 is lost
+        assert_eq!(tracker.update(1), SequenceStatus::Late);
+        assert_eq!(tracker.update(2), SequenceStatus::Late);
+        assert_eq!(tracker.update(3), SequenceStatus::Restarted);
+        assert_eq!(tracker.update(4), SequenceStatus::InOrder);
+        assert_eq!(tracker.get_stats().restarts, 1);
+
+        // Late packets in between in order ones don't count as a restart
+        assert_eq!(tracker.update(2), SequenceStatus::Late);
+        assert_eq!(tracker.update(5), SequenceStatus::InOrder);
+        assert_eq!(tracker.update(3), SequenceStatus::Late);
+        assert_eq!(tracker.update(6), SequenceStatus::InOrder);
+        assert_eq!(tracker.update(4), SequenceStatus::Late);
+        assert_eq!(tracker.get_stats().restarts, 1);
+    }
+
+    proptest! {
+        #[test]
+        fn decode_never_panics(data in proptest::collection::vec(any::<u8>(), 0..MAX_PACKET_SIZE + 16)) {
+            let _ = Packet::decode(&data);
+        }
+
+        #[test]
+        fn framed_round_trip(sequence: u32, tick: u32, description: bool) {
+            let flags = if description { PacketFlags::DESCRIPTION } else { PacketFlags::empty() };
+            let data = Packet { sequence: Some(sequence), mem: mem(tick) }.encode(flags);
+            let decoded = Packet::decode(&data).unwrap();
+            prop_assert_eq!(decoded.sequence, Some(sequence));
+            prop_assert_eq!(decoded.mem.get_ui_tick(), tick);
+        }
+    }
+}
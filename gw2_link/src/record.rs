@@ -0,0 +1,397 @@
+//! Recording and replaying of MumbleLink sessions.
+//!
+//! A recording starts with a small header followed by one frame per update:
+//!
+//! | bytes  | content                                                   |
+//! |--------|-----------------------------------------------------------|
+//! | 8      | magic `GW2LREC\0`                                         |
+//! | 2      | format version (little endian)                            |
+//! | 2      | size of the stored `LinkedMemNet` (little endian)         |
+//!
+//! Each frame is a little endian `u64` with the microseconds since the start of the recording
+//! followed by the `LinkedMemNet` bytes. The description is not part of `LinkedMemNet` and
+//! therefore not recorded.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{Error, LinkSource, LinkedMem, LinkedMemNet, LINKED_MEM_NET_SIZE};
+
+pub const MAGIC: &[u8; 8] = b"GW2LREC\0";
+pub const VERSION: u16 = 1;
+
+/// A single recorded update
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    /// Time since the start of the recording
+    pub timestamp: Duration,
+    pub mem: LinkedMem,
+}
+
+/// Writes frames to a recording
+pub struct Recorder<W: Write> {
+    writer: W,
+    start: Instant,
+}
+
+impl Recorder<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    /// Writes the header. The timestamps of `record` are relative to this call
+    pub fn new(mut writer: W) -> Result<Self, Error> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(LINKED_MEM_NET_SIZE as u16).to_le_bytes())?;
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, mem: &LinkedMem) -> Result<(), Error> {
+        self.record_at(self.start.elapsed(), mem)
+    }
+
+    pub fn record_at(&mut self, timestamp: Duration, mem: &LinkedMem) -> Result<(), Error> {
+        self.writer
+            .write_all(&(timestamp.as_micros() as u64).to_le_bytes())?;
+        self.writer.write_all(&LinkedMemNet::from(mem).encode())?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads frames from a recording
+pub struct RecordingReader<R: Read> {
+    reader: R,
+}
+
+impl RecordingReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> RecordingReader<R> {
+    /// Reads and validates the header
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        if &header[0..8] != MAGIC {
+            return Err(Error::BadRecording("missing magic".to_string()));
+        }
+        let version = u16::from_le_bytes([header[8], header[9]]);
+        if version != VERSION {
+            return Err(Error::BadRecording(format!(
+                "unsupported version {}",
+                version
+            )));
+        }
+        let frame_size = u16::from_le_bytes([header[10], header[11]]) as usize;
+        if frame_size != LINKED_MEM_NET_SIZE {
+            return Err(Error::BadRecording(format!(
+                "frame size {} expected {}",
+                frame_size, LINKED_MEM_NET_SIZE
+            )));
+        }
+        Ok(Self { reader })
+    }
+
+    /// Returns `Ok(None)` at the end of the recording
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, Error> {
+        let mut timestamp = [0u8; 8];
+        match self.reader.read_exact(&mut timestamp) {
+            Ok(()) => (),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let mut data = [0u8; LINKED_MEM_NET_SIZE];
+        self.reader.read_exact(&mut data)?;
+        Ok(Some(Frame {
+            timestamp: Duration::from_micros(u64::from_le_bytes(timestamp)),
+            mem: LinkedMem::from(LinkedMemNet::decode(&data)?),
+        }))
+    }
+
+    pub fn read_all(mut self) -> Result<Vec<Frame>, Error> {
+        let mut frames = vec![];
+        while let Some(frame) = self.next_frame()? {
+            frames.push(frame);
+        }
+        Ok(frames)
+    }
+}
+
+impl<R: Read> Iterator for RecordingReader<R> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+/// Wraps another source and records everything it returns
+pub struct RecordingSource<S: LinkSource, W: Write + Send + Sync> {
+    source: S,
+    recorder: Recorder<W>,
+    /// Last frame that couldn't be recorded. `poll` still returns the frame
+    error: Option<Error>,
+}
+
+impl<S: LinkSource, W: Write + Send + Sync> RecordingSource<S, W> {
+    pub fn new(source: S, recorder: Recorder<W>) -> Self {
+        Self {
+            source,
+            recorder,
+            error: None,
+        }
+    }
+
+    pub fn into_inner(self) -> (S, Recorder<W>) {
+        (self.source, self.recorder)
+    }
+}
+
+impl<S: LinkSource, W: Write + Send + Sync> LinkSource for RecordingSource<S, W> {
+    fn poll(&mut self) -> Option<LinkedMem> {
+        let mem = self.source.poll()?;
+        if let Err(e) = self.recorder.record(&mem) {
+            self.error = Some(e);
+        }
+        Some(mem)
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+}
+
+/// Returns the frames of a recording with their original timing.
+/// The clock starts with the first poll
+pub struct ReplaySource {
+    frames: Vec<Frame>,
+    next: usize,
+    start: Option<Instant>,
+    /// Length of all finished loops
+    offset: Duration,
+    looping: bool,
+}
+
+impl ReplaySource {
+    pub fn new(frames: Vec<Frame>) -> Self {
+        Self {
+            frames,
+            next: 0,
+            start: None,
+            offset: Duration::ZERO,
+            looping: false,
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(RecordingReader::open(path)?.read_all()?))
+    }
+
+    /// Starts from the beginning after the last frame
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.next >= self.frames.len()
+    }
+
+    /// Returns the next frame if it is due at `elapsed` since the start of the replay
+    pub fn poll_at(&mut self, elapsed: Duration) -> Option<LinkedMem> {
+        let mut elapsed = elapsed.saturating_sub(self.offset);
+        if self.looping && self.next >= self.frames.len() && !self.frames.is_empty() {
+            let length = self.frames[self.frames.len() - 1].timestamp;
+            // Without a length every poll would start a new loop, so the frames play once
+            if length.is_zero() || elapsed < length {
+                return None;
+            }
+            self.offset += length;
+            self.next = 0;
+            elapsed -= length;
+        }
+        let frame = self.frames.get(self.next)?;
+        if frame.timestamp > elapsed {
+            return None;
+        }
+        self.next += 1;
+        Some(frame.mem)
+    }
+}
+
+impl LinkSource for ReplaySource {
+    fn poll(&mut self) -> Option<LinkedMem> {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        self.poll_at(start.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use std::io::{self, Write};
+
+    use super::{Frame, Recorder, RecordingReader, RecordingSource, ReplaySource};
+    use crate::{Error, LinkSource, LinkedMem, MockSource};
+
+    fn mem(tick: u32) -> LinkedMem {
+        LinkedMem {
+            ui_tick: tick,
+            camera_position: [1.0, 2.0, tick as f32],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        recorder.record_at(Duration::ZERO, &mem(1)).unwrap();
+        recorder
+            .record_at(Duration::from_millis(16), &mem(2))
+            .unwrap();
+        let data = recorder.into_inner();
+
+        let frames = RecordingReader::new(data.as_slice())
+            .unwrap()
+            .read_all()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].timestamp, Duration::from_millis(16));
+        assert_eq!(frames[1].mem.get_ui_tick(), 2);
+        assert_eq!(frames[1].mem.get_camera_pos(), [1.0, 2.0, 2.0]);
+
+        // Truncated frames are an error, not the end of the recording
+        let truncated = &data[..data.len() - 10];
+        let mut reader = RecordingReader::new(truncated).unwrap();
+        assert!(reader.next_frame().unwrap().is_some());
+        assert!(reader.next_frame().is_err());
+
+        assert!(matches!(
+            RecordingReader::new(&b"not a recording"[..]),
+            Err(Error::BadRecording(_))
+        ));
+    }
+
+    #[test]
+    fn test_replay_timing() {
+        let frames = RecordingReader::new({
+            let mut recorder = Recorder::new(Vec::new()).unwrap();
+            recorder.record_at(Duration::ZERO, &mem(1)).unwrap();
+            recorder
+                .record_at(Duration::from_millis(50), &mem(2))
+                .unwrap();
+            recorder.into_inner().as_slice()
+        })
+        .unwrap()
+        .read_all()
+        .unwrap();
+
+        let mut replay = ReplaySource::new(frames.clone());
+        assert_eq!(replay.poll_at(Duration::ZERO).unwrap().get_ui_tick(), 1);
+        assert!(replay.poll_at(Duration::from_millis(10)).is_none());
+        assert_eq!(
+            replay
+                .poll_at(Duration::from_millis(50))
+                .unwrap()
+                .get_ui_tick(),
+            2
+        );
+        assert!(replay.poll_at(Duration::from_millis(100)).is_none());
+        assert!(replay.is_finished());
+
+        let mut replay = ReplaySource::new(frames).looping(true);
+        assert_eq!(replay.poll_at(Duration::ZERO).unwrap().get_ui_tick(), 1);
+        assert_eq!(
+            replay
+                .poll_at(Duration::from_millis(50))
+                .unwrap()
+                .get_ui_tick(),
+            2
+        );
+        assert_eq!(
+            replay
+                .poll_at(Duration::from_millis(60))
+                .unwrap()
+                .get_ui_tick(),
+            1
+        );
+        assert!(!replay.is_finished());
+    }
+
+    /// Fails every write after the header
+    struct FullDisk {
+        written: usize,
+    }
+
+    impl Write for FullDisk {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written >= 12 {
+                return Err(io::Error::new(io::ErrorKind::StorageFull, "disk full"));
+            }
+            self.written += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_recording_error() {
+        let recorder = Recorder::new(FullDisk { written: 0 }).unwrap();
+        let mut source = RecordingSource::new(MockSource::from_iter([mem(1)]), recorder);
+        assert!(source.take_error().is_none());
+        // The frame is still returned, the error is kept for the caller
+        assert_eq!(source.poll().unwrap().get_ui_tick(), 1);
+        assert!(matches!(source.take_error(), Some(Error::Io(_))));
+        assert!(source.take_error().is_none());
+    }
+
+    #[test]
+    fn test_replay_single_frame_looping() {
+        let mut replay = ReplaySource::new(vec![Frame {
+            timestamp: Duration::ZERO,
+            mem: mem(1),
+        }])
+        .looping(true);
+        assert_eq!(replay.poll_at(Duration::ZERO).unwrap().get_ui_tick(), 1);
+        assert!(replay.poll_at(Duration::ZERO).is_none());
+        assert!(replay.poll_at(Duration::from_secs(1)).is_none());
+
+        // Draining the source like the overlay does ends
+        let mut replay = ReplaySource::new(vec![Frame {
+            timestamp: Duration::ZERO,
+            mem: mem(1),
+        }])
+        .looping(true);
+        let mut frames = 0;
+        while replay.poll().is_some() {
+            frames += 1;
+            assert!(frames < 10);
+        }
+        assert_eq!(frames, 1);
+    }
+}
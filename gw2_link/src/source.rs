@@ -0,0 +1,258 @@
+use std::{collections::VecDeque, io::ErrorKind, net::UdpSocket, time::Duration};
+
+#[cfg(unix)]
+use crate::link::map_shm;
+use crate::{
+    protocol::{Packet, SequenceStats, SequenceStatus, SequenceTracker},
+    Error, GW2LinkBuilder, LinkedMem,
+};
+
+/// Largest possible udp payload. Newer senders may append data to the packets we know
+const MAX_DATAGRAM_SIZE: usize = 65507;
+
+/// Anything that produces `LinkedMem` updates
+pub trait LinkSource: Send + Sync {
+    /// Returns the next update or `None` if there is nothing new right now
+    fn poll(&mut self) -> Option<LinkedMem>;
+
+    /// Returns and clears an error that happened during `poll` but didn't stop it, e.g. a
+    /// frame that couldn't be recorded
+    fn take_error(&mut self) -> Option<Error> {
+        None
+    }
+}
+
+impl<T: LinkSource + ?Sized> LinkSource for Box<T> {
+    fn poll(&mut self) -> Option<LinkedMem> {
+        (**self).poll()
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        (**self).take_error()
+    }
+}
+
+/// Receives the packets sent by the helper. See [`Packet`] for the format
+pub struct UdpSource {
+    socket: UdpSocket,
+    timeout: Duration,
+    buffer: Vec<u8>,
+    sequence: SequenceTracker,
+    /// Last socket error `poll` stopped at
+    error: Option<Error>,
+}
+
+impl UdpSource {
+    pub(crate) fn from_builder(builder: &GW2LinkBuilder) -> Result<Self, Error> {
+        let socket = UdpSocket::bind(builder.get_socket_addr()).map_err(Error::SocketBind)?;
+        Ok(Self {
+            socket,
+            timeout: builder.get_timeout(),
+            buffer: vec![0; MAX_DATAGRAM_SIZE],
+            sequence: SequenceTracker::new(),
+            error: None,
+        })
+    }
+
+    /// Dropped and reordered packets. Legacy packets have no sequence number and aren't counted
+    pub fn get_sequence_stats(&self) -> SequenceStats {
+        self.sequence.get_stats()
+    }
+
+    /// Receives a single packet. Returns `Ok(None)` if no packet arrived in time.
+    /// Packets older than an already received one are returned as [`Error::OutOfOrder`]
+    pub fn receive(&mut self, block: bool) -> Result<Option<LinkedMem>, Error> {
+        self.socket.set_nonblocking(!block).map_err(Error::Socket)?;
+        if block {
+            self.socket
+                .set_read_timeout(Some(self.timeout))
+                .map_err(Error::Socket)?;
+        }
+        let size = match self.socket.recv(&mut self.buffer) {
+            Ok(size) => size,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(None)
+            }
+            Err(e) => return Err(Error::Socket(e)),
+        };
+        let packet = Packet::decode(&self.buffer[..size])?;
+        if let Some(sequence) = packet.sequence {
+            if self.sequence.update(sequence) == SequenceStatus::Late {
+                return Err(Error::OutOfOrder { sequence });
+            }
+        }
+        Ok(Some(packet.mem))
+    }
+}
+
+impl LinkSource for UdpSource {
+    fn poll(&mut self) -> Option<LinkedMem> {
+        loop {
+            match self.receive(false) {
+                Ok(mem) => return mem,
+                // Skip broken packets and try the next one
+                Err(e) if e.is_packet_error() => continue,
+                Err(e) => {
+                    self.error = Some(e);
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+}
+
+/// Reads an existing MumbleLink shared memory that is written by someone else
+/// e.g. a game that writes `/MumbleLink.{uid}` natively
+#[cfg(unix)]
+pub struct ShmSource {
+    mem: *const LinkedMem,
+    last_tick: Option<u32>,
+}
+
+#[cfg(unix)]
+unsafe impl Send for ShmSource {}
+#[cfg(unix)]
+unsafe impl Sync for ShmSource {}
+
+#[cfg(unix)]
+impl ShmSource {
+    pub fn open(memname: &str) -> Result<Self, Error> {
+        let mem = map_shm(memname, false)?;
+        Ok(Self {
+            mem,
+            last_tick: None,
+        })
+    }
+}
+
+#[cfg(unix)]
+impl LinkSource for ShmSource {
+    /// Only returns data if `ui_tick` changed since the last poll
+    fn poll(&mut self) -> Option<LinkedMem> {
+        let mem = unsafe { std::ptr::read_volatile(self.mem) };
+        let tick = mem.get_ui_tick();
+        if self.last_tick == Some(tick) {
+            return None;
+        }
+        self.last_tick = Some(tick);
+        Some(mem)
+    }
+}
+
+/// In memory source returning queued updates. Useful for tests and debugging without a game
+#[derive(Default)]
+pub struct MockSource {
+    queue: VecDeque<LinkedMem>,
+}
+
+impl MockSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, mem: LinkedMem) {
+        self.queue.push_back(mem);
+    }
+}
+
+impl FromIterator<LinkedMem> for MockSource {
+    fn from_iter<T: IntoIterator<Item = LinkedMem>>(iter: T) -> Self {
+        Self {
+            queue: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl LinkSource for MockSource {
+    fn poll(&mut self) -> Option<LinkedMem> {
+        self.queue.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+
+    use super::{LinkSource, MockSource};
+    use crate::{
+        protocol::{Packet, PacketFlags},
+        GW2LinkBuilder, LinkedMem, LINKED_MEM_NET_SIZE,
+    };
+
+    #[test]
+    fn test_mock_source() {
+        let first = LinkedMem {
+            ui_tick: 1,
+            ..Default::default()
+        };
+        let second = LinkedMem {
+            ui_tick: 2,
+            ..Default::default()
+        };
+
+        let mut source: MockSource = [first, second].into_iter().collect();
+        assert_eq!(source.poll().unwrap().get_ui_tick(), 1);
+        assert_eq!(source.poll().unwrap().get_ui_tick(), 2);
+        assert!(source.poll().is_none());
+    }
+
+    #[test]
+    fn test_udp_source() {
+        let mut source = GW2LinkBuilder::new().port(0).build_udp_source().unwrap();
+        let addr = source.socket.local_addr().unwrap();
+        assert!(source.poll().is_none());
+
+        let mut data = [0u8; LINKED_MEM_NET_SIZE];
+        // ui_tick
+        data[4..8].copy_from_slice(&42u32.to_le_bytes());
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        // Broken packets are skipped
+        sender.send_to(&data[..10], addr).unwrap();
+        sender.send_to(&data, addr).unwrap();
+
+        let mem = source.receive(true).unwrap_err();
+        assert!(matches!(mem, crate::Error::PacketSize { got: 10, .. }));
+        let mem = source.receive(true).unwrap().unwrap();
+        assert_eq!(mem.get_ui_tick(), 42);
+
+        // Framed packets with a late one in between
+        for (sequence, tick) in [(5, 43), (7, 44), (6, 45)] {
+            let packet = Packet {
+                sequence: Some(sequence),
+                mem: LinkedMem {
+                    ui_tick: tick,
+                    ..Default::default()
+                },
+            };
+            sender
+                .send_to(&packet.encode(PacketFlags::empty()), addr)
+                .unwrap();
+        }
+        assert_eq!(source.receive(true).unwrap().unwrap().get_ui_tick(), 43);
+        assert_eq!(source.receive(true).unwrap().unwrap().get_ui_tick(), 44);
+        assert!(matches!(
+            source.receive(true),
+            Err(crate::Error::OutOfOrder { sequence: 6 })
+        ));
+        let stats = source.get_sequence_stats();
+        assert_eq!((stats.dropped, stats.late), (1, 1));
+
+        // Broken packets are skipped, not reported
+        sender.send_to(&data[..10], addr).unwrap();
+        sender.send_to(&data, addr).unwrap();
+        let mut mem = None;
+        for _ in 0..100 {
+            mem = source.poll();
+            if mem.is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(mem.unwrap().get_ui_tick(), 42);
+        assert!(source.take_error().is_none());
+    }
+}
@@ -0,0 +1,318 @@
+use std::{fmt::Display, net::SocketAddr, str::FromStr};
+
+use crate::{
+    identity::{parse_name, write_name},
+    Error, LinkedMem, MumbleContext,
+};
+
+bitflags::bitflags! {
+    /// `MumbleContext::ui_state`
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct UiState: u32 {
+        const MAP_OPEN = 1 << 0;
+        const COMPASS_TOP_RIGHT = 1 << 1;
+        const COMPASS_ROTATION = 1 << 2;
+        const GAME_FOCUS = 1 << 3;
+        const COMPETITIVE_MODE = 1 << 4;
+        const TEXTBOX_FOCUS = 1 << 5;
+        const COMBAT = 1 << 6;
+    }
+}
+
+impl UiState {
+    pub fn is_map_open(&self) -> bool {
+        self.contains(Self::MAP_OPEN)
+    }
+
+    pub fn is_compass_top_right(&self) -> bool {
+        self.contains(Self::COMPASS_TOP_RIGHT)
+    }
+
+    pub fn has_compass_rotation(&self) -> bool {
+        self.contains(Self::COMPASS_ROTATION)
+    }
+
+    pub fn has_game_focus(&self) -> bool {
+        self.contains(Self::GAME_FOCUS)
+    }
+
+    pub fn is_competitive_mode(&self) -> bool {
+        self.contains(Self::COMPETITIVE_MODE)
+    }
+
+    pub fn has_textbox_focus(&self) -> bool {
+        self.contains(Self::TEXTBOX_FOCUS)
+    }
+
+    pub fn in_combat(&self) -> bool {
+        self.contains(Self::COMBAT)
+    }
+}
+
+/// `MumbleContext::map_type`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MapType {
+    #[default]
+    Redirect,
+    CharacterCreate,
+    Pvp,
+    Gvg,
+    Instance,
+    Public,
+    Tournament,
+    Tutorial,
+    UserTournament,
+    /// Eternal Battlegrounds
+    Center,
+    BlueHome,
+    GreenHome,
+    RedHome,
+    FortunesVale,
+    ObsidianSanctum,
+    EdgeOfTheMists,
+    PublicMini,
+    BigBattle,
+    WvwLounge,
+    Unknown(u32),
+}
+
+impl From<u32> for MapType {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => MapType::Redirect,
+            1 => MapType::CharacterCreate,
+            2 => MapType::Pvp,
+            3 => MapType::Gvg,
+            4 => MapType::Instance,
+            5 => MapType::Public,
+            6 => MapType::Tournament,
+            7 => MapType::Tutorial,
+            8 => MapType::UserTournament,
+            9 => MapType::Center,
+            10 => MapType::BlueHome,
+            11 => MapType::GreenHome,
+            12 => MapType::RedHome,
+            13 => MapType::FortunesVale,
+            14 => MapType::ObsidianSanctum,
+            15 => MapType::EdgeOfTheMists,
+            16 => MapType::PublicMini,
+            17 => MapType::BigBattle,
+            18 => MapType::WvwLounge,
+            other => MapType::Unknown(other),
+        }
+    }
+}
+
+impl MapType {
+    pub fn is_wvw(&self) -> bool {
+        matches!(
+            self,
+            MapType::Center
+                | MapType::BlueHome
+                | MapType::GreenHome
+                | MapType::RedHome
+                | MapType::FortunesVale
+                | MapType::ObsidianSanctum
+                | MapType::EdgeOfTheMists
+                | MapType::WvwLounge
+        )
+    }
+}
+
+/// `MumbleContext::mount_index`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Mount {
+    #[default]
+    None,
+    Jackal,
+    Griffon,
+    Springer,
+    Skimmer,
+    Raptor,
+    RollerBeetle,
+    Warclaw,
+    Skyscale,
+    Skiff,
+    SiegeTurtle,
+    Unknown(u8),
+}
+
+impl From<u8> for Mount {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Mount::None,
+            1 => Mount::Jackal,
+            2 => Mount::Griffon,
+            3 => Mount::Springer,
+            4 => Mount::Skimmer,
+            5 => Mount::Raptor,
+            6 => Mount::RollerBeetle,
+            7 => Mount::Warclaw,
+            8 => Mount::Skyscale,
+            9 => Mount::Skiff,
+            10 => Mount::SiegeTurtle,
+            other => Mount::Unknown(other),
+        }
+    }
+}
+
+impl Mount {
+    /// The names marker packs use
+    pub const NAMES: &'static [(Self, &'static str)] = &[
+        (Mount::Jackal, "jackal"),
+        (Mount::Griffon, "griffon"),
+        (Mount::Springer, "springer"),
+        (Mount::Skimmer, "skimmer"),
+        (Mount::Raptor, "raptor"),
+        (Mount::RollerBeetle, "rollerbeetle"),
+        (Mount::Warclaw, "warclaw"),
+        (Mount::Skyscale, "skyscale"),
+        (Mount::Skiff, "skiff"),
+        (Mount::SiegeTurtle, "siegeturtle"),
+        // The spelling of TacO
+        (Mount::SiegeTurtle, "seigeturtle"),
+    ];
+}
+
+impl FromStr for Mount {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_name(Self::NAMES, "mount", s)
+    }
+}
+
+impl Display for Mount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unknown = match self {
+            Mount::Unknown(number) => Some(*number),
+            _ => None,
+        };
+        write_name(f, Self::NAMES, self, unknown)
+    }
+}
+
+/// Snapshot of a `LinkedMem` and its context with proper types
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GameState {
+    pub ui_version: u32,
+    pub ui_tick: u32,
+    pub avatar_position: [f32; 3],
+    pub avatar_front: [f32; 3],
+    pub avatar_top: [f32; 3],
+    pub camera_position: [f32; 3],
+    pub camera_front: [f32; 3],
+    pub camera_top: [f32; 3],
+    pub map_id: u32,
+    pub map_type: MapType,
+    pub shard_id: u32,
+    pub instance: u32,
+    pub build_id: u32,
+    pub server_address: Option<SocketAddr>,
+    pub ui_state: UiState,
+    /// Width and height in pixels
+    pub compass_size: [u16; 2],
+    /// Radians
+    pub compass_rotation: f32,
+    /// Continent coordinates
+    pub player_position: [f32; 2],
+    /// Continent coordinates
+    pub map_center: [f32; 2],
+    pub map_scale: f32,
+    pub process_id: u32,
+    pub mount: Mount,
+}
+
+impl From<&LinkedMem> for GameState {
+    fn from(mem: &LinkedMem) -> Self {
+        let ctx: MumbleContext = *mem.get_context();
+        Self {
+            ui_version: mem.ui_version,
+            ui_tick: mem.ui_tick,
+            avatar_position: mem.avatar_position,
+            avatar_front: mem.avatar_front,
+            avatar_top: mem.avatar_top,
+            camera_position: mem.camera_position,
+            camera_front: mem.camera_front,
+            camera_top: mem.camera_top,
+            map_id: ctx.get_map_id(),
+            map_type: ctx.get_map_type(),
+            shard_id: ctx.get_shard_id(),
+            instance: ctx.get_instance(),
+            build_id: ctx.get_build_id(),
+            server_address: ctx.get_server_address(),
+            ui_state: ctx.get_ui_flags(),
+            compass_size: ctx.get_compass_size(),
+            compass_rotation: ctx.get_compass_rotation(),
+            player_position: ctx.get_player_position(),
+            map_center: ctx.get_map_center(),
+            map_scale: ctx.get_map_scale(),
+            process_id: ctx.get_process_id(),
+            mount: ctx.get_mount(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GameState, MapType, Mount, UiState};
+    use crate::{LinkedMem, MumbleContext};
+
+    #[test]
+    fn test_ui_state_flags() {
+        let state = UiState::from_bits_truncate((1 << 0) | (1 << 6));
+        assert!(state.is_map_open());
+        assert!(state.in_combat());
+        assert!(!state.has_game_focus());
+        assert!(!state.is_competitive_mode());
+    }
+
+    #[test]
+    fn test_enums() {
+        assert_eq!(MapType::from(9), MapType::Center);
+        assert!(MapType::from(9).is_wvw());
+        assert!(!MapType::from(5).is_wvw());
+        assert_eq!(MapType::from(100), MapType::Unknown(100));
+        assert_eq!(Mount::from(8), Mount::Skyscale);
+        assert_eq!(Mount::from(200), Mount::Unknown(200));
+        assert_eq!(
+            "RollerBeetle".parse::<Mount>().unwrap(),
+            Mount::RollerBeetle
+        );
+        assert_eq!("seigeturtle".parse::<Mount>().unwrap(), Mount::SiegeTurtle);
+        assert_eq!(Mount::SiegeTurtle.to_string(), "siegeturtle");
+        assert_eq!(Mount::None.to_string(), "none");
+        assert!("bunny".parse::<Mount>().is_err());
+    }
+
+    #[test]
+    fn test_game_state() {
+        let mut mem = LinkedMem {
+            ui_tick: 5,
+            camera_position: [1.0, 2.0, 3.0],
+            ..Default::default()
+        };
+        mem.set_context(&MumbleContext {
+            map_id: 50,
+            map_type: 5,
+            ui_state: UiState::GAME_FOCUS.bits(),
+            compass_width: 300,
+            compass_height: 200,
+            player_x: 10.0,
+            player_y: 20.0,
+            mount_index: 2,
+            ..Default::default()
+        });
+
+        let state = mem.get_game_state();
+        assert_eq!(state.ui_tick, 5);
+        assert_eq!(state.camera_position, [1.0, 2.0, 3.0]);
+        assert_eq!(state.map_id, 50);
+        assert_eq!(state.map_type, MapType::Public);
+        assert!(state.ui_state.has_game_focus());
+        assert_eq!(state.compass_size, [300, 200]);
+        assert_eq!(state.player_position, [10.0, 20.0]);
+        assert_eq!(state.mount, Mount::Griffon);
+        assert_eq!(GameState::from(&mem), state);
+    }
+}
@@ -0,0 +1,150 @@
+//! Runs inside the wine prefix of the game and forwards the MumbleLink shared memory to the
+//! overlay via udp.
+//!
+//! ```text
+//! mumble_bridge.exe [port] [mapping name]
+//! ```
+//!
+//! Build it with `cargo build -p mumble_bridge --release --target x86_64-pc-windows-gnu`.
+
+use std::net::{Ipv4Addr, SocketAddr};
+
+use gw2_link::{
+    protocol::{Packet, PacketFlags},
+    LinkedMem, DEFAULT_PORT,
+};
+
+#[cfg(windows)]
+mod mapping;
+
+const DEFAULT_MAPPING_NAME: &str = "MumbleLink";
+
+struct Args {
+    port: u16,
+    mapping_name: String,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Option<Args> {
+    let port = match args.next() {
+        Some(port) => port.parse().ok()?,
+        None => DEFAULT_PORT,
+    };
+    let mapping_name = args
+        .next()
+        .unwrap_or_else(|| DEFAULT_MAPPING_NAME.to_string());
+    Some(Args { port, mapping_name })
+}
+
+/// Decides which updates of the shared memory are sent and numbers them
+#[derive(Default)]
+struct Forwarder {
+    last_tick: Option<u32>,
+    sequence: u32,
+}
+
+// Only used by the windows build and the tests
+#[cfg_attr(not(windows), allow(dead_code))]
+impl Forwarder {
+    /// Returns the packet if the game wrote a new tick. The tick starts again at 1 when the game
+    /// restarts, so every change counts and not only an increase
+    fn forward(&mut self, mem: &LinkedMem) -> Option<Vec<u8>> {
+        let tick = mem.get_ui_tick();
+        if tick == 0 || self.last_tick == Some(tick) {
+            return None;
+        }
+        self.last_tick = Some(tick);
+        let packet = Packet {
+            sequence: Some(self.sequence),
+            mem: *mem,
+        };
+        self.sequence = self.sequence.wrapping_add(1);
+        Some(packet.encode(PacketFlags::empty()))
+    }
+}
+
+#[cfg(windows)]
+fn run(args: Args) -> std::io::Result<()> {
+    use std::{net::UdpSocket, thread, time::Duration};
+
+    let target = SocketAddr::from((Ipv4Addr::LOCALHOST, args.port));
+    println!("Sending {} to {}", args.mapping_name, target);
+    let mapping = mapping::FileMapping::open_or_create(&args.mapping_name)?;
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let mut forwarder = Forwarder::default();
+    loop {
+        if let Some(packet) = forwarder.forward(&mapping.read()) {
+            socket.send_to(&packet, target)?;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[cfg(not(windows))]
+fn run(args: Args) -> std::io::Result<()> {
+    let target = SocketAddr::from((Ipv4Addr::LOCALHOST, args.port));
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!(
+            "can't send {} to {}: the bridge has to run under wine",
+            args.mapping_name, target
+        ),
+    ))
+}
+
+fn main() -> std::process::ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Some(args) => args,
+        None => {
+            eprintln!("Usage: mumble_bridge.exe [port] [mapping name]");
+            return std::process::ExitCode::FAILURE;
+        }
+    };
+    match run(args) {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gw2_link::{protocol::Packet, LinkedMem};
+
+    use super::{parse_args, Forwarder};
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse_args(std::iter::empty()).unwrap();
+        assert_eq!(args.port, 7070);
+        assert_eq!(args.mapping_name, "MumbleLink");
+
+        let args = parse_args(["7071".to_string(), "MumbleLink2".to_string()].into_iter()).unwrap();
+        assert_eq!(args.port, 7071);
+        assert_eq!(args.mapping_name, "MumbleLink2");
+
+        assert!(parse_args(["port".to_string()].into_iter()).is_none());
+    }
+
+    #[test]
+    fn test_forwarder() {
+        let mut forwarder = Forwarder::default();
+        let mut mem = LinkedMem::default();
+        // The game didn't write anything yet
+        assert!(forwarder.forward(&mem).is_none());
+
+        mem.ui_tick = 5;
+        mem.camera_position = [1.0, 2.0, 3.0];
+        let packet = Packet::decode(&forwarder.forward(&mem).unwrap()).unwrap();
+        assert_eq!(packet.sequence, Some(0));
+        assert_eq!(packet.mem.get_ui_tick(), 5);
+        assert_eq!(packet.mem.get_camera_pos(), [1.0, 2.0, 3.0]);
+        assert!(forwarder.forward(&mem).is_none());
+
+        // Game restarted
+        mem.ui_tick = 1;
+        let packet = Packet::decode(&forwarder.forward(&mem).unwrap()).unwrap();
+        assert_eq!(packet.sequence, Some(1));
+    }
+}
@@ -0,0 +1,67 @@
+use std::{io, mem::size_of, ptr};
+
+use gw2_link::LinkedMem;
+use windows_sys::Win32::{
+    Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE},
+    System::Memory::{
+        CreateFileMappingW, MapViewOfFile, OpenFileMappingW, UnmapViewOfFile, FILE_MAP_ALL_ACCESS,
+        MEMORY_MAPPED_VIEW_ADDRESS, PAGE_READWRITE,
+    },
+};
+
+/// The named file mapping the game writes its `LinkedMem` to
+pub struct FileMapping {
+    handle: HANDLE,
+    view: MEMORY_MAPPED_VIEW_ADDRESS,
+}
+
+impl FileMapping {
+    /// Opens the mapping `name` or creates it if the game didn't start yet
+    pub fn open_or_create(name: &str) -> io::Result<Self> {
+        let wide_name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+        let mut created = false;
+        let mut handle = unsafe { OpenFileMappingW(FILE_MAP_ALL_ACCESS, 0, wide_name.as_ptr()) };
+        if handle == 0 {
+            println!("Couldn't open existing mapping...creating a new one");
+            handle = unsafe {
+                CreateFileMappingW(
+                    INVALID_HANDLE_VALUE,
+                    ptr::null(),
+                    PAGE_READWRITE,
+                    0,
+                    size_of::<LinkedMem>() as u32,
+                    wide_name.as_ptr(),
+                )
+            };
+            if handle == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            created = true;
+        }
+
+        let view = unsafe { MapViewOfFile(handle, FILE_MAP_ALL_ACCESS, 0, 0, 0) };
+        if view.Value.is_null() {
+            let error = io::Error::last_os_error();
+            unsafe { CloseHandle(handle) };
+            return Err(error);
+        }
+        if created {
+            unsafe { ptr::write_bytes(view.Value as *mut u8, 0, size_of::<LinkedMem>()) };
+        }
+        Ok(Self { handle, view })
+    }
+
+    /// Copies the current content. The game writes it concurrently
+    pub fn read(&self) -> LinkedMem {
+        unsafe { ptr::read_volatile(self.view.Value as *const LinkedMem) }
+    }
+}
+
+impl Drop for FileMapping {
+    fn drop(&mut self) {
+        unsafe {
+            UnmapViewOfFile(self.view);
+            CloseHandle(self.handle);
+        }
+    }
+}